@@ -0,0 +1,5 @@
+#!/bin/sh
+
+source ~/.bash_profile
+
+maelstrom test -w g-counter --bin ../../target/debug/g_counter --node-count 3 --rate 100 --time-limit 20 --nemesis partition
\ No newline at end of file
@@ -0,0 +1,5 @@
+#!/bin/sh
+
+source ~/.bash_profile
+
+maelstrom test -w kafka --bin ../../target/debug/kafka --node-count 2 --concurrency 2n --time-limit 20 --rate 1000
\ No newline at end of file
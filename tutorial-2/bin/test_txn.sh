@@ -0,0 +1,5 @@
+#!/bin/sh
+
+source ~/.bash_profile
+
+maelstrom test -w txn-rw-register --bin ../../target/debug/txn --node-count 2 --concurrency 2n --time-limit 20 --rate 1000 --consistency-models read-committed --availability total --nemesis partition
\ No newline at end of file
@@ -2,7 +2,7 @@ use anyhow::Context;
 use dist::{main_loop, Event, Node};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::{StdoutLock, Write};
+use std::io::Write;
 
 struct BroadcastNode {
     id: usize,
@@ -32,6 +32,7 @@ impl Node<(), Payload> for BroadcastNode {
         _state: (),
         init: dist::Init,
         inject: std::sync::mpsc::Sender<Event<Payload>>,
+        _rpc: dist::Rpc,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -46,7 +47,7 @@ impl Node<(), Payload> for BroadcastNode {
     fn step(
         &mut self,
         input: Event<Payload>,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
     ) -> anyhow::Result<()> {
         let Event::Message(input) = input else {
             panic!("got injected event when there's event injection");
@@ -3,10 +3,10 @@ use dist::{main_loop, Body, Event, Message, Node};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::io::StdoutLock;
+use std::io::Write;
 use std::time::Duration;
 
-struct BroadcastNode {
+pub struct BroadcastNode {
     id: usize,
     node: String,
 
@@ -42,7 +42,7 @@ pub enum Payload {
     },
 }
 
-enum InjectedPayload {
+pub enum InjectedPayload {
     Gossip,
 }
 
@@ -51,6 +51,7 @@ impl Node<(), Payload, InjectedPayload> for BroadcastNode {
         _state: (),
         init: dist::Init,
         tx: std::sync::mpsc::Sender<Event<Payload, InjectedPayload>>,
+        _rpc: dist::Rpc,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -89,7 +90,7 @@ impl Node<(), Payload, InjectedPayload> for BroadcastNode {
     fn step(
         &mut self,
         input: Event<Payload, InjectedPayload>,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
     ) -> anyhow::Result<()> {
         match input {
             Event::EOF => {}
@@ -1,7 +1,7 @@
 use anyhow::{bail, Context};
 use dist::{main_loop, Body, Event, Message, Node};
 use serde::{Deserialize, Serialize};
-use std::io::{StdoutLock, Write};
+use std::io::Write;
 
 pub struct EchoNode {
     pub id: usize,
@@ -19,6 +19,7 @@ impl Node<(), Payload> for EchoNode {
         _s: (),
         _init: dist::Init,
         _tx: std::sync::mpsc::Sender<Event<Payload>>,
+        _rpc: dist::Rpc,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -29,7 +30,7 @@ impl Node<(), Payload> for EchoNode {
     fn step(
         &mut self,
         input: Event<Payload>,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
     ) -> anyhow::Result<()> {
         let Event::Message(input) = input else {
             panic!("got injected event when there's no event injection");
@@ -0,0 +1,181 @@
+use anyhow::Context;
+use dist::{main_loop, Event, Kv, Node, Rpc, RpcError, SEQ_KV};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+/*
+Grow-only counter: clients add deltas on any node and read the total from any node.
+
+Every node keeps the sum of the deltas it was given under its own key in seq-kv,
+and the counter is the sum over the keys of all nodes. A key has a single writer,
+but the writer still uses compare-and-swap: when the reply to a swap is lost,
+reading the key tells whether the swap happened, and retrying the same swap can never count a delta twice.
+
+seq-kv is sequentially consistent, a read may return an old value of another node's key,
+so a read is only eventually consistent. Values never go down, so the node keeps the largest value
+it saw for every key and a read never goes back in time.
+
+Sequential consistency alone does not even promise that: a node that never writes may be kept
+on an old state forever, like a node that only serves reads, or every node once the adds stopped.
+So a read first writes a value never written before to the node's own sync key,
+the reads after that write are ordered after it, and see at least what the keys held by then.
+*/
+
+// how long the node waits for seq-kv before it asks again
+const KV_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct CounterNode {
+    id: usize,
+    node: String,
+    node_ids: Vec<String>,
+    kv: Kv,
+
+    // key: node id
+    // value: the largest value seen in that node's key, for this node the value it wrote last
+    counters: HashMap<String, usize>,
+    // number of sync writes so far, the next one writes this value
+    syncs: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    Add { delta: usize },
+    AddOk,
+    Read,
+    ReadOk { value: usize },
+}
+
+fn counter_key(node: &str) -> String {
+    format!("counter-{}", node)
+}
+
+fn sync_key(node: &str) -> String {
+    format!("sync-{}", node)
+}
+
+fn is_timeout(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<RpcError>(), Some(RpcError::Timeout))
+}
+
+impl CounterNode {
+    // the value of a node's key, 0 while the key does not exist, None when seq-kv did not answer
+    fn read_counter(
+        &self,
+        node: &str,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Option<usize>> {
+        match self.kv.read(&counter_key(node), output) {
+            Ok(value) => Ok(Some(value.unwrap_or(0))),
+            Err(err) if is_timeout(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn add(
+        &mut self,
+        delta: usize,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let key = counter_key(&self.node);
+        let mut from = self.counters.get(&self.node).copied().unwrap_or(0);
+        loop {
+            let to = from + delta;
+            match self.kv.cas(&key, from, to, true, output) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) if is_timeout(&err) => {}
+                Err(err) => return Err(err),
+            }
+
+            // the swap failed, or its reply got lost: either the key already holds `to`
+            // because the swap did happen, or it holds something else to start again from
+            let Some(current) = self.read_counter(&self.node, output)? else {
+                continue;
+            };
+            if current == to {
+                break;
+            }
+            from = current;
+        }
+        self.counters.insert(self.node.clone(), from + delta);
+        Ok(())
+    }
+
+    fn read(&mut self, output: &mut impl Write) -> anyhow::Result<usize> {
+        // without the write the reads below may return a state of any age,
+        // when it times out they still return one no older than the last read did
+        match self.kv.write(&sync_key(&self.node), self.syncs, output) {
+            Ok(()) => {}
+            Err(err) if is_timeout(&err) => {}
+            Err(err) => return Err(err),
+        }
+        self.syncs += 1;
+
+        for node in self.node_ids.clone() {
+            if node == self.node {
+                continue;
+            }
+            // a node whose key did not answer counts with the value seen before
+            if let Some(value) = self.read_counter(&node, output)? {
+                let seen = self.counters.entry(node).or_default();
+                *seen = (*seen).max(value);
+            }
+        }
+        Ok(self.counters.values().sum())
+    }
+}
+
+impl Node<(), Payload> for CounterNode {
+    fn from_init(
+        _state: (),
+        init: dist::Init,
+        _inject: std::sync::mpsc::Sender<Event<Payload>>,
+        rpc: Rpc,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            id: 1,
+            node: init.node_id,
+            node_ids: init.node_ids,
+            kv: Kv::new(SEQ_KV, rpc, KV_TIMEOUT),
+            counters: HashMap::new(),
+            syncs: 0,
+        })
+    }
+
+    fn step(
+        &mut self,
+        input: Event<Payload>,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let Event::Message(input) = input else {
+            return Ok(());
+        };
+
+        let mut reply = input.into_reply(Some(&mut self.id));
+        match reply.body.payload {
+            Payload::Add { delta } => {
+                self.add(delta, output).context("add to the counter")?;
+                reply.body.payload = Payload::AddOk;
+                reply.send(&mut *output).context("reply to add")?;
+            }
+            Payload::Read => {
+                let value = self.read(output).context("read the counter")?;
+                reply.body.payload = Payload::ReadOk { value };
+                reply.send(&mut *output).context("reply to read")?;
+            }
+            Payload::AddOk | Payload::ReadOk { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<_, CounterNode, _, _>(())
+}
@@ -0,0 +1,299 @@
+use anyhow::Context;
+use dist::{main_loop, Event, Kv, Node, Rpc, RpcError, LIN_KV};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+/*
+Kafka-style log: clients append messages to named logs (keys) on any node, poll them from an offset,
+and commit the offsets they processed.
+
+The nodes share no memory, they coordinate through lin-kv, which is linearizable:
+- the message at offset o of log k is stored under "entry-k-o"
+- appending is claiming the first free offset: a compare-and-swap from null with create_if_not_exists
+  only succeeds while the entry does not exist, so exactly one send gets each offset,
+  and offsets are handed out without gaps, o is taken before anybody tries o + 1
+- the committed offset of log k is stored under "committed-k", it only ever grows
+
+When the reply to a claim is lost, the claim may or may not have happened. Every entry carries the id
+of the send that wrote it, so reading the entry tells whether it is ours, and a message is never appended twice.
+Entries never change once written, so the node keeps every entry it saw and reads it from lin-kv only once.
+*/
+
+// how long the node waits for lin-kv before it asks again
+const KV_TIMEOUT: Duration = Duration::from_millis(500);
+
+// upper limit of messages returned per log by a single poll
+const MAX_POLL_MESSAGES: usize = 32;
+
+struct LogNode {
+    id: usize,
+    node: String,
+    kv: Kv,
+
+    // sends handled by this node, numbers the entry ids
+    sends: usize,
+
+    // key: log
+    // value: the offset after the last entry this node knows, where the next claim starts
+    next_offsets: HashMap<String, usize>,
+
+    // entries read from or written to lin-kv, by log and offset
+    entries: HashMap<(String, usize), Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    // node and number of the send that appended the entry
+    id: String,
+    msg: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    Send {
+        key: String,
+        msg: usize,
+    },
+    SendOk {
+        offset: usize,
+    },
+    Poll {
+        offsets: HashMap<String, usize>,
+    },
+    PollOk {
+        // every message as a pair of offset and message
+        msgs: HashMap<String, Vec<(usize, usize)>>,
+    },
+    CommitOffsets {
+        offsets: HashMap<String, usize>,
+    },
+    CommitOffsetsOk,
+    ListCommittedOffsets {
+        keys: Vec<String>,
+    },
+    ListCommittedOffsetsOk {
+        offsets: HashMap<String, usize>,
+    },
+}
+
+fn entry_key(log: &str, offset: usize) -> String {
+    format!("entry-{}-{}", log, offset)
+}
+
+fn committed_key(log: &str) -> String {
+    format!("committed-{}", log)
+}
+
+fn is_timeout(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<RpcError>(), Some(RpcError::Timeout))
+}
+
+impl LogNode {
+    // reads the key until lin-kv answers
+    fn read<T: serde::de::DeserializeOwned>(
+        &self,
+        key: &str,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Option<T>> {
+        loop {
+            match self.kv.read(key, output) {
+                Err(err) if is_timeout(&err) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    // the entry at the offset, None when nobody claimed the offset yet
+    fn entry(
+        &mut self,
+        log: &str,
+        offset: usize,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Option<Entry>> {
+        if let Some(entry) = self.entries.get(&(log.to_string(), offset)) {
+            return Ok(Some(entry.clone()));
+        }
+        let entry: Option<Entry> =
+            self.read(&entry_key(log, offset), output)?;
+        if let Some(entry) = &entry {
+            self.remember(log, offset, entry.clone());
+        }
+        Ok(entry)
+    }
+
+    fn remember(&mut self, log: &str, offset: usize, entry: Entry) {
+        let next = self.next_offsets.entry(log.to_string()).or_default();
+        *next = (*next).max(offset + 1);
+        self.entries.insert((log.to_string(), offset), entry);
+    }
+
+    // appends the message to the log and returns its offset
+    fn send(
+        &mut self,
+        log: &str,
+        msg: usize,
+        output: &mut impl Write,
+    ) -> anyhow::Result<usize> {
+        let entry = Entry {
+            id: format!("{}-{}", self.node, self.sends),
+            msg,
+        };
+        self.sends += 1;
+        let value = serde_json::to_value(&entry)?;
+
+        let mut offset = self.next_offsets.get(log).copied().unwrap_or(0);
+        loop {
+            let key = entry_key(log, offset);
+            match self.kv.cas(&key, Value::Null, value.clone(), true, output) {
+                Ok(true) => break,
+                // taken, but maybe by this very send when the reply to an earlier claim got lost
+                Ok(false) => {
+                    if self.entry(log, offset, output)?.as_ref() == Some(&entry)
+                    {
+                        break;
+                    }
+                    offset += 1;
+                }
+                // the claim may have happened, trying it again tells
+                Err(err) if is_timeout(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.remember(log, offset, entry);
+        Ok(offset)
+    }
+
+    // the messages of the log from the offset on, up to the first offset nobody claimed yet
+    fn poll(
+        &mut self,
+        log: &str,
+        from: usize,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Vec<(usize, usize)>> {
+        let mut msgs = vec![];
+        for offset in from..from + MAX_POLL_MESSAGES {
+            match self.entry(log, offset, output)? {
+                Some(entry) => msgs.push((offset, entry.msg)),
+                None => break,
+            }
+        }
+        Ok(msgs)
+    }
+
+    // raises the committed offset of the log, a lower offset than the committed one changes nothing
+    fn commit(
+        &mut self,
+        log: &str,
+        offset: usize,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let key = committed_key(log);
+        loop {
+            let committed: Option<usize> = self.read(&key, output)?;
+            if committed.is_some_and(|committed| committed >= offset) {
+                return Ok(());
+            }
+            let from = serde_json::to_value(committed)?;
+            match self.kv.cas(&key, from, Value::from(offset), true, output) {
+                Ok(true) => return Ok(()),
+                // another commit got there first, or the reply got lost, read again
+                Ok(false) => {}
+                Err(err) if is_timeout(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Node<(), Payload> for LogNode {
+    fn from_init(
+        _state: (),
+        init: dist::Init,
+        _inject: std::sync::mpsc::Sender<Event<Payload>>,
+        rpc: Rpc,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            id: 1,
+            node: init.node_id,
+            kv: Kv::new(LIN_KV, rpc, KV_TIMEOUT),
+            sends: 0,
+            next_offsets: HashMap::new(),
+            entries: HashMap::new(),
+        })
+    }
+
+    fn step(
+        &mut self,
+        input: Event<Payload>,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let Event::Message(input) = input else {
+            return Ok(());
+        };
+
+        let mut reply = input.into_reply(Some(&mut self.id));
+        match reply.body.payload {
+            Payload::Send { key, msg } => {
+                let offset = self
+                    .send(&key, msg, output)
+                    .with_context(|| format!("append to log {}", key))?;
+                reply.body.payload = Payload::SendOk { offset };
+                reply.send(&mut *output).context("reply to send")?;
+            }
+            Payload::Poll { offsets } => {
+                let mut msgs = HashMap::new();
+                for (key, from) in offsets {
+                    let polled = self
+                        .poll(&key, from, output)
+                        .with_context(|| format!("poll log {}", key))?;
+                    msgs.insert(key, polled);
+                }
+                reply.body.payload = Payload::PollOk { msgs };
+                reply.send(&mut *output).context("reply to poll")?;
+            }
+            Payload::CommitOffsets { offsets } => {
+                for (key, offset) in offsets {
+                    self.commit(&key, offset, output)
+                        .with_context(|| format!("commit log {}", key))?;
+                }
+                reply.body.payload = Payload::CommitOffsetsOk;
+                reply
+                    .send(&mut *output)
+                    .context("reply to commit_offsets")?;
+            }
+            Payload::ListCommittedOffsets { keys } => {
+                let mut offsets = HashMap::new();
+                for key in keys {
+                    // a log without commits is left out
+                    if let Some(offset) =
+                        self.read(&committed_key(&key), output)?
+                    {
+                        offsets.insert(key, offset);
+                    }
+                }
+                reply.body.payload =
+                    Payload::ListCommittedOffsetsOk { offsets };
+                reply
+                    .send(&mut *output)
+                    .context("reply to list_committed_offsets")?;
+            }
+            Payload::SendOk { .. }
+            | Payload::PollOk { .. }
+            | Payload::CommitOffsetsOk
+            | Payload::ListCommittedOffsetsOk { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<_, LogNode, _, _>(())
+}
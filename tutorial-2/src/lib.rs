@@ -1,21 +1,33 @@
 use anyhow::Context;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::io::{BufRead, StdoutLock, Write};
-use std::sync::mpsc::{Receiver, Sender};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub mod sim;
 
 pub trait Node<S, Payload, InjectedPayload = ()> {
+    // rpc is the node's client for calling other nodes and Maelstrom services, see Rpc
     fn from_init(
         s: S,
         init: Init,
         inject: std::sync::mpsc::Sender<Event<Payload, InjectedPayload>>,
+        rpc: Rpc,
     ) -> anyhow::Result<Self>
     where
         Self: Sized;
 
+    // output is wherever the main loop writes to, STDOUT under main_loop, see main_loop_with
     fn step(
         &mut self,
         input: Event<Payload, InjectedPayload>,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
     ) -> anyhow::Result<()>;
 }
 
@@ -85,32 +97,465 @@ impl<Payload> Message<Payload> {
     }
 }
 
+impl Message<Value> {
+    // a message read without knowing its payload type yet, turned into the typed message
+    pub fn decode_payload<Payload: DeserializeOwned>(
+        self,
+    ) -> serde_json::Result<Message<Payload>> {
+        Ok(Message {
+            src: self.src,
+            dst: self.dst,
+            body: Body {
+                id: self.body.id,
+                in_reply_to: self.body.in_reply_to,
+                payload: serde_json::from_value(self.body.payload)?,
+            },
+        })
+    }
+}
+
+/*
+Error codes Maelstrom defines for the body of an `error` reply,
+codes below 1000 are reserved for Maelstrom, a node may use the others for its own errors.
+*/
+pub mod error_code {
+    pub const TIMEOUT: usize = 0;
+    pub const NODE_NOT_FOUND: usize = 1;
+    pub const NOT_SUPPORTED: usize = 10;
+    pub const TEMPORARILY_UNAVAILABLE: usize = 11;
+    pub const MALFORMED_REQUEST: usize = 12;
+    pub const CRASH: usize = 13;
+    pub const ABORT: usize = 14;
+    pub const KEY_DOES_NOT_EXIST: usize = 20;
+    pub const KEY_ALREADY_EXISTS: usize = 21;
+    pub const PRECONDITION_FAILED: usize = 22;
+    pub const TXN_CONFLICT: usize = 30;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    // no reply arrived in time, the request may or may not have been handled
+    Timeout,
+    // the other side answered with an `error` body, see error_code
+    Remote { code: usize, text: Option<String> },
+    // the reply is neither an error nor the expected payload
+    Malformed(String),
+    // the main loop stopped routing replies
+    Closed,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RpcError::Timeout => write!(f, "rpc timed out"),
+            RpcError::Remote { code, text } => {
+                write!(f, "rpc failed with error code {}", code)?;
+                if let Some(text) = text {
+                    write!(f, ": {}", text)?;
+                }
+                Ok(())
+            }
+            RpcError::Malformed(err) => {
+                write!(f, "malformed rpc reply: {}", err)
+            }
+            RpcError::Closed => write!(f, "rpc reply channel closed"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/*
+Rpc sends requests to other nodes (or to Maelstrom services like seq-kv) and correlates the replies.
+
+Every request gets a fresh msg_id and an entry in the pending table. main_loop reads every message
+from STDIN as JSON first and offers it to Rpc::route: a message whose in_reply_to names a pending request
+goes to that request's RpcHandle, everything else continues to the node's step function as before.
+Because the routing runs on the STDIN thread, step may block on RpcHandle::wait without dead-locking.
+The entry lives as long as the handle, a handle that timed out or was dropped takes it along,
+so the replies Maelstrom loses do not pile up in the table.
+
+Nodes that send requests of their own (without Rpc) should take their msg_ids from Rpc::next_msg_id,
+otherwise a reply to such a request could be mistaken for the reply to an rpc with the same id.
+Rpc is a cheap handle, clones share the same ids and pending table.
+*/
+#[derive(Clone)]
+pub struct Rpc {
+    node_id: String,
+    next_id: Arc<AtomicUsize>,
+    pending: Arc<Mutex<HashMap<usize, Sender<Message<Value>>>>>,
+}
+
+impl Rpc {
+    // ids start at 1, main_loop already used 0 for init_ok
+    pub fn new(node_id: String) -> Self {
+        Rpc {
+            node_id,
+            next_id: Arc::new(AtomicUsize::new(1)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn next_msg_id(&self) -> usize {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /*
+    Function writes the request to output and returns the handle to wait on for its reply.
+    Resp is the payload of the expected reply, an `error` reply is turned into RpcError::Remote.
+    */
+    pub fn call<Req, Resp>(
+        &self,
+        dst: &str,
+        payload: Req,
+        output: &mut impl Write,
+    ) -> anyhow::Result<RpcHandle<Resp>>
+    where
+        Req: Serialize,
+    {
+        let id = self.next_msg_id();
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let request = Message {
+            src: self.node_id.clone(),
+            dst: dst.to_string(),
+            body: Body {
+                id: Some(id),
+                in_reply_to: None,
+                payload,
+            },
+        };
+        if let Err(err) = request.send(output) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(err.context(format!("send rpc {} to {}", id, dst)));
+        }
+
+        Ok(RpcHandle {
+            id,
+            replies: rx,
+            pending: self.pending.clone(),
+            resp: PhantomData,
+        })
+    }
+
+    /*
+    Function hands a reply to the request it answers, and gives back every other message.
+    A reply arriving after its handle is gone matches no request and is given back as well,
+    main_loop drops it unless the node's payload knows it (see forward_input).
+    */
+    pub fn route(&self, message: Message<Value>) -> Option<Message<Value>> {
+        let Some(in_reply_to) = message.body.in_reply_to else {
+            return Some(message);
+        };
+        let Some(tx) = self.pending.lock().unwrap().remove(&in_reply_to) else {
+            return Some(message);
+        };
+        // the handle may be gone already, nobody is waiting for the reply then
+        let _ = tx.send(message);
+        None
+    }
+
+    // number of requests still waiting for their reply
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+pub struct RpcHandle<Resp> {
+    id: usize,
+    replies: Receiver<Message<Value>>,
+    pending: Arc<Mutex<HashMap<usize, Sender<Message<Value>>>>>,
+    resp: PhantomData<fn() -> Resp>,
+}
+
+// nobody waits for the reply anymore, wait consumes the handle, so this runs after a timeout too
+impl<Resp> Drop for RpcHandle<Resp> {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&self.id);
+        }
+    }
+}
+
+impl<Resp: DeserializeOwned> RpcHandle<Resp> {
+    // msg_id of the request
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    // blocks until the reply arrives, at most for `timeout`
+    pub fn wait(self, timeout: Duration) -> Result<Resp, RpcError> {
+        match self.replies.recv_timeout(timeout) {
+            Ok(reply) => decode_reply(reply),
+            Err(RecvTimeoutError::Timeout) => Err(RpcError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(RpcError::Closed),
+        }
+    }
+
+    // the reply if it already arrived, None while it is still on its way
+    pub fn try_wait(&self) -> Option<Result<Resp, RpcError>> {
+        match self.replies.try_recv() {
+            Ok(reply) => Some(decode_reply(reply)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(RpcError::Closed)),
+        }
+    }
+}
+
+fn decode_reply<Resp: DeserializeOwned>(
+    reply: Message<Value>,
+) -> Result<Resp, RpcError> {
+    let payload = reply.body.payload;
+    if payload.get("type").and_then(Value::as_str) == Some("error") {
+        let code = payload.get("code").and_then(Value::as_u64);
+        let text = payload.get("text").and_then(Value::as_str);
+        return Err(RpcError::Remote {
+            code: code.map_or(error_code::CRASH, |code| code as usize),
+            text: text.map(str::to_string),
+        });
+    }
+    serde_json::from_value(payload)
+        .map_err(|err| RpcError::Malformed(err.to_string()))
+}
+
+/*
+Maelstrom runs key-value services next to the nodes, reachable like any other node:
+- seq-kv: sequentially consistent
+- lin-kv: linearizable
+- lww-kv: last write wins
+They all speak the same protocol, KvPayload, and answer a missing key with KEY_DOES_NOT_EXIST
+and a failed compare-and-swap with PRECONDITION_FAILED.
+*/
+pub const SEQ_KV: &str = "seq-kv";
+pub const LIN_KV: &str = "lin-kv";
+pub const LWW_KV: &str = "lww-kv";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KvPayload {
+    Read {
+        key: String,
+    },
+    ReadOk {
+        value: Value,
+    },
+    Write {
+        key: String,
+        value: Value,
+    },
+    WriteOk,
+    Cas {
+        key: String,
+        from: Value,
+        to: Value,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        create_if_not_exists: bool,
+    },
+    CasOk,
+}
+
+/*
+Kv is a client of one of the key-value services, every call blocks until the service answered,
+at most for the timeout, and fails with the RpcError inside the anyhow::Error otherwise.
+*/
+#[derive(Clone)]
+pub struct Kv {
+    service: String,
+    rpc: Rpc,
+    timeout: Duration,
+}
+
+impl Kv {
+    pub fn new(service: &str, rpc: Rpc, timeout: Duration) -> Self {
+        Kv {
+            service: service.to_string(),
+            rpc,
+            timeout,
+        }
+    }
+
+    fn request(
+        &self,
+        payload: KvPayload,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Result<KvPayload, RpcError>> {
+        let handle = self.rpc.call(&self.service, payload, output)?;
+        Ok(handle.wait(self.timeout))
+    }
+
+    // the value stored under the key, None when the key does not exist
+    pub fn read<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        output: &mut impl Write,
+    ) -> anyhow::Result<Option<T>> {
+        let read = KvPayload::Read {
+            key: key.to_string(),
+        };
+        match self.request(read, output)? {
+            Ok(KvPayload::ReadOk { value }) => Ok(Some(
+                serde_json::from_value(value)
+                    .with_context(|| format!("value of key {}", key))?,
+            )),
+            Err(RpcError::Remote { code, .. })
+                if code == error_code::KEY_DOES_NOT_EXIST =>
+            {
+                Ok(None)
+            }
+            Ok(other) => anyhow::bail!("unexpected reply to read: {:?}", other),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn write<T: Serialize>(
+        &self,
+        key: &str,
+        value: T,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let write = KvPayload::Write {
+            key: key.to_string(),
+            value: serde_json::to_value(value)?,
+        };
+        match self.request(write, output)? {
+            Ok(KvPayload::WriteOk) => Ok(()),
+            Ok(other) => {
+                anyhow::bail!("unexpected reply to write: {:?}", other)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /*
+    Function replaces the value of the key with `to` if it still is `from`, and tells whether it did.
+    With create_if_not_exists a missing key is created with `to`, otherwise it is an error.
+    */
+    pub fn cas<T: Serialize>(
+        &self,
+        key: &str,
+        from: T,
+        to: T,
+        create_if_not_exists: bool,
+        output: &mut impl Write,
+    ) -> anyhow::Result<bool> {
+        let cas = KvPayload::Cas {
+            key: key.to_string(),
+            from: serde_json::to_value(from)?,
+            to: serde_json::to_value(to)?,
+            create_if_not_exists,
+        };
+        match self.request(cas, output)? {
+            Ok(KvPayload::CasOk) => Ok(true),
+            Err(RpcError::Remote { code, .. })
+                if code == error_code::PRECONDITION_FAILED =>
+            {
+                Ok(false)
+            }
+            Ok(other) => anyhow::bail!("unexpected reply to cas: {:?}", other),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// hands every input line to rpc or to the node, until the input ends or the node stopped listening
+fn forward_input<P, IP>(
+    input: impl Iterator<Item = std::io::Result<String>>,
+    rpc: &Rpc,
+    tx: &Sender<Event<P, IP>>,
+) -> anyhow::Result<()>
+where
+    P: DeserializeOwned,
+{
+    for line in input {
+        let line = line.context("Maelstrom input could not be read")?;
+        let input: Message<Value> = serde_json::from_str(&line)
+            .context("Maelstrom input could not be deserialized")?;
+        // replies to requests sent through rpc go to their handles, not to the node
+        let Some(input) = rpc.route(input) else {
+            continue;
+        };
+        let is_reply = input.body.in_reply_to.is_some();
+        let input: Message<P> = match input.decode_payload() {
+            Ok(input) => input,
+            // most likely the late reply to an rpc that stopped waiting for it
+            Err(_) if is_reply => continue,
+            Err(err) => {
+                return Err(err).context(
+                    "Maelstrom input does not match the node's payload",
+                )
+            }
+        };
+
+        if tx.send(Event::Message(input)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
 // here define main_loop
+// runs the node over STDIN and STDOUT, the way Maelstrom talks to it
 pub fn main_loop<S, N, P, IP>(init_state: S) -> anyhow::Result<()>
 where
     P: DeserializeOwned + Send + 'static,
     N: Node<S, P, IP>,
     IP: Send + 'static,
+{
+    // a locked STDIN cannot move to the input thread, a buffered reader of the handle can
+    let stdin = BufReader::new(std::io::stdin());
+    main_loop_with::<S, N, P, IP, _, _>(
+        init_state,
+        stdin,
+        std::io::stdout().lock(),
+    )
+}
+
+/*
+Runs the node over any source of lines and any sink, STDIN and STDOUT, channels, files or TCP streams:
+one message per line in, one message per line out.
+
+The first line has to be the init message. Every other line is read on a thread of its own,
+replies to calls made through rpc are handed to their handles there, and all else becomes an event for step.
+The end of the input is the EOF event, the last one the node steps through before the loop returns.
+An input line that cannot be read or decoded ends the loop too, with its error,
+except a reply the node's payload does not know, which is dropped.
+*/
+pub fn main_loop_with<S, N, P, IP, R, W>(
+    init_state: S,
+    input: R,
+    mut output: W,
+) -> anyhow::Result<()>
+where
+    P: DeserializeOwned + Send + 'static,
+    N: Node<S, P, IP>,
+    IP: Send + 'static,
+    R: BufRead + Send + 'static,
+    W: Write,
 {
     let (tx, rx) = std::sync::mpsc::channel();
-    let stdin = std::io::stdin().lock();
-    let mut stdout = std::io::stdout().lock();
-    let mut stdin = stdin.lines();
+    let mut input = input.lines();
 
     let init_msg: Message<InitPayload> = serde_json::from_str(
-        &stdin
+        &input
             .next()
-            .expect("no init message received")
-            .context("failed to read init message from stdin")?,
+            .context("no init message received")?
+            .context("failed to read init message from input")?,
     )
     .context("init message could not be deserialized")?;
 
     let InitPayload::Init(init) = init_msg.body.payload else {
-        panic!("first message should be init");
+        anyhow::bail!("first message should be init");
     };
 
-    let mut node: N = Node::from_init(init_state, init, tx.clone())
-        .context("node initialization failed")?;
+    let rpc = Rpc::new(init.node_id.clone());
+    let mut node: N =
+        Node::from_init(init_state, init, tx.clone(), rpc.clone())
+            .context("node initialization failed")?;
 
     let reply = Message {
         src: init_msg.dst,
@@ -121,39 +566,29 @@ where
             payload: InitPayload::InitOk,
         },
     };
-
-    serde_json::to_writer(&mut stdout, &reply)
-        .context("serialize response to init")?;
-    stdout.write_all(b"\n");
+    reply.send(&mut output).context("reply to init")?;
+    output.flush().context("flush reply to init")?;
 
     // --- thread && channel ---
-    drop(stdin);
-
     let jh = std::thread::spawn(move || {
-        let stdin = std::io::stdin().lock();
-        for line in stdin.lines() {
-            let line =
-                line.context("Maelstrom input form STDIN could not be read")?;
-            let input: Message<P> = serde_json::from_str(&line).context(
-                "Maelstrom input from STDIN could not be deserialized",
-            )?;
-
-            if let Err(_) = tx.send(Event::Message(input)) {
-                return Ok::<_, anyhow::Error>(());
-            }
-        }
+        let result = forward_input(input, &rpc, &tx);
         let _ = tx.send(Event::EOF);
-        Ok(())
+        result
     });
 
     // loop input items in receiver channel
     for input in rx {
-        node.step(input, &mut stdout)
+        let eof = matches!(input, Event::EOF);
+        node.step(input, &mut output)
             .context("Node step function failed")?;
+        output.flush().context("flush node output")?;
+        if eof {
+            break;
+        }
     }
     jh.join()
-        .expect("stdin thread panicked")
-        .context("stdin thread err")?;
+        .expect("input thread panicked")
+        .context("input thread err")?;
 
     Ok(())
 }
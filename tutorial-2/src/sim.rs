@@ -0,0 +1,810 @@
+use crate::{
+    error_code, main_loop_with, Body, Init, InitPayload, KvPayload, Message,
+    Node, LIN_KV, SEQ_KV,
+};
+use anyhow::Context;
+use rand::prelude::*;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/*
+In-process stand-in for Maelstrom: runs a cluster of nodes inside one process, so nodes can be tested
+with cargo test on a machine without Java.
+
+Every node runs main_loop_with on its own thread, over an Inbox and an Outbox instead of STDIN and STDOUT.
+Whatever a node writes to its output is parsed back into messages and handed to the network,
+a router thread holding every message in flight until its latency has passed, and then:
+- a message for a node becomes a line of the node's input, starting after the init message
+- a message for a client goes to the client's channel, see Client
+- a message for lin-kv or seq-kv is answered by an in-memory key-value store, see KvStore
+
+Messages between two nodes get lost at the configured drop rate, or always while the nodes are partitioned.
+Clients and services are never cut off, as in Maelstrom.
+
+The workload functions at the bottom drive a cluster through clients and check what the nodes answered.
+*/
+
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    // every message is delayed by a random duration in this range
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+
+    // chance of a message between two nodes getting lost, from 0 to 1
+    pub drop_rate: f64,
+
+    // how far behind the newest value a seq-kv read may be, see KvStore
+    pub seq_kv_staleness: Duration,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::ZERO,
+            max_latency: Duration::from_millis(5),
+            drop_rate: 0.0,
+            seq_kv_staleness: Duration::from_millis(20),
+        }
+    }
+}
+
+// a message on its way, the earliest delivery comes first out of the heap
+struct InFlight {
+    at: Instant,
+    seq: usize,
+    message: Message<Value>,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+enum Destination {
+    // the lines of the node's Inbox
+    Node(Sender<String>),
+    Client(Sender<Message<Value>>),
+}
+
+// state shared between the simulation, the router and the node threads
+struct Shared {
+    config: NetworkConfig,
+    node_ids: Vec<String>,
+
+    // key: node id
+    // value: the group of the partition the node is in, empty when the network is whole
+    partition: Mutex<HashMap<String, usize>>,
+
+    destinations: Mutex<HashMap<String, Destination>>,
+
+    // failures of the nodes, the errors their main loops ended with
+    errors: Mutex<Vec<String>>,
+
+    network_stopped: AtomicBool,
+}
+
+impl Shared {
+    fn is_node(&self, id: &str) -> bool {
+        self.node_ids.iter().any(|n| n == id)
+    }
+
+    // whether a message sent now between the two ends gets lost
+    fn loses(&self, src: &str, dst: &str) -> bool {
+        if !self.is_node(src) || !self.is_node(dst) {
+            return false;
+        }
+        let partition = self.partition.lock().unwrap();
+        if partition.get(src) != partition.get(dst) {
+            return true;
+        }
+        thread_rng().gen_bool(self.config.drop_rate)
+    }
+
+    fn latency(&self) -> Duration {
+        let NetworkConfig {
+            min_latency,
+            max_latency,
+            ..
+        } = self.config;
+        if max_latency <= min_latency {
+            return min_latency;
+        }
+        thread_rng().gen_range(min_latency..=max_latency)
+    }
+
+    fn fail(&self, node: &str, err: anyhow::Error) {
+        self.errors
+            .lock()
+            .unwrap()
+            .push(format!("{}: {:#}", node, err));
+    }
+}
+
+/*
+The input of a node: the lines the network delivers, ending when the simulation stops.
+*/
+struct Inbox {
+    lines: Receiver<String>,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for Inbox {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for Inbox {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        // once the channel is closed, the empty rest of the last line is the end of the input
+        if self.pos == self.line.len() {
+            if let Ok(line) = self.lines.recv() {
+                self.line = line.into_bytes();
+                self.line.push(b'\n');
+                self.pos = 0;
+            }
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/*
+The output of a node: collects what the node writes, and sends every full line to the network as a message.
+*/
+struct Outbox {
+    buffer: Vec<u8>,
+    network: Sender<Message<Value>>,
+}
+
+impl Write for Outbox {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let message =
+                serde_json::from_slice(&line).map_err(std::io::Error::other)?;
+            // the router only goes away when the simulation stops
+            let _ = self.network.send(message);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/*
+In-memory lin-kv or seq-kv, also the fake kv service of the tests running node binaries.
+
+Every key keeps the values it had lately, each one numbered and stamped with the time it was written.
+Writes and compare-and-swaps always work on the newest value. A read returns the newest value
+when staleness is zero, which is linearizable, that is lin-kv.
+
+With a staleness, a read may instead return any value the key still had staleness ago,
+the absent value of a key created since included, but never one older than what the same client
+already saw of the key, through a read, a write or a compare-and-swap. Every client sees every key
+move forward in the order of the writes, and catches up once the staleness has passed,
+which is how seq-kv behaves for a single key.
+
+A write or a compare-and-swap also puts the client in line with everyone else's writes:
+after it, a read of any key returns what the key held at that moment or something newer.
+A client that only reads has nothing to order it, with a long staleness it may keep reading
+old values for as long as the staleness lasts.
+*/
+#[derive(Default)]
+pub struct KvStore {
+    staleness: Duration,
+
+    // key: kv key
+    // value: the number, write time and value of every version of the key that is still readable,
+    // oldest first, None while the key did not exist
+    versions: HashMap<String, Vec<(usize, Instant, Option<Value>)>>,
+
+    // key: client and kv key
+    // value: number of the newest version of the key the client saw
+    seen: HashMap<(String, String), usize>,
+
+    // key: client
+    // value: when the client last wrote or swapped, its reads skip the versions replaced before
+    synced: HashMap<String, Instant>,
+}
+
+impl KvStore {
+    pub fn new(staleness: Duration) -> Self {
+        Self {
+            staleness,
+            ..Self::default()
+        }
+    }
+
+    fn error(code: usize, text: String) -> Value {
+        json!({"type": "error", "code": code, "text": text})
+    }
+
+    // the newest value of the key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.versions.get(key)?.last()?.2.as_ref()
+    }
+
+    // sets the key without a client, as if written by a node that went away
+    pub fn insert(&mut self, key: &str, value: Value) {
+        self.set(key, value);
+    }
+
+    // stores a new version of the key, returns its number
+    fn set(&mut self, key: &str, value: Value) -> usize {
+        let now = Instant::now();
+        let versions = self
+            .versions
+            .entry(key.to_string())
+            .or_insert_with(|| vec![(0, now, None)]);
+        let number = versions.last().map_or(0, |(n, _, _)| n + 1);
+        versions.push((number, now, Some(value)));
+
+        // a version replaced longer than staleness ago can no longer be read
+        while versions.len() > 1
+            && now.duration_since(versions[1].1) > self.staleness
+        {
+            versions.remove(0);
+        }
+        number
+    }
+
+    fn observe(&mut self, client: &str, key: &str, number: usize) {
+        let seen = self
+            .seen
+            .entry((client.to_string(), key.to_string()))
+            .or_default();
+        *seen = (*seen).max(number);
+    }
+
+    // the value a read of the client gets, see the comment on KvStore
+    fn read(&mut self, client: &str, key: &str) -> Option<Value> {
+        let seen = self
+            .seen
+            .get(&(client.to_string(), key.to_string()))
+            .copied()
+            .unwrap_or(0);
+        let synced = self.synced.get(client).copied();
+        let now = Instant::now();
+        let versions = self.versions.get(key)?;
+        let readable: Vec<_> = versions
+            .iter()
+            .enumerate()
+            .filter(|(i, (number, _, _))| {
+                // readable while it was still the newest staleness ago,
+                // and when the client last wrote
+                *number >= seen
+                    && versions.get(i + 1).is_none_or(|(_, replaced, _)| {
+                        now.duration_since(*replaced) <= self.staleness
+                            && synced.is_none_or(|synced| *replaced >= synced)
+                    })
+            })
+            .map(|(_, version)| version.clone())
+            .collect();
+        let (number, _, value) = readable.choose(&mut thread_rng())?.clone();
+        self.observe(client, key, number);
+        value
+    }
+
+    // the reply payload to a request payload of the client
+    pub fn handle(&mut self, client: &str, request: Value) -> Value {
+        let request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(err) => {
+                return Self::error(
+                    error_code::MALFORMED_REQUEST,
+                    err.to_string(),
+                )
+            }
+        };
+        if !matches!(request, KvPayload::Read { .. }) {
+            self.synced.insert(client.to_string(), Instant::now());
+        }
+        let reply = match request {
+            KvPayload::Read { key } => match self.read(client, &key) {
+                Some(value) => KvPayload::ReadOk { value },
+                None => {
+                    return Self::error(
+                        error_code::KEY_DOES_NOT_EXIST,
+                        format!("key {} does not exist", key),
+                    )
+                }
+            },
+            KvPayload::Write { key, value } => {
+                let number = self.set(&key, value);
+                self.observe(client, &key, number);
+                KvPayload::WriteOk
+            }
+            KvPayload::Cas {
+                key,
+                from,
+                to,
+                create_if_not_exists,
+            } => {
+                // the swap compares against the newest value, and the client has seen it either way
+                let newest = self
+                    .versions
+                    .get(&key)
+                    .and_then(|versions| versions.last())
+                    .map(|(number, _, value)| (*number, value.clone()));
+                if let Some((number, _)) = newest {
+                    self.observe(client, &key, number);
+                }
+                match newest.and_then(|(_, value)| value) {
+                    Some(value) if value != from => {
+                        return Self::error(
+                            error_code::PRECONDITION_FAILED,
+                            format!("expected {}, but had {}", from, value),
+                        )
+                    }
+                    None if !create_if_not_exists => {
+                        return Self::error(
+                            error_code::KEY_DOES_NOT_EXIST,
+                            format!("key {} does not exist", key),
+                        )
+                    }
+                    _ => {
+                        let number = self.set(&key, to);
+                        self.observe(client, &key, number);
+                        KvPayload::CasOk
+                    }
+                }
+            }
+            KvPayload::ReadOk { .. }
+            | KvPayload::WriteOk
+            | KvPayload::CasOk => {
+                return Self::error(
+                    error_code::NOT_SUPPORTED,
+                    "not a request".to_string(),
+                )
+            }
+        };
+        serde_json::to_value(reply).expect("kv payloads always serialize")
+    }
+}
+
+// the router thread: holds messages until they are due, then hands them to their destination
+fn route(shared: Arc<Shared>, inbox: Receiver<Message<Value>>) {
+    let mut in_flight = BinaryHeap::new();
+    let mut seq = 0;
+    let mut services: HashMap<&str, KvStore> = [
+        (LIN_KV, KvStore::default()),
+        (SEQ_KV, KvStore::new(shared.config.seq_kv_staleness)),
+    ]
+    .into_iter()
+    .collect();
+    let mut next_service_id = 1;
+
+    while !shared.network_stopped.load(Ordering::SeqCst) {
+        let wait = in_flight
+            .peek()
+            .map(|next: &InFlight| {
+                next.at.saturating_duration_since(Instant::now())
+            })
+            .unwrap_or(Duration::from_millis(50))
+            .min(Duration::from_millis(50));
+        match inbox.recv_timeout(wait) {
+            Ok(message) => {
+                if !shared.loses(&message.src, &message.dst) {
+                    in_flight.push(InFlight {
+                        at: Instant::now() + shared.latency(),
+                        seq,
+                        message,
+                    });
+                    seq += 1;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        while in_flight
+            .peek()
+            .is_some_and(|next| next.at <= Instant::now())
+        {
+            let InFlight { message, .. } = in_flight.pop().unwrap();
+
+            if let Some(service) = services.get_mut(message.dst.as_str()) {
+                let reply = Message {
+                    src: message.dst.clone(),
+                    dst: message.src.clone(),
+                    body: Body {
+                        id: Some(next_service_id),
+                        in_reply_to: message.body.id,
+                        payload: service
+                            .handle(&message.src, message.body.payload),
+                    },
+                };
+                next_service_id += 1;
+                in_flight.push(InFlight {
+                    at: Instant::now() + shared.latency(),
+                    seq,
+                    message: reply,
+                });
+                seq += 1;
+                continue;
+            }
+
+            let destinations = shared.destinations.lock().unwrap();
+            // a message for nobody in the cluster is lost, as in Maelstrom
+            match destinations.get(&message.dst) {
+                Some(Destination::Node(lines)) => {
+                    let line = serde_json::to_string(&message)
+                        .expect("messages of JSON values always serialize");
+                    let _ = lines.send(line);
+                }
+                Some(Destination::Client(tx)) => {
+                    let _ = tx.send(message);
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+pub struct Simulation {
+    shared: Arc<Shared>,
+    network: Sender<Message<Value>>,
+    nodes: Vec<JoinHandle<()>>,
+    next_client: AtomicUsize,
+}
+
+impl Simulation {
+    // starts node_count nodes named n0, n1, ..., every one created from a clone of init_state
+    pub fn start<S, N, P, IP>(
+        node_count: usize,
+        config: NetworkConfig,
+        init_state: S,
+    ) -> Self
+    where
+        S: Clone + Send + 'static,
+        N: Node<S, P, IP>,
+        P: DeserializeOwned + Send + 'static,
+        IP: Send + 'static,
+    {
+        let node_ids: Vec<_> =
+            (0..node_count).map(|i| format!("n{}", i)).collect();
+        let shared = Arc::new(Shared {
+            config,
+            node_ids: node_ids.clone(),
+            partition: Mutex::new(HashMap::new()),
+            destinations: Mutex::new(HashMap::new()),
+            errors: Mutex::new(Vec::new()),
+            network_stopped: AtomicBool::new(false),
+        });
+
+        let (network, inbox) = mpsc::channel();
+        let router = shared.clone();
+        thread::spawn(move || route(router, inbox));
+
+        let mut nodes = vec![];
+        for node_id in &node_ids {
+            let (lines, inbox) = mpsc::channel();
+            // the init message comes first, as from Maelstrom, its init_ok goes to nobody
+            let init = Message {
+                src: "c0".to_string(),
+                dst: node_id.clone(),
+                body: Body {
+                    id: Some(0),
+                    in_reply_to: None,
+                    payload: InitPayload::Init(Init {
+                        node_id: node_id.clone(),
+                        node_ids: node_ids.clone(),
+                    }),
+                },
+            };
+            let _ = lines.send(
+                serde_json::to_string(&init)
+                    .expect("init messages always serialize"),
+            );
+            shared
+                .destinations
+                .lock()
+                .unwrap()
+                .insert(node_id.clone(), Destination::Node(lines));
+
+            let input = Inbox {
+                lines: inbox,
+                line: vec![],
+                pos: 0,
+            };
+            let output = Outbox {
+                buffer: vec![],
+                network: network.clone(),
+            };
+            let (node_id, state, shared) =
+                (node_id.clone(), init_state.clone(), shared.clone());
+            nodes.push(thread::spawn(move || {
+                let result =
+                    main_loop_with::<S, N, P, IP, _, _>(state, input, output);
+                if let Err(err) = result {
+                    shared.fail(&node_id, err);
+                }
+            }));
+        }
+
+        Self {
+            shared,
+            network,
+            nodes,
+            next_client: AtomicUsize::new(1),
+        }
+    }
+
+    pub fn node_ids(&self) -> &[String] {
+        &self.shared.node_ids
+    }
+
+    // a new client, named c1, c2, ...
+    pub fn client(&self) -> Client {
+        let id =
+            format!("c{}", self.next_client.fetch_add(1, Ordering::SeqCst));
+        let (tx, inbox) = mpsc::channel();
+        self.shared
+            .destinations
+            .lock()
+            .unwrap()
+            .insert(id.clone(), Destination::Client(tx));
+        Client {
+            id,
+            next_id: 1,
+            inbox,
+            network: self.network.clone(),
+        }
+    }
+
+    // splits the nodes into groups that cannot talk to each other, nodes in no group are alone
+    pub fn partition(&self, groups: &[&[&str]]) {
+        let mut partition = self.shared.partition.lock().unwrap();
+        partition.clear();
+        for (group, nodes) in groups.iter().enumerate() {
+            for node in *nodes {
+                partition.insert(node.to_string(), group);
+            }
+        }
+        let mut alone = groups.len();
+        for node in &self.shared.node_ids {
+            if !partition.contains_key(node) {
+                partition.insert(node.clone(), alone);
+                alone += 1;
+            }
+        }
+    }
+
+    pub fn heal(&self) {
+        self.shared.partition.lock().unwrap().clear();
+    }
+
+    // everything that went wrong in the nodes so far
+    pub fn errors(&self) -> Vec<String> {
+        self.shared.errors.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        // the end of their input stops the nodes, the network keeps going until they did,
+        // a step may still be waiting for a reply
+        self.shared
+            .destinations
+            .lock()
+            .unwrap()
+            .retain(|_, destination| {
+                matches!(destination, Destination::Client(_))
+            });
+        for node in self.nodes.drain(..) {
+            let _ = node.join();
+        }
+        self.shared.network_stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/*
+A Maelstrom client: sends requests to the nodes and waits for their replies.
+*/
+pub struct Client {
+    id: String,
+    next_id: usize,
+    inbox: Receiver<Message<Value>>,
+    network: Sender<Message<Value>>,
+}
+
+impl Client {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    // the reply payload, None when no reply arrived in time
+    pub fn request(
+        &mut self,
+        dst: &str,
+        payload: Value,
+        timeout: Duration,
+    ) -> Option<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = Message {
+            src: self.id.clone(),
+            dst: dst.to_string(),
+            body: Body {
+                id: Some(id),
+                in_reply_to: None,
+                payload,
+            },
+        };
+        self.network.send(message).ok()?;
+
+        // replies to earlier requests that timed out may still show up, they are skipped
+        let deadline = Instant::now() + timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let reply = self.inbox.recv_timeout(wait).ok()?;
+            if reply.body.in_reply_to == Some(id) {
+                return Some(reply.body.payload);
+            }
+        }
+    }
+}
+
+// how long a workload client waits for a reply
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn check_errors(sim: &Simulation) -> anyhow::Result<()> {
+    let errors = sim.errors();
+    anyhow::ensure!(errors.is_empty(), "nodes failed: {:?}", errors);
+    Ok(())
+}
+
+/*
+Broadcast workload: every node is told every other node is its neighbour, then the messages 0..messages
+are broadcast round robin over the nodes. After settle, every node has to read every message it acknowledged.
+*/
+pub fn broadcast_workload(
+    sim: &Simulation,
+    messages: usize,
+    settle: Duration,
+) -> anyhow::Result<()> {
+    let mut client = sim.client();
+    let nodes = sim.node_ids().to_vec();
+
+    let topology: HashMap<_, Vec<_>> = nodes
+        .iter()
+        .map(|n| {
+            (
+                n.clone(),
+                nodes.iter().filter(|m| m != &n).cloned().collect(),
+            )
+        })
+        .collect();
+    for node in &nodes {
+        let reply = client
+            .request(
+                node,
+                json!({"type": "topology", "topology": topology}),
+                REQUEST_TIMEOUT,
+            )
+            .with_context(|| format!("{} did not answer topology", node))?;
+        anyhow::ensure!(
+            reply["type"] == "topology_ok",
+            "{} answered topology with {}",
+            node,
+            reply
+        );
+    }
+
+    let mut acknowledged = vec![];
+    for message in 0..messages {
+        let node = &nodes[message % nodes.len()];
+        let reply = client.request(
+            node,
+            json!({"type": "broadcast", "message": message}),
+            REQUEST_TIMEOUT,
+        );
+        if reply.is_some_and(|reply| reply["type"] == "broadcast_ok") {
+            acknowledged.push(message);
+        }
+    }
+
+    thread::sleep(settle);
+    for node in &nodes {
+        let reply = client
+            .request(node, json!({"type": "read"}), REQUEST_TIMEOUT)
+            .with_context(|| format!("{} did not answer read", node))?;
+        let read: HashSet<usize> = serde_json::from_value(
+            reply["messages"].clone(),
+        )
+        .with_context(|| format!("{} answered read with {}", node, reply))?;
+        let lost: Vec<_> =
+            acknowledged.iter().filter(|m| !read.contains(m)).collect();
+        anyhow::ensure!(
+            lost.is_empty(),
+            "{} lost acknowledged messages {:?}",
+            node,
+            lost
+        );
+    }
+    check_errors(sim)
+}
+
+/*
+Grow-only counter workload: adds the deltas 1, 2, 3, 1, 2, 3, ... round robin over the nodes.
+After settle, every node has to read at least the sum of the acknowledged adds,
+and no more than the sum of all adds, the unacknowledged ones may or may not have happened.
+*/
+pub fn g_counter_workload(
+    sim: &Simulation,
+    adds: usize,
+    settle: Duration,
+) -> anyhow::Result<()> {
+    let mut client = sim.client();
+    let nodes = sim.node_ids().to_vec();
+
+    let (mut acknowledged, mut attempted) = (0, 0);
+    for add in 0..adds {
+        let node = &nodes[add % nodes.len()];
+        let delta = add % 3 + 1;
+        attempted += delta;
+        let reply = client.request(
+            node,
+            json!({"type": "add", "delta": delta}),
+            REQUEST_TIMEOUT,
+        );
+        if reply.is_some_and(|reply| reply["type"] == "add_ok") {
+            acknowledged += delta;
+        }
+    }
+
+    thread::sleep(settle);
+    for node in &nodes {
+        let reply = client
+            .request(node, json!({"type": "read"}), REQUEST_TIMEOUT)
+            .with_context(|| format!("{} did not answer read", node))?;
+        let value = reply["value"]
+            .as_u64()
+            .with_context(|| format!("{} answered read with {}", node, reply))?
+            as usize;
+        anyhow::ensure!(
+            (acknowledged..=attempted).contains(&value),
+            "{} read {}, expected between {} and {}",
+            node,
+            value,
+            acknowledged,
+            attempted
+        );
+    }
+    check_errors(sim)
+}
@@ -0,0 +1,301 @@
+use anyhow::Context;
+use dist::{main_loop, Body, Event, Message, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::Duration;
+
+/*
+Totally available transactions, read committed: every node runs a transaction on its own,
+without asking anybody, so it keeps answering while the network is partitioned.
+
+A transaction runs within a single step, nothing else happens on the node in between, so it is atomic locally.
+Its writes are committed together, only the last write to every key, and handed to the other nodes
+as a commit. A node never sees a value another transaction wrote but did not commit, nor a value
+a transaction overwrote itself, which is what read committed asks for.
+
+Commits spread by gossip. A node sends a peer every commit the peer did not acknowledge yet,
+again and again, so after a partition heals the commits that were held back still get through.
+Every write carries the version of its commit, a Lamport clock and the node id, the newest version
+of a key wins, and all nodes end up with the same values no matter in which order commits arrive.
+*/
+
+// how often the nodes gossip their commits
+const GOSSIP_INTERVAL: Duration = Duration::from_millis(300);
+
+// Lamport clock and node of a commit, later versions win, the node id breaks ties
+type Version = (usize, String);
+
+// node and sequence number of a commit
+type CommitId = (String, usize);
+
+struct TxnNode {
+    id: usize,
+    node: String,
+    peers: Vec<String>,
+
+    // Lamport clock, larger than every commit this node knows
+    clock: usize,
+
+    // key: register
+    // value: the current value and the version of the commit that wrote it
+    store: HashMap<usize, (Version, usize)>,
+
+    // every commit this node knows, its own and the ones gossiped by others
+    commits: HashMap<CommitId, Commit>,
+
+    // commits made by this node, numbers the commit ids
+    seq: usize,
+
+    // key: node id
+    // value: commits we know the peer knows
+    known: HashMap<String, HashSet<CommitId>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Commit {
+    node: String,
+    seq: usize,
+    clock: usize,
+    // pairs of key and the last value the transaction wrote to it
+    // a list rather than a map: map keys inside a flattened payload have to be strings
+    writes: Vec<(usize, usize)>,
+}
+
+impl Commit {
+    fn id(&self) -> CommitId {
+        (self.node.clone(), self.seq)
+    }
+
+    fn version(&self) -> Version {
+        (self.clock, self.node.clone())
+    }
+}
+
+/*
+A micro-op is encoded as an array of three: ["r", key, value] or ["w", key, value].
+A read comes in with a null value, and goes back with the value it read, null for a key never written.
+
+Serde has no derive for that shape, so Op converts to and from the plain tuple (String, usize, Option<usize>),
+which serde encodes as exactly that array. An unknown function, or a write without a value,
+fails the conversion, and with it the decoding of the whole message.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    try_from = "(String, usize, Option<usize>)",
+    into = "(String, usize, Option<usize>)"
+)]
+pub enum Op {
+    Read { key: usize, value: Option<usize> },
+    Write { key: usize, value: usize },
+}
+
+impl TryFrom<(String, usize, Option<usize>)> for Op {
+    type Error = String;
+
+    fn try_from(
+        (f, key, value): (String, usize, Option<usize>),
+    ) -> Result<Self, Self::Error> {
+        match (f.as_str(), value) {
+            ("r", value) => Ok(Op::Read { key, value }),
+            ("w", Some(value)) => Ok(Op::Write { key, value }),
+            ("w", None) => Err(format!("write to {} without a value", key)),
+            (f, _) => Err(format!("unknown micro-op function {:?}", f)),
+        }
+    }
+}
+
+impl From<Op> for (String, usize, Option<usize>) {
+    fn from(op: Op) -> Self {
+        match op {
+            Op::Read { key, value } => ("r".to_string(), key, value),
+            Op::Write { key, value } => ("w".to_string(), key, Some(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Payload {
+    Txn { txn: Vec<Op> },
+    TxnOk { txn: Vec<Op> },
+    Gossip { commits: Vec<Commit> },
+    GossipOk { ids: Vec<CommitId> },
+}
+
+enum InjectedPayload {
+    Gossip,
+}
+
+impl TxnNode {
+    // runs the transaction and commits its writes, returns the micro-ops with the values read
+    fn transact(&mut self, txn: Vec<Op>) -> Vec<Op> {
+        // the transaction reads its own writes before anybody else's
+        let mut writes = HashMap::new();
+        let txn = txn
+            .into_iter()
+            .map(|op| match op {
+                Op::Read { key, .. } => {
+                    let value = writes
+                        .get(&key)
+                        .or_else(|| self.store.get(&key).map(|(_, v)| v))
+                        .copied();
+                    Op::Read { key, value }
+                }
+                Op::Write { key, value } => {
+                    writes.insert(key, value);
+                    op
+                }
+            })
+            .collect();
+
+        if !writes.is_empty() {
+            self.clock += 1;
+            self.seq += 1;
+            let commit = Commit {
+                node: self.node.clone(),
+                seq: self.seq,
+                clock: self.clock,
+                writes: writes.into_iter().collect(),
+            };
+            self.apply(commit);
+        }
+        txn
+    }
+
+    // applies the writes newer than the stored values, a commit known before changes nothing
+    fn apply(&mut self, commit: Commit) {
+        if self.commits.contains_key(&commit.id()) {
+            return;
+        }
+        self.clock = self.clock.max(commit.clock);
+
+        let version = commit.version();
+        for &(key, value) in &commit.writes {
+            let newer = self
+                .store
+                .get(&key)
+                .is_none_or(|(stored, _)| stored < &version);
+            if newer {
+                self.store.insert(key, (version.clone(), value));
+            }
+        }
+        self.commits.insert(commit.id(), commit);
+    }
+
+    fn gossip(&self, output: &mut impl Write) -> anyhow::Result<()> {
+        for peer in &self.peers {
+            let known_to_peer = &self.known[peer];
+            let commits: Vec<_> = self
+                .commits
+                .iter()
+                .filter(|(id, _)| !known_to_peer.contains(*id))
+                .map(|(_, commit)| commit.clone())
+                .collect();
+            if commits.is_empty() {
+                continue;
+            }
+
+            Message {
+                src: self.node.clone(),
+                dst: peer.clone(),
+                body: Body {
+                    id: None,
+                    in_reply_to: None,
+                    payload: Payload::Gossip { commits },
+                },
+            }
+            .send(&mut *output)
+            .with_context(|| format!("gossip to {}", peer))?;
+        }
+        Ok(())
+    }
+}
+
+impl Node<(), Payload, InjectedPayload> for TxnNode {
+    fn from_init(
+        _state: (),
+        init: dist::Init,
+        tx: std::sync::mpsc::Sender<Event<Payload, InjectedPayload>>,
+        _rpc: dist::Rpc,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(GOSSIP_INTERVAL);
+            if tx.send(Event::Injected(InjectedPayload::Gossip)).is_err() {
+                break;
+            }
+        });
+
+        let peers: Vec<_> = init
+            .node_ids
+            .into_iter()
+            .filter(|n| n != &init.node_id)
+            .collect();
+        Ok(Self {
+            id: 1,
+            node: init.node_id,
+            known: peers.iter().map(|p| (p.clone(), HashSet::new())).collect(),
+            peers,
+            clock: 0,
+            store: HashMap::new(),
+            commits: HashMap::new(),
+            seq: 0,
+        })
+    }
+
+    fn step(
+        &mut self,
+        input: Event<Payload, InjectedPayload>,
+        output: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        match input {
+            Event::EOF => {}
+
+            Event::Injected(InjectedPayload::Gossip) => {
+                self.gossip(output)?;
+            }
+
+            Event::Message(input) => {
+                let mut reply = input.into_reply(Some(&mut self.id));
+                match reply.body.payload {
+                    Payload::Txn { txn } => {
+                        let txn = self.transact(txn);
+                        reply.body.payload = Payload::TxnOk { txn };
+                        reply.send(&mut *output).context("reply to txn")?;
+                    }
+
+                    Payload::Gossip { commits } => {
+                        let ids: Vec<_> =
+                            commits.iter().map(Commit::id).collect();
+                        // the peer gossiping the commits knows them, no need to send them back
+                        if let Some(known) = self.known.get_mut(&reply.dst) {
+                            known.extend(ids.iter().cloned());
+                        }
+                        for commit in commits {
+                            self.apply(commit);
+                        }
+                        reply.body.payload = Payload::GossipOk { ids };
+                        reply.send(&mut *output).context("reply to gossip")?;
+                    }
+
+                    Payload::GossipOk { ids } => {
+                        if let Some(known) = self.known.get_mut(&reply.dst) {
+                            known.extend(ids);
+                        }
+                    }
+
+                    Payload::TxnOk { .. } => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    main_loop::<_, TxnNode, _, _>(())
+}
@@ -1,10 +1,7 @@
 use anyhow::Context;
 use dist::{main_loop, Body, Event, Message, Node};
 use serde::{Deserialize, Serialize};
-use std::{
-    io::{StdoutLock, Write},
-    thread::sleep,
-};
+use std::{io::Write, thread::sleep};
 
 struct UniqueNode {
     id: usize,
@@ -26,6 +23,7 @@ impl Node<(), Payload> for UniqueNode {
         _state: (),
         init: dist::Init,
         inject: std::sync::mpsc::Sender<Event<Payload>>,
+        _rpc: dist::Rpc,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -39,7 +37,7 @@ impl Node<(), Payload> for UniqueNode {
     fn step(
         &mut self,
         input: Event<Payload>,
-        output: &mut StdoutLock,
+        output: &mut impl Write,
     ) -> anyhow::Result<()> {
         let Event::Message(input) = input else {
             panic!();
@@ -0,0 +1,131 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use dist::sim::KvStore;
+use dist::{Body, Message};
+use serde_json::{json, Value};
+
+/*
+Stand-in for lin-kv or seq-kv, shared by all nodes of a test: the simulator's KvStore without staleness,
+linearizable, which is also a valid sequentially consistent store.
+With drop_cas_replies set, that many swaps are applied without their reply ever being sent.
+*/
+#[derive(Default)]
+pub struct FakeKv {
+    pub store: KvStore,
+    pub drop_cas_replies: usize,
+}
+
+impl FakeKv {
+    // the reply payload to the client's request, None when the reply gets lost
+    pub fn handle(&mut self, client: &str, request: Value) -> Option<Value> {
+        let is_cas = request["type"] == "cas";
+        let reply = self.store.handle(client, request);
+        if is_cas && reply["type"] == "cas_ok" && self.drop_cas_replies > 0 {
+            self.drop_cas_replies -= 1;
+            return None;
+        }
+        Some(reply)
+    }
+}
+
+// a node binary, talking to the test over its STDIN and STDOUT, its calls to the kv service answered by a FakeKv
+pub struct NodeProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Receiver<Message<Value>>,
+    node_id: String,
+    service: &'static str,
+    next_id: usize,
+}
+
+impl NodeProcess {
+    // bin is the path of the binary, CARGO_BIN_EXE_<name>
+    pub fn start(
+        bin: &str,
+        service: &'static str,
+        node_id: &str,
+        node_ids: &[&str],
+        kv: &mut FakeKv,
+    ) -> Self {
+        let mut child = Command::new(bin)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let reader = BufReader::new(child.stdout.take().unwrap());
+        let (tx, stdout) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = serde_json::from_str(&line.unwrap()).unwrap();
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut process = NodeProcess {
+            child,
+            stdin,
+            stdout,
+            node_id: node_id.to_string(),
+            service,
+            next_id: 1,
+        };
+        let init = json!({
+            "type": "init",
+            "node_id": node_id,
+            "node_ids": node_ids,
+        });
+        let reply = process.request(init, kv);
+        assert_eq!(reply["type"], "init_ok");
+        process
+    }
+
+    fn send(&mut self, src: &str, in_reply_to: Option<usize>, payload: Value) {
+        let message = Message {
+            src: src.to_string(),
+            dst: self.node_id.clone(),
+            body: Body {
+                id: Some(self.next_id),
+                in_reply_to,
+                payload,
+            },
+        };
+        self.next_id += 1;
+        message.send(&mut self.stdin).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    // sends a client request and answers the node's calls to the kv service until the client gets its reply
+    pub fn request(&mut self, payload: Value, kv: &mut FakeKv) -> Value {
+        let id = self.next_id;
+        self.send("c1", None, payload);
+        loop {
+            let message = self
+                .stdout
+                .recv_timeout(Duration::from_secs(5))
+                .expect("node did not answer");
+            if message.dst == self.service {
+                if let Some(reply) =
+                    kv.handle(&message.src, message.body.payload)
+                {
+                    self.send(self.service, message.body.id, reply);
+                }
+            } else if message.body.in_reply_to == Some(id) {
+                return message.body.payload;
+            }
+        }
+    }
+}
+
+impl Drop for NodeProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use dist::SEQ_KV;
+    use serde_json::{json, Value};
+
+    use super::common::{FakeKv, NodeProcess};
+
+    fn start(node_id: &str, node_ids: &[&str], kv: &mut FakeKv) -> NodeProcess {
+        let bin = env!("CARGO_BIN_EXE_g_counter");
+        NodeProcess::start(bin, SEQ_KV, node_id, node_ids, kv)
+    }
+
+    fn add(node: &mut NodeProcess, delta: usize, kv: &mut FakeKv) {
+        let reply = node.request(json!({"type": "add", "delta": delta}), kv);
+        assert_eq!(reply["type"], "add_ok");
+    }
+
+    fn read(node: &mut NodeProcess, kv: &mut FakeKv) -> Value {
+        let reply = node.request(json!({"type": "read"}), kv);
+        assert_eq!(reply["type"], "read_ok");
+        reply["value"].clone()
+    }
+
+    #[test]
+    fn test_add_and_read() {
+        let mut kv = FakeKv::default();
+        // n2 counted 5 before
+        kv.store.insert("counter-n2", json!(5));
+
+        let mut node = start("n1", &["n1", "n2"], &mut kv);
+        assert_eq!(read(&mut node, &mut kv), json!(5));
+        add(&mut node, 3, &mut kv);
+        add(&mut node, 4, &mut kv);
+        assert_eq!(kv.store.get("counter-n1"), Some(&json!(7)));
+        assert_eq!(read(&mut node, &mut kv), json!(12));
+
+        // n2 keeps counting on its own
+        kv.store.insert("counter-n2", json!(10));
+        assert_eq!(read(&mut node, &mut kv), json!(17));
+    }
+
+    #[test]
+    fn test_lost_cas_reply_counts_once() {
+        let mut kv = FakeKv {
+            drop_cas_replies: 1,
+            ..FakeKv::default()
+        };
+        let mut node = start("n1", &["n1"], &mut kv);
+
+        // the swap happened but the node never heard of it, after the timeout it finds out by reading
+        add(&mut node, 3, &mut kv);
+        add(&mut node, 1, &mut kv);
+        assert_eq!(kv.store.get("counter-n1"), Some(&json!(4)));
+        assert_eq!(read(&mut node, &mut kv), json!(4));
+    }
+
+    #[test]
+    fn test_restart_starts_from_stored_value() {
+        let mut kv = FakeKv::default();
+        // a previous run of n1 left its count behind, the first swap fails and the node reads it
+        kv.store.insert("counter-n1", json!(6));
+
+        let mut node = start("n1", &["n1"], &mut kv);
+        add(&mut node, 2, &mut kv);
+        assert_eq!(kv.store.get("counter-n1"), Some(&json!(8)));
+        assert_eq!(read(&mut node, &mut kv), json!(8));
+    }
+}
@@ -0,0 +1,128 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use dist::LIN_KV;
+    use serde_json::{json, Value};
+
+    use super::common::{FakeKv, NodeProcess};
+
+    fn start(node_id: &str, node_ids: &[&str], kv: &mut FakeKv) -> NodeProcess {
+        let bin = env!("CARGO_BIN_EXE_kafka");
+        NodeProcess::start(bin, LIN_KV, node_id, node_ids, kv)
+    }
+
+    // the messages stored for a log, in offset order
+    fn log(kv: &FakeKv, key: &str) -> Vec<Value> {
+        (0..)
+            .map_while(|offset| {
+                kv.store.get(&format!("entry-{}-{}", key, offset))
+            })
+            .map(|entry| entry["msg"].clone())
+            .collect()
+    }
+
+    fn append(
+        node: &mut NodeProcess,
+        key: &str,
+        msg: usize,
+        kv: &mut FakeKv,
+    ) -> Value {
+        let reply =
+            node.request(json!({"type": "send", "key": key, "msg": msg}), kv);
+        assert_eq!(reply["type"], "send_ok");
+        reply["offset"].clone()
+    }
+
+    fn poll(node: &mut NodeProcess, offsets: Value, kv: &mut FakeKv) -> Value {
+        let reply =
+            node.request(json!({"type": "poll", "offsets": offsets}), kv);
+        assert_eq!(reply["type"], "poll_ok");
+        reply["msgs"].clone()
+    }
+
+    fn commit(node: &mut NodeProcess, offsets: Value, kv: &mut FakeKv) {
+        let reply = node
+            .request(json!({"type": "commit_offsets", "offsets": offsets}), kv);
+        assert_eq!(reply["type"], "commit_offsets_ok");
+    }
+
+    fn list_committed(
+        node: &mut NodeProcess,
+        keys: &[&str],
+        kv: &mut FakeKv,
+    ) -> Value {
+        let reply = node.request(
+            json!({"type": "list_committed_offsets", "keys": keys}),
+            kv,
+        );
+        assert_eq!(reply["type"], "list_committed_offsets_ok");
+        reply["offsets"].clone()
+    }
+
+    #[test]
+    fn test_send_poll_and_commit() {
+        let mut kv = FakeKv::default();
+        let mut node = start("n1", &["n1"], &mut kv);
+
+        assert_eq!(append(&mut node, "a", 10, &mut kv), json!(0));
+        assert_eq!(append(&mut node, "a", 11, &mut kv), json!(1));
+        assert_eq!(append(&mut node, "b", 20, &mut kv), json!(0));
+        assert_eq!(append(&mut node, "a", 12, &mut kv), json!(2));
+
+        // a log without messages past the offset polls empty
+        let msgs = poll(&mut node, json!({"a": 1, "b": 0, "c": 0}), &mut kv);
+        assert_eq!(
+            msgs,
+            json!({"a": [[1, 11], [2, 12]], "b": [[0, 20]], "c": []})
+        );
+
+        assert_eq!(list_committed(&mut node, &["a", "b"], &mut kv), json!({}));
+        commit(&mut node, json!({"a": 2, "b": 0}), &mut kv);
+        // committed offsets never go back
+        commit(&mut node, json!({"a": 1}), &mut kv);
+        assert_eq!(
+            list_committed(&mut node, &["a", "b", "c"], &mut kv),
+            json!({"a": 2, "b": 0})
+        );
+    }
+
+    #[test]
+    fn test_nodes_share_the_logs() {
+        let mut kv = FakeKv::default();
+        let mut n1 = start("n1", &["n1", "n2"], &mut kv);
+        let mut n2 = start("n2", &["n1", "n2"], &mut kv);
+
+        // every node learns of the offsets the other one took when its claim fails
+        assert_eq!(append(&mut n1, "a", 1, &mut kv), json!(0));
+        assert_eq!(append(&mut n2, "a", 2, &mut kv), json!(1));
+        assert_eq!(append(&mut n1, "a", 3, &mut kv), json!(2));
+        assert_eq!(append(&mut n1, "a", 4, &mut kv), json!(3));
+        assert_eq!(append(&mut n2, "a", 5, &mut kv), json!(4));
+        assert_eq!(
+            log(&kv, "a"),
+            vec![json!(1), json!(2), json!(3), json!(4), json!(5)]
+        );
+
+        let msgs = json!({"a": [[0, 1], [1, 2], [2, 3], [3, 4], [4, 5]]});
+        assert_eq!(poll(&mut n2, json!({"a": 0}), &mut kv), msgs);
+        assert_eq!(poll(&mut n1, json!({"a": 0}), &mut kv), msgs);
+
+        commit(&mut n1, json!({"a": 3}), &mut kv);
+        assert_eq!(list_committed(&mut n2, &["a"], &mut kv), json!({"a": 3}));
+    }
+
+    #[test]
+    fn test_lost_claim_reply_appends_once() {
+        let mut kv = FakeKv {
+            drop_cas_replies: 1,
+            ..FakeKv::default()
+        };
+        let mut node = start("n1", &["n1"], &mut kv);
+
+        // the claim happened but the node never heard of it, after the timeout it finds its own entry
+        assert_eq!(append(&mut node, "a", 7, &mut kv), json!(0));
+        assert_eq!(append(&mut node, "a", 8, &mut kv), json!(1));
+        assert_eq!(log(&kv, "a"), vec![json!(7), json!(8)]);
+    }
+}
@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use anyhow::Context;
+    use dist::{main_loop_with, Body, Event, Message, Node};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    /*
+    Echoes, and says goodbye to the client when the input ends, to show EOF is the last event stepped through.
+    */
+    struct EchoNode {
+        id: usize,
+        node: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Payload {
+        Echo { echo: String },
+        EchoOk { echo: String },
+        Bye,
+    }
+
+    impl Node<(), Payload> for EchoNode {
+        fn from_init(
+            _state: (),
+            init: dist::Init,
+            _inject: std::sync::mpsc::Sender<Event<Payload>>,
+            _rpc: dist::Rpc,
+        ) -> anyhow::Result<Self> {
+            Ok(Self {
+                id: 1,
+                node: init.node_id,
+            })
+        }
+
+        fn step(
+            &mut self,
+            input: Event<Payload>,
+            output: &mut impl Write,
+        ) -> anyhow::Result<()> {
+            match input {
+                Event::Message(input) => {
+                    let mut reply = input.into_reply(Some(&mut self.id));
+                    if let Payload::Echo { echo } = reply.body.payload {
+                        reply.body.payload = Payload::EchoOk { echo };
+                        reply.send(output).context("reply to echo")?;
+                    }
+                }
+                Event::EOF => {
+                    Message {
+                        src: self.node.clone(),
+                        dst: "c1".to_string(),
+                        body: Body {
+                            id: None,
+                            in_reply_to: None,
+                            payload: Payload::Bye,
+                        },
+                    }
+                    .send(output)
+                    .context("say bye")?;
+                }
+                Event::Injected(()) => {}
+            }
+            Ok(())
+        }
+    }
+
+    // an echo request as an input line
+    fn gen_echo(id: usize, echo: &str) -> String {
+        json!({"src": "c1", "dest": "n1", "body": {"msg_id": id, "type": "echo", "echo": echo}})
+            .to_string()
+            + "\n"
+    }
+
+    fn init_line() -> String {
+        json!({"src": "c1", "dest": "n1", "body": {"msg_id": 1, "type": "init", "node_id": "n1", "node_ids": ["n1"]}})
+            .to_string()
+            + "\n"
+    }
+
+    fn output_lines(output: &[u8]) -> Vec<Value> {
+        output
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_main_loop_over_memory() {
+        let input = init_line() + &gen_echo(2, "a") + &gen_echo(3, "b");
+        let mut output = vec![];
+
+        // the loop returns once the node stepped through the end of the input
+        main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(input),
+            &mut output,
+        )
+        .unwrap();
+
+        let output = output_lines(&output);
+        assert_eq!(output.len(), 4);
+        assert_eq!(output[0]["body"]["type"], "init_ok");
+        assert_eq!(output[0]["body"]["in_reply_to"], 1);
+        assert_eq!(output[1]["body"]["echo"], "a");
+        assert_eq!(output[1]["body"]["in_reply_to"], 2);
+        assert_eq!(output[2]["body"]["echo"], "b");
+        assert_eq!(output[2]["body"]["in_reply_to"], 3);
+        assert_eq!(output[3]["body"]["type"], "bye");
+    }
+
+    #[test]
+    fn test_bad_input_ends_main_loop() {
+        let input =
+            init_line() + &gen_echo(2, "a") + "not json\n" + &gen_echo(3, "b");
+        let mut output = vec![];
+
+        let err = main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(input),
+            &mut output,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("could not be deserialized"));
+
+        // what came before the bad line was handled, nothing after it
+        let output = output_lines(&output);
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[1]["body"]["echo"], "a");
+        assert_eq!(output[2]["body"]["type"], "bye");
+    }
+
+    #[test]
+    fn test_drop_unknown_replies() {
+        // a reply no rpc waits for, the node does not know its payload either
+        let late = json!({"src": "seq-kv", "dest": "n1", "body": {"in_reply_to": 5, "type": "read_ok", "value": 1}})
+            .to_string()
+            + "\n";
+        let input = init_line() + &gen_echo(2, "a") + &late + &gen_echo(3, "b");
+        let mut output = vec![];
+
+        main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(input),
+            &mut output,
+        )
+        .unwrap();
+
+        let output = output_lines(&output);
+        assert_eq!(output.len(), 4);
+        assert_eq!(output[1]["body"]["echo"], "a");
+        assert_eq!(output[2]["body"]["echo"], "b");
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let mut output = vec![];
+        let err = main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(""),
+            &mut output,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no init message received"));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_first_message_not_init() {
+        // a message of another node
+        let mut output = vec![];
+        let err = main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(gen_echo(1, "a")),
+            &mut output,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("could not be deserialized"));
+        assert!(output.is_empty());
+
+        // the reply to an init, sent to the node before its own init
+        let init_ok = json!({"src": "c1", "dest": "n1", "body": {"in_reply_to": 1, "type": "init_ok"}})
+            .to_string()
+            + "\n";
+        let err = main_loop_with::<_, EchoNode, _, _, _, _>(
+            (),
+            Cursor::new(init_ok),
+            &mut output,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "first message should be init");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_main_loop_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let node = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let input = BufReader::new(stream.try_clone().unwrap());
+            main_loop_with::<_, EchoNode, _, _, _, _>((), input, stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut reply = || -> Value {
+            serde_json::from_str(&replies.next().unwrap().unwrap()).unwrap()
+        };
+
+        stream.write_all(init_line().as_bytes()).unwrap();
+        assert_eq!(reply()["body"]["type"], "init_ok");
+        stream
+            .write_all(gen_echo(2, "over tcp").as_bytes())
+            .unwrap();
+        assert_eq!(reply()["body"]["echo"], "over tcp");
+
+        // closing our side is the end of the node's input
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        assert_eq!(reply()["body"]["type"], "bye");
+        node.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use dist::{error_code, Body, Message, Rpc, RpcError};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Payload {
+        Read { key: String },
+        ReadOk { value: usize },
+    }
+
+    // the request rpc wrote to the output, as the other node would read it
+    fn sent_request(output: &[u8]) -> Message<Payload> {
+        let line = std::str::from_utf8(output).unwrap();
+        assert!(line.ends_with('\n'));
+        serde_json::from_str(line).unwrap()
+    }
+
+    fn gen_reply(in_reply_to: usize, payload: Value) -> Message<Value> {
+        Message {
+            src: "seq-kv".to_string(),
+            dst: "n1".to_string(),
+            body: Body {
+                id: Some(7),
+                in_reply_to: Some(in_reply_to),
+                payload,
+            },
+        }
+    }
+
+    fn read(key: &str) -> Payload {
+        Payload::Read {
+            key: key.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_call_and_reply() {
+        let rpc = Rpc::new("n1".to_string());
+        let mut output = vec![];
+        let handle = rpc
+            .call::<_, Payload>("seq-kv", read("counter"), &mut output)
+            .unwrap();
+
+        let request = sent_request(&output);
+        assert_eq!(request.src, "n1");
+        assert_eq!(request.dst, "seq-kv");
+        assert_eq!(request.body.id, Some(handle.id()));
+        assert_eq!(request.body.payload, read("counter"));
+        assert_eq!(rpc.pending(), 1);
+        assert!(handle.try_wait().is_none());
+
+        // the reply is swallowed by rpc, it never reaches the node
+        let reply =
+            gen_reply(handle.id(), json!({"type": "read_ok", "value": 5}));
+        assert!(rpc.route(reply).is_none());
+        assert_eq!(rpc.pending(), 0);
+        assert_eq!(
+            handle.wait(Duration::from_secs(1)),
+            Ok(Payload::ReadOk { value: 5 })
+        );
+    }
+
+    #[test]
+    fn test_replies_are_correlated() {
+        let rpc = Rpc::new("n1".to_string());
+        let mut output = vec![];
+        let first = rpc
+            .call::<_, Payload>("n2", read("a"), &mut output)
+            .unwrap();
+        let second = rpc
+            .call::<_, Payload>("n3", read("b"), &mut output)
+            .unwrap();
+        assert_ne!(first.id(), second.id());
+
+        // replies arrive in the other order, from another thread
+        let router = rpc.clone();
+        let (first_id, second_id) = (first.id(), second.id());
+        thread::spawn(move || {
+            router.route(gen_reply(
+                second_id,
+                json!({"type": "read_ok", "value": 2}),
+            ));
+            router.route(gen_reply(
+                first_id,
+                json!({"type": "read_ok", "value": 1}),
+            ));
+        });
+        assert_eq!(
+            first.wait(Duration::from_secs(1)),
+            Ok(Payload::ReadOk { value: 1 })
+        );
+        assert_eq!(
+            second.wait(Duration::from_secs(1)),
+            Ok(Payload::ReadOk { value: 2 })
+        );
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let rpc = Rpc::new("n1".to_string());
+        let handle = rpc
+            .call::<_, Payload>("seq-kv", read("missing"), &mut vec![])
+            .unwrap();
+        let reply = gen_reply(
+            handle.id(),
+            json!({"type": "error", "code": 20, "text": "key does not exist"}),
+        );
+        rpc.route(reply);
+        assert_eq!(
+            handle.wait(Duration::from_secs(1)),
+            Err(RpcError::Remote {
+                code: error_code::KEY_DOES_NOT_EXIST,
+                text: Some("key does not exist".to_string()),
+            })
+        );
+
+        // neither an error nor the expected payload
+        let handle = rpc
+            .call::<_, Payload>("seq-kv", read("counter"), &mut vec![])
+            .unwrap();
+        rpc.route(gen_reply(handle.id(), json!({"type": "write_ok"})));
+        assert!(matches!(
+            handle.wait(Duration::from_secs(1)),
+            Err(RpcError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_timeout_and_late_reply() {
+        let rpc = Rpc::new("n1".to_string());
+        let handle = rpc
+            .call::<_, Payload>("n2", read("a"), &mut vec![])
+            .unwrap();
+        let id = handle.id();
+        assert_eq!(rpc.pending(), 1);
+        assert_eq!(
+            handle.wait(Duration::from_millis(10)),
+            Err(RpcError::Timeout)
+        );
+        assert_eq!(rpc.pending(), 0);
+
+        // the reply showing up after the timeout matches no request anymore
+        let late = gen_reply(id, json!({"type": "read_ok", "value": 1}));
+        assert!(rpc.route(late).is_some());
+        assert_eq!(rpc.pending(), 0);
+
+        // the same for a handle dropped without waiting
+        let handle = rpc
+            .call::<_, Payload>("n2", read("a"), &mut vec![])
+            .unwrap();
+        assert_eq!(rpc.pending(), 1);
+        drop(handle);
+        assert_eq!(rpc.pending(), 0);
+    }
+
+    #[test]
+    fn test_other_messages_pass_through() {
+        let rpc = Rpc::new("n1".to_string());
+
+        // a request from a client, and a reply to a request rpc never sent
+        let request = Message {
+            src: "c1".to_string(),
+            dst: "n1".to_string(),
+            body: Body {
+                id: Some(3),
+                in_reply_to: None,
+                payload: json!({"type": "read", "key": "a"}),
+            },
+        };
+        let routed = rpc.route(request).unwrap();
+        let typed: Message<Payload> = routed.decode_payload().unwrap();
+        assert_eq!(typed.body.payload, read("a"));
+
+        let unknown = gen_reply(99, json!({"type": "read_ok", "value": 1}));
+        assert!(rpc.route(unknown).is_some());
+    }
+}
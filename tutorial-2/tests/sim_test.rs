@@ -0,0 +1,251 @@
+// the nodes live in their binaries, the tests compile the binaries' sources as modules to get at them
+#[allow(dead_code)]
+#[path = "../src/broadcast/multi_broadcast.rs"]
+mod multi_broadcast;
+
+#[allow(dead_code)]
+#[path = "../src/g_counter/g_counter.rs"]
+mod g_counter;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use dist::sim::{
+        broadcast_workload, g_counter_workload, KvStore, NetworkConfig,
+        Simulation,
+    };
+    use dist::{error_code, Event, Node};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::g_counter::CounterNode;
+    use super::multi_broadcast::BroadcastNode;
+
+    #[test]
+    fn test_broadcast() {
+        let sim = Simulation::start::<_, BroadcastNode, _, _>(
+            5,
+            NetworkConfig::default(),
+            (),
+        );
+        broadcast_workload(&sim, 40, Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn test_broadcast_through_partition_and_drops() {
+        let config = NetworkConfig {
+            min_latency: Duration::from_millis(1),
+            max_latency: Duration::from_millis(10),
+            drop_rate: 0.2,
+            ..NetworkConfig::default()
+        };
+        let sim = Simulation::start::<_, BroadcastNode, _, _>(4, config, ());
+
+        // the first half of the broadcasts happens while the cluster is split in two
+        sim.partition(&[&["n0", "n1"], &["n2", "n3"]]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(200));
+                sim.heal();
+            });
+            broadcast_workload(&sim, 40, Duration::from_secs(2)).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_g_counter() {
+        let sim = Simulation::start::<_, CounterNode, _, _>(
+            3,
+            NetworkConfig::default(),
+            (),
+        );
+        // the counter nodes only talk to seq-kv, a partition between them changes nothing
+        sim.partition(&[&["n0"], &["n1"], &["n2"]]);
+        g_counter_workload(&sim, 30, Duration::from_millis(100)).unwrap();
+    }
+
+    #[test]
+    fn test_g_counter_reads_after_adds_stop() {
+        // nothing makes seq-kv show a node that only reads anything newer than what it saw
+        let config = NetworkConfig {
+            seq_kv_staleness: Duration::from_secs(3600),
+            ..NetworkConfig::default()
+        };
+        let sim = Simulation::start::<_, CounterNode, _, _>(3, config, ());
+        // no add is lost, so every node has to read the total
+        g_counter_workload(&sim, 30, Duration::ZERO).unwrap();
+    }
+
+    #[test]
+    fn test_seq_kv_stale_reads() {
+        let mut kv = KvStore::new(Duration::from_secs(3600));
+        let read = |kv: &mut KvStore, client: &str| {
+            kv.handle(client, json!({"type": "read", "key": "k"}))
+        };
+        let write = json!({"type": "write", "key": "k", "value": 1});
+        assert_eq!(kv.handle("n0", write), json!({"type": "write_ok"}));
+        assert_eq!(kv.get("k"), Some(&json!(1)));
+
+        // the writer sees its own write, others may still find the key missing
+        assert_eq!(read(&mut kv, "n0"), json!({"type": "read_ok", "value": 1}));
+        let replies: Vec<_> = (0..50)
+            .map(|client| read(&mut kv, &format!("c{}", client)))
+            .collect();
+        assert!(replies.iter().any(|reply| reply["type"] == "error"));
+        assert!(replies.iter().any(|reply| reply["value"] == 1));
+
+        // a client never goes back to an older value than it saw
+        let cas = json!({"type": "cas", "key": "k", "from": 1, "to": 2});
+        assert_eq!(kv.handle("n1", cas), json!({"type": "cas_ok"}));
+        let mut newest = 0;
+        for _ in 0..50 {
+            let value = read(&mut kv, "n2")["value"].as_u64().unwrap_or(0);
+            assert!(value >= newest);
+            newest = value;
+        }
+
+        // a failed swap shows the newest value, the reads after it do too
+        let cas = json!({"type": "cas", "key": "k", "from": 1, "to": 3});
+        assert_eq!(
+            kv.handle("n3", cas)["code"],
+            error_code::PRECONDITION_FAILED
+        );
+        assert_eq!(read(&mut kv, "n3")["value"], 2);
+
+        // a write to any key brings the client up to date with every key
+        let write = json!({"type": "write", "key": "other", "value": 0});
+        assert_eq!(kv.handle("n4", write), json!({"type": "write_ok"}));
+        assert_eq!(read(&mut kv, "n4")["value"], 2);
+    }
+
+    #[test]
+    fn test_seq_kv_catches_up() {
+        let mut kv = KvStore::new(Duration::from_millis(20));
+        kv.insert("k", json!(1));
+        kv.insert("k", json!(2));
+        thread::sleep(Duration::from_millis(50));
+        for client in 0..20 {
+            let reply = kv.handle(
+                &format!("c{}", client),
+                json!({"type": "read", "key": "k"}),
+            );
+            assert_eq!(reply["value"], 2);
+        }
+
+        // lin-kv never reads stale
+        let mut kv = KvStore::default();
+        for value in 0..20 {
+            kv.insert("k", json!(value));
+            let reply = kv.handle("c1", json!({"type": "read", "key": "k"}));
+            assert_eq!(reply["value"], value);
+        }
+    }
+
+    /*
+    A broadcast node that acknowledges every message and forgets it, the checker has to catch it.
+    */
+    struct ForgetfulNode {
+        id: usize,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Payload {
+        Broadcast { message: usize },
+        BroadcastOk,
+        Read,
+        ReadOk { messages: Vec<usize> },
+        Topology { topology: serde_json::Value },
+        TopologyOk,
+    }
+
+    impl Node<(), Payload> for ForgetfulNode {
+        fn from_init(
+            _state: (),
+            _init: dist::Init,
+            _inject: std::sync::mpsc::Sender<Event<Payload>>,
+            _rpc: dist::Rpc,
+        ) -> anyhow::Result<Self> {
+            Ok(Self { id: 1 })
+        }
+
+        fn step(
+            &mut self,
+            input: Event<Payload>,
+            output: &mut impl Write,
+        ) -> anyhow::Result<()> {
+            let Event::Message(input) = input else {
+                return Ok(());
+            };
+            let mut reply = input.into_reply(Some(&mut self.id));
+            reply.body.payload = match reply.body.payload {
+                Payload::Broadcast { .. } => Payload::BroadcastOk,
+                Payload::Read => Payload::ReadOk { messages: vec![] },
+                Payload::Topology { .. } => Payload::TopologyOk,
+                _ => return Ok(()),
+            };
+            reply.send(output).context("reply")
+        }
+    }
+
+    #[test]
+    fn test_checker_catches_lost_messages() {
+        let sim = Simulation::start::<_, ForgetfulNode, _, _>(
+            2,
+            NetworkConfig::default(),
+            (),
+        );
+        let err = broadcast_workload(&sim, 4, Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("lost acknowledged messages"));
+    }
+
+    #[test]
+    fn test_client_and_node_errors() {
+        let sim = Simulation::start::<_, ForgetfulNode, _, _>(
+            1,
+            NetworkConfig::default(),
+            (),
+        );
+        let mut client = sim.client();
+        assert_eq!(client.id(), "c1");
+
+        let reply = client.request(
+            "n0",
+            json!({"type": "read"}),
+            Duration::from_secs(1),
+        );
+        assert_eq!(reply, Some(json!({"type": "read_ok", "messages": []})));
+
+        // nobody by that name, the request is lost
+        let reply = client.request(
+            "n7",
+            json!({"type": "read"}),
+            Duration::from_millis(50),
+        );
+        assert_eq!(reply, None);
+
+        // a message the node cannot decode ends its main loop, as it would end the process under Maelstrom
+        let reply = client.request(
+            "n0",
+            json!({"type": "add"}),
+            Duration::from_millis(50),
+        );
+        assert_eq!(reply, None);
+        let errors = loop {
+            let errors = sim.errors();
+            if !errors.is_empty() {
+                break errors;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("n0: "));
+        assert!(client
+            .request("n0", json!({"type": "read"}), Duration::from_millis(100))
+            .is_none());
+    }
+}
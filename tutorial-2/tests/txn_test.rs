@@ -0,0 +1,193 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Child, ChildStdin, Command, Stdio};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use dist::{Body, Message};
+    use serde_json::{json, Value};
+
+    // a txn process, talking to the test over its STDIN and STDOUT
+    struct TxnProcess {
+        child: Child,
+        stdin: ChildStdin,
+        stdout: Receiver<Message<Value>>,
+        next_id: usize,
+    }
+
+    impl TxnProcess {
+        fn start(node_ids: &[&str]) -> Self {
+            let mut child = Command::new(env!("CARGO_BIN_EXE_txn"))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let stdin = child.stdin.take().unwrap();
+            let reader = BufReader::new(child.stdout.take().unwrap());
+            let (tx, stdout) = mpsc::channel();
+            thread::spawn(move || {
+                for line in reader.lines() {
+                    let message = serde_json::from_str(&line.unwrap()).unwrap();
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut process = TxnProcess {
+                child,
+                stdin,
+                stdout,
+                next_id: 1,
+            };
+            let init = json!({
+                "type": "init",
+                "node_id": "n1",
+                "node_ids": node_ids,
+            });
+            let reply = process.request("c1", init);
+            assert_eq!(reply["type"], "init_ok");
+            process
+        }
+
+        fn send(&mut self, src: &str, payload: Value) -> usize {
+            let id = self.next_id;
+            let message = Message {
+                src: src.to_string(),
+                dst: "n1".to_string(),
+                body: Body {
+                    id: Some(id),
+                    in_reply_to: None,
+                    payload,
+                },
+            };
+            self.next_id += 1;
+            message.send(&mut self.stdin).unwrap();
+            self.stdin.flush().unwrap();
+            id
+        }
+
+        // the next message the node sends to dst, skipping all others
+        fn recv(&mut self, dst: &str) -> Message<Value> {
+            // gossip keeps coming, the deadline is for the whole wait
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let message = self
+                    .stdout
+                    .recv_timeout(
+                        deadline.saturating_duration_since(Instant::now()),
+                    )
+                    .expect("node did not answer");
+                if message.dst == dst {
+                    return message;
+                }
+            }
+        }
+
+        fn request(&mut self, src: &str, payload: Value) -> Value {
+            let id = self.send(src, payload);
+            loop {
+                let message = self.recv(src);
+                if message.body.in_reply_to == Some(id) {
+                    return message.body.payload;
+                }
+            }
+        }
+
+        fn txn(&mut self, txn: Value) -> Value {
+            let reply = self.request("c1", json!({"type": "txn", "txn": txn}));
+            assert_eq!(reply["type"], "txn_ok");
+            reply["txn"].clone()
+        }
+    }
+
+    impl Drop for TxnProcess {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    #[test]
+    fn test_txn_reads_own_writes() {
+        let mut node = TxnProcess::start(&["n1"]);
+
+        let txn = node.txn(json!([
+            ["r", 1, null],
+            ["w", 1, 5],
+            ["r", 1, null],
+            ["w", 1, 6],
+            ["w", 2, 7]
+        ]));
+        assert_eq!(
+            txn,
+            json!([
+                ["r", 1, null],
+                ["w", 1, 5],
+                ["r", 1, 5],
+                ["w", 1, 6],
+                ["w", 2, 7]
+            ])
+        );
+
+        let txn =
+            node.txn(json!([["r", 1, null], ["r", 2, null], ["r", 3, null]]));
+        assert_eq!(txn, json!([["r", 1, 6], ["r", 2, 7], ["r", 3, null]]));
+    }
+
+    #[test]
+    fn test_commits_are_gossiped_until_acknowledged() {
+        let mut node = TxnProcess::start(&["n1", "n2"]);
+        node.txn(json!([["w", 1, 5], ["w", 1, 6]]));
+
+        // only the committed value is replicated, never the one the transaction overwrote
+        let gossip = node.recv("n2").body.payload;
+        assert_eq!(gossip["type"], "gossip");
+        let commits = gossip["commits"].as_array().unwrap();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0]["writes"], json!([[1, 6]]));
+
+        // n2 did not acknowledge, as if partitioned away, so the commit comes again
+        let again = node.recv("n2").body.payload;
+        assert_eq!(again["commits"], gossip["commits"]);
+
+        let id = json!([commits[0]["node"], commits[0]["seq"]]);
+        node.send("n2", json!({"type": "gossip_ok", "ids": [id]}));
+        node.txn(json!([["w", 2, 1]]));
+
+        // a gossip sent before the acknowledgement got in may still be on its way
+        let gossip = loop {
+            let gossip = node.recv("n2").body.payload;
+            if gossip["commits"][0]["writes"] != json!([[1, 6]]) {
+                break gossip;
+            }
+        };
+        assert_eq!(gossip["commits"].as_array().unwrap().len(), 1);
+        assert_eq!(gossip["commits"][0]["writes"], json!([[2, 1]]));
+    }
+
+    #[test]
+    fn test_gossiped_commits_apply_newest_version() {
+        let mut node = TxnProcess::start(&["n1", "n2"]);
+        node.txn(json!([["w", 1, 5]]));
+
+        let commit = |seq: usize, clock: usize, value: usize| json!({"node": "n2", "seq": seq, "clock": clock, "writes": [[1, value], [2, value]]});
+        let reply = node.request(
+            "n2",
+            json!({"type": "gossip", "commits": [commit(1, 3, 10), commit(2, 2, 20)]}),
+        );
+        assert_eq!(reply["type"], "gossip_ok");
+        assert_eq!(reply["ids"], json!([["n2", 1], ["n2", 2]]));
+
+        // the commit with the later clock wins, whatever the order it came in
+        let txn = node.txn(json!([["r", 1, null], ["r", 2, null]]));
+        assert_eq!(txn, json!([["r", 1, 10], ["r", 2, 10]]));
+
+        // the clock moved past the gossiped commits, a new local write wins over them
+        node.txn(json!([["w", 1, 30]]));
+        let txn = node.txn(json!([["r", 1, null]]));
+        assert_eq!(txn, json!([["r", 1, 30]]));
+    }
+}
@@ -0,0 +1,25 @@
+#![no_main]
+
+/*
+Fuzz target for the block decoder, run it from tutorial-3 with cargo-fuzz (needs a nightly toolchain):
+
+    cargo +nightly fuzz run decode_block
+
+Blocks arrive from peers and from disk, so the decoder is the first code to see bytes nobody checked.
+Whatever the input, decoding must not panic, and a block that decodes must encode back to the same bytes.
+A decoded block then goes through the consensus checks of an empty chain, which must not panic either.
+The properties in tests/consensus_test.rs run the same checks on every `cargo test`.
+*/
+use blockchain::{
+    block::Block,
+    blockchain::Blockchain,
+    codec::{Decode, Encode},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(block) = Block::decode(data) {
+        assert_eq!(block.encode(), data);
+        let _ = Blockchain::new().verify_block(&block);
+    }
+});
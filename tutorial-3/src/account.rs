@@ -0,0 +1,144 @@
+/*
+Definition of the account-based ledger.
+
+The notes in transactions.rs describe how Bitcoin moves coins: every transaction spends
+whole outputs of earlier transactions and creates new ones, and a balance is the sum of the unspent outputs
+paying an address (the UTXO model). Ethereum keeps a state instead, a map from address to account:
+
+    address -> Account { balance, nonce }
+
+and a transfer simply takes value from the sender's balance and adds it to the recipients' balances.
+A chain picks one of the two models in its parameters (see LedgerMode in blockchain.rs).
+
+The account model does not need a new kind of transaction, the same Transaction is read differently:
+- it has a single input, the sender. Its prev_out does not point to an output,
+  it holds the sender's address (as bytes) and the sender's nonce instead, see Input::account
+- the input's value is what the sender pays in total: the outputs plus the fee for the miner
+- the input's public key must hash to the sender's address, and its signature must be valid, same as UTXO
+- the outputs credit their value to the balance of their address
+- the coinbase credits the miner like any other output
+
+Without outputs to spend there is nothing stopping a valid signed transfer from being sent twice.
+That is what the nonce is for: it counts the transfers an account has made,
+a transfer must carry exactly the sender's current nonce, and every applied transfer increments it.
+So once a transfer is in the chain, the same signed bytes can never be valid again.
+
+Every block commits to the state after its transactions with a state root:
+the Merkle root (see merkle.rs) over one leaf per account, in address order,
+
+    leaf = H(address + balance + nonce)
+
+so two nodes agree on the root only when they agree on every account.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    blockchain::BlockValidationErr,
+    hashtable::HashAlgorithm,
+    merkle,
+    transactions::{Input, Transaction},
+    u32_bytes, u64_bytes, wallet, Address, Hash,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: u64,
+    // number of transfers sent from the account, the next transfer must carry this value
+    pub nonce: u32,
+}
+
+/*
+Function checks a transfer against the given state and returns its fee.
+An address never seen before is an account with nothing in it.
+*/
+pub(crate) fn check_transaction(
+    accounts: &HashMap<Address, Account>,
+    transaction: &Transaction,
+) -> Result<u64, BlockValidationErr> {
+    if transaction.is_coinbase() {
+        return Err(BlockValidationErr::UnexpectedCoinbase);
+    }
+    let [input] = transaction.inputs.as_slice() else {
+        return Err(BlockValidationErr::InvalidInput);
+    };
+
+    let sender = wallet::public_key_to_address(&input.public_key);
+    if input.prev_out.tx_hash != sender.as_bytes() {
+        return Err(BlockValidationErr::MismatchedPublicKey);
+    }
+    if !wallet::verify_signature(
+        &input.public_key,
+        &transaction.signing_bytes(),
+        &input.signature,
+    ) {
+        return Err(BlockValidationErr::InvalidSignature);
+    }
+
+    let account = accounts.get(&sender).copied().unwrap_or_default();
+    if input.prev_out.index != account.nonce {
+        return Err(BlockValidationErr::InvalidNonce);
+    }
+    if input.value > account.balance {
+        return Err(BlockValidationErr::InsufficientBalance);
+    }
+
+    transaction
+        .fee()
+        .ok_or(BlockValidationErr::InsufficientInputValue)
+}
+
+/*
+Function moves the transaction's value from its sender to its outputs and bumps the sender's nonce,
+the coinbase only credits its outputs. The transaction is expected to be checked already,
+but the arithmetic saturates anyway, so an audit can replay invalid transactions as well.
+Returns the addresses whose account changed.
+*/
+pub(crate) fn apply_transaction(
+    accounts: &mut HashMap<Address, Account>,
+    transaction: &Transaction,
+) -> Vec<Address> {
+    let mut touched = vec![];
+    if !transaction.is_coinbase() {
+        for input in &transaction.inputs {
+            let sender = sender_address(input);
+            let account = accounts.entry(sender.clone()).or_default();
+            account.balance = account.balance.saturating_sub(input.value);
+            account.nonce = account.nonce.saturating_add(1);
+            touched.push(sender);
+        }
+    }
+
+    for output in &transaction.outputs {
+        let account = accounts.entry(output.to_addr.clone()).or_default();
+        account.balance = account.balance.saturating_add(output.value);
+        touched.push(output.to_addr.clone());
+    }
+    touched
+}
+
+// the address an account input spends from, see Input::account
+pub(crate) fn sender_address(input: &Input) -> Address {
+    String::from_utf8_lossy(&input.prev_out.tx_hash).into_owned()
+}
+
+// the Merkle root over every account of the state, filled with zeros for an empty state
+pub fn state_root(
+    accounts: &HashMap<Address, Account>,
+    algorithm: HashAlgorithm,
+) -> Hash {
+    let mut addresses: Vec<&Address> = accounts.keys().collect();
+    addresses.sort();
+
+    let leaves: Vec<Hash> = addresses
+        .into_iter()
+        .map(|address| {
+            let account = &accounts[address];
+            let mut bytes = address.as_bytes().to_vec();
+            bytes.extend(&u64_bytes(&account.balance));
+            bytes.extend(&u32_bytes(&account.nonce));
+            algorithm.digest(&bytes)
+        })
+        .collect();
+    merkle::merkle_root_with(&leaves, algorithm)
+}
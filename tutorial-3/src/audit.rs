@@ -0,0 +1,408 @@
+/*
+Definition of the chain audit.
+
+update_with_block checks a block once, when it arrives. That says nothing about the blocks
+afterwards: `Blockchain::blocks` is a public field, and blocks loaded from disk or sent by a peer
+may not be what was checked in the first place. An audit walks the active chain again from the
+genesis block and re-checks everything from scratch, without trusting anything the chain has stored:
+
+- hash linkage: every block sits at its own index, uses the chain's hash algorithm,
+  its stored hash is the hash of its contents,
+  and it points to the stored hash of the block before it
+- proof of work: every hash meets its difficulty, and every difficulty follows the retarget rule
+- timestamps: every block is later than the block before it
+- merkle roots: every merkle root is the root of the block's transactions
+- ledger consistency: the UTXO set is re-built by replaying every transaction, every input must spend
+  an output of the replayed set, and in the end the replayed set must be the one the chain keeps.
+  On an account chain (see account.rs) the accounts are replayed instead, every transfer must carry
+  the sender's nonce and fit its balance, and every block's state root must match the replayed accounts
+- supply: no coinbase pays more than the subsidy plus the fees of its block,
+  and the coins in circulation never exceed what the issuance schedule has minted so far
+
+Unlike update_with_block, the audit does not stop at the first problem.
+Every check goes on to the end of the chain, and the report names the first block
+(and transaction, where there is one) that failed each check.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    account::{self, Account},
+    block::{check_difficulty, Block},
+    blockchain::{
+        check_transaction, collect_outputs, BlockValidationErr, Blockchain,
+        LedgerMode,
+    },
+    hashtable::Hashtable,
+    transactions::{OutPoint, Output},
+    Address, Hash,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditCheck {
+    HashLinkage,
+    ProofOfWork,
+    TimestampOrder,
+    MerkleRoot,
+    LedgerConsistency,
+    Supply,
+}
+
+// the first problem one check found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditFinding {
+    pub check: AuditCheck,
+    // position of the offending block in the active chain
+    pub block_index: u32,
+    // the offending transaction, None when the block as a whole is at fault
+    pub tx_hash: Option<Hash>,
+    pub reason: String,
+}
+
+// the ledger the audit re-builds on its own, only the one of the chain's LedgerMode is filled
+#[derive(Default)]
+struct ReplayedLedger {
+    unspent_outputs: HashMap<OutPoint, Output>,
+    accounts: HashMap<Address, Account>,
+}
+
+impl ReplayedLedger {
+    fn supply(&self) -> u64 {
+        let outputs = self.unspent_outputs.values().map(|output| output.value);
+        let balances = self.accounts.values().map(|account| account.balance);
+        outputs
+            .chain(balances)
+            .fold(0, |total, value| total.saturating_add(value))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    pub blocks_checked: usize,
+    pub transactions_checked: usize,
+    // coins held by the replayed ledger at the tip
+    pub total_supply: u64,
+    // coins the issuance schedule has minted up to the tip, total_supply is at most this
+    // (lower when fees were left unclaimed, or when coins went to GENESIS_ADDRESS)
+    pub max_supply: u64,
+    // at most one finding per check, in the order they were found
+    pub findings: Vec<AuditFinding>,
+}
+
+impl AuditReport {
+    // whether every check passed
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn finding(&self, check: AuditCheck) -> Option<&AuditFinding> {
+        self.findings.iter().find(|finding| finding.check == check)
+    }
+
+    // only the first problem of each check is kept, the later ones are usually its consequences
+    fn record(
+        &mut self,
+        check: AuditCheck,
+        block_index: u32,
+        tx_hash: Option<Hash>,
+        reason: impl ToString,
+    ) {
+        if self.finding(check).is_none() {
+            self.findings.push(AuditFinding {
+                check,
+                block_index,
+                tx_hash,
+                reason: reason.to_string(),
+            });
+        }
+    }
+}
+
+impl Blockchain {
+    /*
+    Function re-checks the whole active chain and reports what is wrong with it,
+    an empty chain gives a clean report.
+    */
+    pub fn audit(&self) -> AuditReport {
+        let mut report = AuditReport::default();
+        let mut ledger = ReplayedLedger::default();
+
+        for (height, block) in self.blocks.iter().enumerate() {
+            let height = height as u32;
+            let prev = height
+                .checked_sub(1)
+                .map(|prev| &self.blocks[prev as usize]);
+
+            self.audit_header(&mut report, height, block, prev);
+            self.audit_transactions(&mut report, height, block, &mut ledger);
+
+            report.blocks_checked += 1;
+            report.transactions_checked += block.transactions.len();
+            report.max_supply = report
+                .max_supply
+                .saturating_add(self.params.block_subsidy(height));
+            report.total_supply = ledger.supply();
+            if report.total_supply > report.max_supply {
+                report.record(
+                    AuditCheck::Supply,
+                    height,
+                    None,
+                    format!(
+                        "{} coins in circulation, the schedule allows {}",
+                        report.total_supply, report.max_supply
+                    ),
+                );
+            }
+        }
+
+        let tip = self.blocks.len().saturating_sub(1) as u32;
+        if &ledger.unspent_outputs != self.unspent_outputs() {
+            report.record(
+                AuditCheck::LedgerConsistency,
+                tip,
+                None,
+                "the chain's UTXO set differs from the replayed one",
+            );
+        }
+        if &ledger.accounts != self.accounts() {
+            report.record(
+                AuditCheck::LedgerConsistency,
+                tip,
+                None,
+                "the chain's accounts differ from the replayed ones",
+            );
+        }
+
+        report
+    }
+
+    fn audit_header(
+        &self,
+        report: &mut AuditReport,
+        height: u32,
+        block: &Block,
+        prev: Option<&Block>,
+    ) {
+        let hash = block.hash();
+        let prev_hash = prev.map_or(vec![0; 32], |prev| prev.hash.clone());
+        if block.index != height {
+            report.record(
+                AuditCheck::HashLinkage,
+                height,
+                None,
+                BlockValidationErr::MismatchedIndex,
+            );
+        } else if block.hash_algorithm != self.params.hash_algorithm {
+            report.record(
+                AuditCheck::HashLinkage,
+                height,
+                None,
+                BlockValidationErr::MismatchedHashAlgorithm,
+            );
+        } else if block.hash != hash {
+            report.record(
+                AuditCheck::HashLinkage,
+                height,
+                None,
+                BlockValidationErr::InvalidHash,
+            );
+        } else if block.prev_block_hash != prev_hash {
+            report.record(
+                AuditCheck::HashLinkage,
+                height,
+                None,
+                BlockValidationErr::MismatchedPreviousHash,
+            );
+        }
+
+        // the re-calculated hash, the stored one may have been replaced by one that meets the target
+        if !check_difficulty(&hash, block.difficulty) {
+            report.record(
+                AuditCheck::ProofOfWork,
+                height,
+                None,
+                BlockValidationErr::DifficultyNotMet,
+            );
+        } else if block.difficulty != self.expected_difficulty(height) {
+            report.record(
+                AuditCheck::ProofOfWork,
+                height,
+                None,
+                BlockValidationErr::MismatchedDifficulty,
+            );
+        }
+
+        if prev.is_some_and(|prev| block.timestamp <= prev.timestamp) {
+            report.record(
+                AuditCheck::TimestampOrder,
+                height,
+                None,
+                BlockValidationErr::AchronologicalTimestamp,
+            );
+        }
+
+        if block.merkle_root != block.compute_merkle_root() {
+            report.record(
+                AuditCheck::MerkleRoot,
+                height,
+                None,
+                BlockValidationErr::InvalidMerkleRoot,
+            );
+        }
+
+        if block.has_duplicate_transactions() {
+            report.record(
+                AuditCheck::MerkleRoot,
+                height,
+                None,
+                BlockValidationErr::DuplicateTransaction,
+            );
+        }
+    }
+
+    /*
+    Function checks the block's transactions against the replayed ledger,
+    the same way verify_transactions does, then applies them to the ledger.
+    Invalid transactions are applied as well (whatever of them can be),
+    so that one bad transaction does not make every later block look bad too.
+    */
+    fn audit_transactions(
+        &self,
+        report: &mut AuditReport,
+        height: u32,
+        block: &Block,
+        ledger: &mut ReplayedLedger,
+    ) {
+        let mut block_spent: HashSet<OutPoint> = HashSet::new();
+        let mut block_created: HashMap<OutPoint, Output> = HashMap::new();
+        let mut total_fee: u64 = 0;
+
+        let coinbase = block
+            .transactions
+            .first()
+            .filter(|transaction| transaction.is_coinbase());
+        match coinbase {
+            Some(coinbase) if coinbase.coinbase_height() != Some(height) => {
+                report.record(
+                    AuditCheck::Supply,
+                    height,
+                    Some(coinbase.hash()),
+                    BlockValidationErr::MismatchedCoinbaseHeight,
+                );
+            }
+            Some(_) => {}
+            None => report.record(
+                AuditCheck::Supply,
+                height,
+                None,
+                BlockValidationErr::MissingCoinbase,
+            ),
+        }
+
+        let skip = coinbase.map_or(0, |_| 1);
+        for transaction in &block.transactions[skip..] {
+            let checked = match self.params.ledger {
+                LedgerMode::Utxo => {
+                    let input_outpoints = transaction.input_outpoints();
+                    let checked =
+                        check_transaction(&ledger.unspent_outputs, transaction)
+                            .and_then(|fee| {
+                                if input_outpoints.is_disjoint(&block_spent) {
+                                    Ok(fee)
+                                } else {
+                                    Err(BlockValidationErr::DoubleSpend)
+                                }
+                            });
+                    block_spent.extend(input_outpoints);
+                    collect_outputs(transaction, &mut block_created);
+                    checked
+                }
+                // transfers see the accounts left by the transfers before them
+                LedgerMode::Account => {
+                    let checked = account::check_transaction(
+                        &ledger.accounts,
+                        transaction,
+                    );
+                    account::apply_transaction(
+                        &mut ledger.accounts,
+                        transaction,
+                    );
+                    checked
+                }
+            };
+            match checked {
+                Ok(fee) => total_fee = total_fee.saturating_add(fee),
+                Err(err) => report.record(
+                    AuditCheck::LedgerConsistency,
+                    height,
+                    Some(transaction.hash()),
+                    err,
+                ),
+            }
+        }
+
+        if let Some(coinbase) = coinbase {
+            let max_reward =
+                self.params.block_subsidy(height).saturating_add(total_fee);
+            if coinbase.output_value() > max_reward {
+                report.record(
+                    AuditCheck::Supply,
+                    height,
+                    Some(coinbase.hash()),
+                    BlockValidationErr::InvalidCoinbaseValue,
+                );
+            }
+            match self.params.ledger {
+                LedgerMode::Utxo => {
+                    collect_outputs(coinbase, &mut block_created)
+                }
+                LedgerMode::Account => {
+                    account::apply_transaction(&mut ledger.accounts, coinbase);
+                }
+            }
+        }
+
+        for out_point in &block_spent {
+            ledger.unspent_outputs.remove(out_point);
+        }
+        ledger.unspent_outputs.extend(block_created);
+
+        let state_root = match self.params.ledger {
+            LedgerMode::Utxo => vec![0; 32],
+            LedgerMode::Account => account::state_root(
+                &ledger.accounts,
+                self.params.hash_algorithm,
+            ),
+        };
+        if block.state_root != state_root {
+            report.record(
+                AuditCheck::LedgerConsistency,
+                height,
+                None,
+                BlockValidationErr::InvalidStateRoot,
+            );
+        }
+    }
+
+    /*
+    Function returns the difficulty the retarget rule expects at the given height of the active chain.
+    This is the rule of Blockchain::difficulty_after, but computed from the audited blocks
+    instead of the block tree, which holds the blocks as they were when they were accepted.
+    */
+    fn expected_difficulty(&self, height: u32) -> u128 {
+        let height = height as usize;
+        if height == 0 {
+            return self.params.initial_difficulty;
+        }
+
+        let last = &self.blocks[height - 1];
+        let interval = self.params.retarget_interval.max(2) as usize;
+        if !height.is_multiple_of(interval) {
+            return last.difficulty;
+        }
+
+        let first = &self.blocks[height - interval];
+        let timespan = last.timestamp.saturating_sub(first.timestamp);
+        self.params.retarget(last.difficulty, timespan)
+    }
+}
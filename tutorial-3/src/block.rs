@@ -1,23 +1,58 @@
 /*
 Definition of the Blocks.
+
+A block is its header plus its transactions. The header is everything but the transactions,
+it commits to them through the Merkle root (see merkle.rs), so the header alone is enough
+to check a block's hash and proof of work. That is what a light client keeps (see spv.rs).
 */
 
+use std::collections::HashSet;
 use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    hashtable::Hashtable,
+    difficulty_bytes_as_u128,
+    hashtable::{HashAlgorithm, Hashtable},
+    merkle::{self, MerkleProof},
     transactions::{self, Transaction},
     u128_bytes, u32_bytes, u64_bytes, Hash,
 };
 
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Block {
     pub index: u32,
     pub timestamp: u128,
     pub hash: Hash,
     pub prev_block_hash: Hash,
+    // root of the Merkle tree over the transaction hashes, see merkle.rs
+    pub merkle_root: Hash,
+    // root of the account state after the block, filled with zeros on a UTXO chain, see account.rs
+    pub state_root: Hash,
     pub transactions: Vec<Transaction>,
     pub nonce: u64,
     pub difficulty: u128,
+    // digest behind the block hash and the Merkle tree, see hashtable.rs
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/*
+The header of a block: the same fields as Block, without the transactions.
+It produces the same bytes as its block (see Hashtable for Block below), so it hashes to the block's hash.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub index: u32,
+    pub timestamp: u128,
+    pub hash: Hash,
+    pub prev_block_hash: Hash,
+    pub merkle_root: Hash,
+    pub state_root: Hash,
+    pub nonce: u64,
+    pub difficulty: u128,
+    pub hash_algorithm: HashAlgorithm,
 }
 
 /*
@@ -28,13 +63,14 @@ impl Debug for Block {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(
             f,
-            "Block[{}]: {} at: {}, trans cnt: {}, nonce: {}, difficulty: {}",
+            "Block[{}]: {} at: {}, trans cnt: {}, nonce: {}, difficulty: {}, hash: {:?}",
             &self.index,
             hex::encode(&self.hash),
             &self.timestamp,
             &self.transactions.len(),
             &self.nonce,
-            &self.difficulty
+            &self.difficulty,
+            &self.hash_algorithm
         )
     }
 }
@@ -58,6 +94,16 @@ impl Block {
         nonce: u64,
         difficulty: u128,
     ) -> Self {
+        // the root has to be calculated before the transactions are moved into the struct
+        let hash_algorithm = HashAlgorithm::default();
+        let merkle_root = merkle::merkle_root_with(
+            &transactions
+                .iter()
+                .map(|item| item.hash())
+                .collect::<Vec<Hash>>(),
+            hash_algorithm,
+        );
+
         // here we hand the received parameters to struct
         Block {
             index,
@@ -65,27 +111,135 @@ impl Block {
             // Block's init hash value is an empty array with type u8 and with length 32
             hash: vec![0; 32],
             prev_block_hash,
+            merkle_root,
+            state_root: vec![0; 32],
             transactions,
             nonce,
             difficulty,
+            hash_algorithm,
         }
     }
 
     /*
-    Function mine is trying to mimic the process of mining a block-coin from the blockchain
+    Function switches a freshly created block to another hash algorithm,
+    the Merkle root is built again with that algorithm.
+    It has to happen before mining, the hash found by mining is only valid for one algorithm.
+    */
+    pub fn with_hash_algorithm(
+        mut self,
+        hash_algorithm: HashAlgorithm,
+    ) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self.merkle_root = self.compute_merkle_root();
+        self
+    }
+
+    /*
+    Function mine is trying to mimic the process of mining a block-coin from the blockchain:
+    keep trying nonce values until the block's hash satisfies its own difficulty.
+    Once a nonce is found, both nonce and hash are stored in the block.
     */
-    pub fn mine(&mut self) {
-        todo!("add more details after we finish the transaction logic in Block")
+    pub fn mine(&mut self) -> bool {
+        self.mine_range(0..u64::MAX, None)
     }
 
-    pub fn trans_hash(&mut self) -> Vec<u8> {
+    /*
+    Function mine_range only tries the nonces inside the given range,
+    so the whole nonce space can be split into disjoint ranges and mined by multiple threads.
+    The optional cancel flag is checked before every attempt, once another thread
+    finds a solution it can set the flag to stop the others.
+    Returns true only when a valid nonce is found, the block's hash stays untouched otherwise.
+    */
+    pub fn mine_range(
+        &mut self,
+        nonces: Range<u64>,
+        cancel: Option<&AtomicBool>,
+    ) -> bool {
+        for nonce_attempt in nonces {
+            if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                return false;
+            }
+
+            self.nonce = nonce_attempt;
+            let hash = self.hash();
+            if check_difficulty(&hash, self.difficulty) {
+                self.hash = hash;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            hash: self.hash.clone(),
+            prev_block_hash: self.prev_block_hash.clone(),
+            merkle_root: self.merkle_root.clone(),
+            state_root: self.state_root.clone(),
+            nonce: self.nonce,
+            difficulty: self.difficulty,
+            hash_algorithm: self.hash_algorithm,
+        }
+    }
+
+    pub fn transaction_hashes(&self) -> Vec<Hash> {
         self.transactions
             .iter()
-            .flat_map(|item| item.hash())
-            .collect::<Vec<u8>>()
+            .map(|item| item.hash())
+            .collect::<Vec<Hash>>()
+    }
+
+    // whether two of the block's transactions have the same hash, see Blockchain::check_header
+    pub fn has_duplicate_transactions(&self) -> bool {
+        let hashes = self.transaction_hashes();
+        let unique: HashSet<&Hash> = hashes.iter().collect();
+        unique.len() != hashes.len()
+    }
+
+    /*
+    Function re-builds the Merkle root from the block's current transactions,
+    a block is only consistent when this equals the merkle_root stored in its header.
+    */
+    pub fn compute_merkle_root(&self) -> Hash {
+        merkle::merkle_root_with(
+            &self.transaction_hashes(),
+            self.hash_algorithm,
+        )
+    }
+
+    // inclusion proof of the transaction at the given position of the block
+    pub fn merkle_proof(
+        &self,
+        transaction_index: usize,
+    ) -> Option<MerkleProof> {
+        merkle::merkle_proof_with(
+            &self.transaction_hashes(),
+            transaction_index,
+            self.hash_algorithm,
+        )
     }
 }
 
+/*
+A hash satisfies the difficulty only when its value (see difficulty_bytes_as_u128 in lib.rs)
+is strictly smaller than the difficulty target, so a smaller difficulty means more mining work.
+*/
+pub fn check_difficulty(hash: &Hash, difficulty: u128) -> bool {
+    difficulty > difficulty_bytes_as_u128(hash)
+}
+
+/*
+Function estimates how many hashes it takes on average to mine a block with the given difficulty.
+A hash meets the difficulty with a chance of difficulty / 2^128, so it takes about 2^128 / difficulty tries.
+The chain with the most accumulated work wins, not the one with the most blocks.
+*/
+pub fn block_work(difficulty: u128) -> u128 {
+    u128::MAX / difficulty.max(1)
+}
+
 /*
 Let Block implement trait Hashtable and provide the implementation details of the function bytes.
 */
@@ -121,23 +275,50 @@ impl Hashtable for Block {
         // fourth, continue append Block's nonce value to the bytes array
         bytes.extend(&u64_bytes(&self.nonce));
 
-        // fifth, iterate current block's transaction vector
-        // and extract each item's bytes append to the bytes
-        // (and this bytes will feed hash function as to be hashed data)
-        bytes.extend(
-            self.transactions
-                .iter()
-                .flat_map(|item| item.bytes())
-                .collect::<Vec<u8>>(),
-        );
+        // fifth, instead of every transaction's bytes we only append the Merkle root,
+        // it already commits to all the transactions of the block, and keeps the hashed
+        // header at a fixed size no matter how many transactions the block carries
+        bytes.extend(&self.merkle_root);
+
+        // the state root gets the same treatment, the hash commits to the whole state through it
+        bytes.extend(&self.state_root);
 
         // last, we append the inner difficulty
-        // todo: [Can seder help us ???] and I need to leave a question here: convert all the items into bytes is the process of serialize
-        // can use use the seder library to help us finish this step ? instead of convert all items in Block by our own ?
+        // note: these bytes only feed the hash function and cannot be decoded again,
+        // storing or sending a block goes through the versioned format in codec.rs (or serde for JSON)
         bytes.extend(&u128_bytes(&self.difficulty));
 
+        // the algorithm is part of the hashed header too, so it cannot be swapped after mining
+        bytes.push(self.hash_algorithm as u8);
+
         // finally, we return our bytes array and hand over the data to crypto which already
         // implemented in the function `hash`
         bytes
     }
+
+    // a block is hashed with the algorithm it records, instead of the default SHA-256
+    fn hash(&self) -> Vec<u8> {
+        self.hash_with(self.hash_algorithm)
+    }
+}
+
+// the header repeats the bytes of Block::bytes in the same order, see the comments there
+impl Hashtable for BlockHeader {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(&u32_bytes(&self.index));
+        bytes.extend(&u128_bytes(&self.timestamp));
+        bytes.extend(&self.prev_block_hash);
+        bytes.extend(&u64_bytes(&self.nonce));
+        bytes.extend(&self.merkle_root);
+        bytes.extend(&self.state_root);
+        bytes.extend(&u128_bytes(&self.difficulty));
+        bytes.push(self.hash_algorithm as u8);
+
+        bytes
+    }
+
+    fn hash(&self) -> Vec<u8> {
+        self.hash_with(self.hash_algorithm)
+    }
 }
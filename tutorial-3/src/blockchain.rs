@@ -1 +1,1183 @@
+/*
+Definition of the Blockchain.
 
+A blockchain is nothing more than an ordered list of blocks, where every block
+points back to the hash of the block right before it. The very first block is
+called the genesis block, and since there is nothing before it, its
+prev_block_hash is filled with zeros.
+
+Before a block is added to the chain, we verify it against its parent block:
+- its prev_block_hash must be the hash of a block we already have
+- its index must be the next height after its parent
+- its timestamp must be later than the parent's timestamp
+- it must use the chain's hash algorithm (see hashtable.rs)
+- its stored hash must be the hash we re-calculate from its contents
+- its merkle_root must be the root of its transactions (the hash only covers the root)
+- its hash must satisfy the difficulty target it claims
+- the difficulty it claims must be the one the chain expects at its height (see below)
+
+Besides the blocks, the chain also keeps track of who owns which coins, the ledger.
+By default it is the set of unspent transaction outputs (UTXO).
+An output is created by a transaction and stays unspent until another transaction uses it as an input,
+so every input of a regular (non-coinbase) transaction must be found in this set:
+- spending an output that is not in the set means it never existed or was already spent
+- spending the same output twice inside one block is a double-spend
+- the input's public key must hash to the address of the spent output,
+  and its signature must be valid for the transaction's signing bytes
+- the inputs must provide at least the value of the outputs, the rest is the miner's fee
+
+A chain created with `LedgerMode::Account` keeps a map from address to balance and nonce instead,
+its transfers and its per block state root are described in account.rs.
+Both ledgers share everything else, so the same workload can run against either of them.
+
+And every block must start with exactly one coinbase transaction, which is how new coins enter the system.
+The coinbase may pay the miner at most the block subsidy plus the fees of the block's other transactions.
+Like Bitcoin, the subsidy is cut in half every `halving_interval` blocks (see ChainParams),
+so the total supply approaches a fixed limit.
+
+The difficulty is not chosen by the miner either. The genesis block uses `initial_difficulty`,
+and every `retarget_interval` blocks the target is adjusted by how long the last window of blocks took
+compared with `target_block_time`: blocks coming too fast lower the target (harder),
+blocks coming too slow raise it (easier). Every other block keeps the difficulty of its predecessor.
+
+Two miners can find a block on top of the same parent at about the same time,
+so the chain is really a tree of blocks, and each branch of it is a candidate chain:
+
+    genesis - 1 - 2 - 3a - 4a      <- active chain, the branch with the most work
+                    \
+                     3b            <- side branch
+
+Like Bitcoin, the active chain is the branch with the most accumulated work (see block_work in block.rs),
+not the longest one (on a tie, the branch whose tip arrived first). A block on a side branch is only stored, its transactions cannot be checked yet
+because the ledger belongs to the active chain. Once a side branch collects more work than the active chain,
+we reorganize: the active blocks down to the fork point are disconnected (their ledger changes are undone),
+and the blocks of the heavier branch are connected (and fully verified) one by one.
+If one of them turns out to be invalid, it is dropped together with its descendants,
+and the chain goes back to the heaviest branch left.
+Every change of the active chain is announced to the subscribers as a ReorgEvent.
+*/
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{
+    account::{self, Account},
+    block::{block_work, check_difficulty, Block},
+    hashtable::{HashAlgorithm, Hashtable},
+    merkle::MerkleProof,
+    transactions::{OutPoint, Output, Transaction},
+    wallet, Address, Hash,
+};
+
+/*
+Each variant names the check that rejected the block,
+so callers can tell why a block was refused instead of getting a plain `false`.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockValidationErr {
+    // block.index is not the next height of the chain
+    MismatchedIndex,
+    // block.hash is not the hash calculated from the block's contents
+    InvalidHash,
+    // block.hash does not satisfy block.difficulty
+    DifficultyNotMet,
+    // block.merkle_root is not the root of the block's transactions
+    InvalidMerkleRoot,
+    // the same transaction shows up twice in the block
+    DuplicateTransaction,
+    // block.timestamp is not later than the previous block's timestamp
+    AchronologicalTimestamp,
+    // block.prev_block_hash does not point to the previous block
+    MismatchedPreviousHash,
+    // genesis block's prev_block_hash is not filled with zeros
+    InvalidGenesisBlockFormat,
+    // a transaction spends an output that is not in the UTXO set,
+    // or claims a different value than the spent output holds
+    InvalidInput,
+    // an input's public key does not hash to the spent output's address
+    MismatchedPublicKey,
+    // an input's signature does not match the transaction's signing bytes
+    InvalidSignature,
+    // the same output is spent more than once inside the block
+    DoubleSpend,
+    // a transaction's outputs are worth more than its inputs
+    InsufficientInputValue,
+    // the block has no transactions, or its first transaction is not a coinbase
+    MissingCoinbase,
+    // a coinbase transaction shows up after the first transaction of the block
+    UnexpectedCoinbase,
+    // the coinbase pays more than the block subsidy plus the block's fees
+    InvalidCoinbaseValue,
+    // the height in the coinbase input is not the block's index
+    MismatchedCoinbaseHeight,
+    // block.difficulty is not the one the retarget rule expects at this height
+    MismatchedDifficulty,
+    // the block is already in the block tree
+    DuplicateBlock,
+    // block.hash_algorithm is not the one of the chain
+    MismatchedHashAlgorithm,
+    // an account transfer does not carry the sender's current nonce
+    InvalidNonce,
+    // an account transfer pays more than the sender's balance
+    InsufficientBalance,
+    // block.state_root is not the root of the accounts after the block,
+    // or not filled with zeros on a UTXO chain
+    InvalidStateRoot,
+}
+
+impl Display for BlockValidationErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let msg = match self {
+            BlockValidationErr::MismatchedIndex => "mismatched block index",
+            BlockValidationErr::InvalidHash => {
+                "block hash does not match its contents"
+            }
+            BlockValidationErr::DifficultyNotMet => {
+                "block hash does not meet the difficulty"
+            }
+            BlockValidationErr::InvalidMerkleRoot => {
+                "merkle root does not match the block transactions"
+            }
+            BlockValidationErr::DuplicateTransaction => {
+                "block lists the same transaction twice"
+            }
+            BlockValidationErr::AchronologicalTimestamp => {
+                "block timestamp is not after the previous block"
+            }
+            BlockValidationErr::MismatchedPreviousHash => {
+                "block does not point to the previous block hash"
+            }
+            BlockValidationErr::InvalidGenesisBlockFormat => {
+                "genesis block prev_block_hash should be all zeros"
+            }
+            BlockValidationErr::InvalidInput => {
+                "transaction spends an output that is not unspent"
+            }
+            BlockValidationErr::MismatchedPublicKey => {
+                "input public key does not match the spent output address"
+            }
+            BlockValidationErr::InvalidSignature => {
+                "input signature is not valid for the transaction"
+            }
+            BlockValidationErr::DoubleSpend => {
+                "transaction output is spent more than once"
+            }
+            BlockValidationErr::InsufficientInputValue => {
+                "transaction outputs exceed its inputs"
+            }
+            BlockValidationErr::MissingCoinbase => {
+                "first transaction of the block should be a coinbase"
+            }
+            BlockValidationErr::UnexpectedCoinbase => {
+                "only the first transaction of the block may be a coinbase"
+            }
+            BlockValidationErr::InvalidCoinbaseValue => {
+                "coinbase pays more than the block subsidy plus fees"
+            }
+            BlockValidationErr::MismatchedCoinbaseHeight => {
+                "coinbase height does not match the block index"
+            }
+            BlockValidationErr::MismatchedDifficulty => {
+                "block difficulty does not match the expected difficulty"
+            }
+            BlockValidationErr::DuplicateBlock => "block is already known",
+            BlockValidationErr::MismatchedHashAlgorithm => {
+                "block hash algorithm does not match the chain"
+            }
+            BlockValidationErr::InvalidNonce => {
+                "transfer nonce does not match the sender account"
+            }
+            BlockValidationErr::InsufficientBalance => {
+                "transfer exceeds the sender balance"
+            }
+            BlockValidationErr::InvalidStateRoot => {
+                "state root does not match the accounts after the block"
+            }
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for BlockValidationErr {}
+
+// an address no public key hashes to in practice, the genesis coins are lost for good
+pub const GENESIS_ADDRESS: &str = "0000000000000000000000000000000000000000";
+
+// how a chain records who owns which coins
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LedgerMode {
+    // coins are unspent outputs, spent whole by the inputs of later transactions
+    #[default]
+    Utxo,
+    // coins are account balances, transfers are ordered by the sender's nonce (see account.rs)
+    Account,
+}
+
+/*
+Consensus parameters shared by every block of a chain.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    // coins minted by the coinbase of the genesis block
+    pub initial_subsidy: u64,
+    // number of blocks between two halvings of the subsidy
+    pub halving_interval: u32,
+    // difficulty of the genesis block, also the easiest difficulty a retarget may reach
+    pub initial_difficulty: u128,
+    // number of blocks between two difficulty adjustments
+    pub retarget_interval: u32,
+    // expected time between two blocks in milliseconds, the same unit as now()
+    pub target_block_time: u128,
+    // a single retarget moves the difficulty by at most this factor, in either direction
+    pub max_retarget_factor: u128,
+    // digest of the block hashes and Merkle trees, every block must record this one
+    pub hash_algorithm: HashAlgorithm,
+    pub ledger: LedgerMode,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        // same initial reward as Bitcoin, but the interval is much shorter than Bitcoin's 210,000
+        // so that simulations can see a few halvings happening,
+        // and the same goes for the retarget interval (Bitcoin: 2016 blocks of 10 minutes)
+        ChainParams {
+            initial_subsidy: 50,
+            halving_interval: 210,
+            initial_difficulty: u128::MAX >> 16,
+            retarget_interval: 10,
+            target_block_time: 10_000,
+            max_retarget_factor: 4,
+            hash_algorithm: HashAlgorithm::Sha256,
+            ledger: LedgerMode::Utxo,
+        }
+    }
+}
+
+impl ChainParams {
+    /*
+    Function returns the coins a coinbase may mint in the block at the given index.
+    Every halving_interval blocks the subsidy is shifted right by one bit (divided by two),
+    once it is shifted 64 times there is nothing left.
+    */
+    pub fn block_subsidy(&self, index: u32) -> u64 {
+        let halvings = index / self.halving_interval.max(1);
+        if halvings >= u64::BITS {
+            return 0;
+        }
+        self.initial_subsidy >> halvings
+    }
+
+    /*
+    Function returns the difficulty that follows a retarget window.
+    `timespan` is the time between the first and the last block of the window,
+    which covers retarget_interval - 1 block intervals.
+
+    new difficulty = difficulty * timespan / expected timespan
+
+    The timespan is clamped first, so one retarget can make mining at most
+    max_retarget_factor times harder or easier, no matter how skewed the timestamps are.
+    */
+    pub fn retarget(&self, difficulty: u128, timespan: u128) -> u128 {
+        let factor = self.max_retarget_factor.max(1);
+        let expected = self
+            .target_block_time
+            .saturating_mul(self.retarget_interval.max(2) as u128 - 1)
+            .max(1);
+        let timespan = timespan
+            .clamp((expected / factor).max(1), expected.saturating_mul(factor));
+
+        // difficulty * timespan may not fit into 128 bits, so the quotient and the remainder
+        // of difficulty / expected are scaled separately, which gives the same result
+        let quotient = difficulty / expected;
+        let remainder = difficulty % expected;
+        let next = quotient
+            .saturating_mul(timespan)
+            .saturating_add(remainder.saturating_mul(timespan) / expected);
+        next.clamp(1, self.initial_difficulty.max(1))
+    }
+
+    /*
+    Function builds the genesis block of a network with these parameters.
+    It only depends on the parameters, so nodes that never talked to each other
+    still start from the same block. Its coinbase pays GENESIS_ADDRESS, which nobody owns.
+    */
+    pub fn genesis_block(&self) -> Block {
+        let coinbase = Transaction::coinbase(
+            0,
+            vec![Output::new(
+                GENESIS_ADDRESS.to_owned(),
+                self.initial_subsidy,
+            )],
+        );
+        let mut block = Block::new(
+            0,
+            0,
+            vec![0; 32],
+            vec![coinbase],
+            0,
+            self.initial_difficulty,
+        )
+        .with_hash_algorithm(self.hash_algorithm);
+        if self.ledger == LedgerMode::Account {
+            let mut accounts = HashMap::new();
+            account::apply_transaction(&mut accounts, &block.transactions[0]);
+            block.state_root =
+                account::state_root(&accounts, self.hash_algorithm);
+        }
+        block.mine();
+        block
+    }
+}
+
+// how a block changes the ledger, produced by Blockchain::check_block
+pub(crate) enum LedgerChanges {
+    // out points the block spends and outputs it creates
+    Utxo {
+        spent: HashSet<OutPoint>,
+        created: HashMap<OutPoint, Output>,
+    },
+    // the new value of every account the block touches
+    Account(HashMap<Address, Account>),
+}
+
+// what a connected block changed, put back when the block is disconnected
+enum Undo {
+    // the outputs the block spent
+    Utxo(Vec<(OutPoint, Output)>),
+    // the accounts the block touched as they were before it, None for accounts it created
+    Account(Vec<(Address, Option<Account>)>),
+}
+
+/*
+Sent to the subscribers (see Blockchain::subscribe) every time the active chain changes.
+Appending a block on top of the tip is a reorg that does not disconnect anything.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorgEvent {
+    // blocks that left the active chain, from the old tip down to the fork point
+    pub disconnected: Vec<Block>,
+    // blocks that joined the active chain, from the fork point up to the new tip
+    pub connected: Vec<Block>,
+}
+
+// a block of the block tree together with the work of the whole branch ending at it
+struct BlockNode {
+    block: Block,
+    chain_work: u128,
+    // the order in which blocks were received, between two branches with the same work
+    // the one whose tip arrived first wins
+    sequence: u64,
+}
+
+impl BlockNode {
+    // a branch ending at a node of higher rank beats a branch ending at a node of lower rank
+    fn rank(&self) -> (u128, Reverse<u64>) {
+        (self.chain_work, Reverse(self.sequence))
+    }
+}
+
+pub struct Blockchain {
+    // the active chain, from the genesis block up to the tip with the most work
+    pub blocks: Vec<Block>,
+    pub params: ChainParams,
+
+    // all the outputs that are not spent yet on the active chain, keyed by where they were created
+    unspent_outputs: HashMap<OutPoint, Output>,
+    // the accounts of the active chain, only used by LedgerMode::Account
+    accounts: HashMap<Address, Account>,
+    // every block we accepted so far, on the active chain or on a side branch, keyed by hash
+    tree: HashMap<Hash, BlockNode>,
+    // key: block hash
+    // value: hashes of the blocks of the tree built on top of it
+    children: HashMap<Hash, Vec<Hash>>,
+    // the block of the tree with the most work, the tip once the chain switched to its branch
+    best_tip: Option<Hash>,
+    // ledger changes of each block of the active chain, put back when the block is disconnected
+    undo: HashMap<Hash, Undo>,
+    next_sequence: u64,
+    subscribers: Vec<Sender<ReorgEvent>>,
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blockchain {
+    pub fn new() -> Self {
+        Self::with_params(ChainParams::default())
+    }
+
+    pub fn with_params(params: ChainParams) -> Self {
+        Blockchain {
+            blocks: vec![],
+            params,
+            unspent_outputs: HashMap::new(),
+            accounts: HashMap::new(),
+            tree: HashMap::new(),
+            children: HashMap::new(),
+            best_tip: None,
+            undo: HashMap::new(),
+            next_sequence: 0,
+            subscribers: vec![],
+        }
+    }
+
+    pub fn unspent_outputs(&self) -> &HashMap<OutPoint, Output> {
+        &self.unspent_outputs
+    }
+
+    pub fn is_unspent(&self, out_point: &OutPoint) -> bool {
+        self.unspent_outputs.contains_key(out_point)
+    }
+
+    pub fn get_unspent(&self, out_point: &OutPoint) -> Option<&Output> {
+        self.unspent_outputs.get(out_point)
+    }
+
+    // the unspent outputs paying the address, in no particular order
+    pub fn unspent_outputs_of(
+        &self,
+        address: &str,
+    ) -> Vec<(&OutPoint, &Output)> {
+        self.unspent_outputs
+            .iter()
+            .filter(|(_, output)| output.to_addr == address)
+            .collect()
+    }
+
+    pub fn balance(&self, address: &str) -> u64 {
+        match self.params.ledger {
+            LedgerMode::Utxo => self
+                .unspent_outputs_of(address)
+                .iter()
+                .map(|(_, output)| output.value)
+                .sum(),
+            LedgerMode::Account => self.account(address).balance,
+        }
+    }
+
+    pub fn accounts(&self) -> &HashMap<Address, Account> {
+        &self.accounts
+    }
+
+    // an address the chain never saw is an empty account with nonce 0
+    pub fn account(&self, address: &str) -> Account {
+        self.accounts.get(address).copied().unwrap_or_default()
+    }
+
+    // root of the accounts at the tip, see account.rs
+    pub fn state_root(&self) -> Hash {
+        account::state_root(&self.accounts, self.params.hash_algorithm)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // the tip is the latest block of the active chain
+    pub fn last_block(&self) -> Option<&Block> {
+        self.blocks.last()
+    }
+
+    // whether the block is in the block tree, on the active chain or not
+    pub fn contains_block(&self, hash: &Hash) -> bool {
+        self.tree.contains_key(hash)
+    }
+
+    pub fn get_block(&self, hash: &Hash) -> Option<&Block> {
+        self.tree.get(hash).map(|node| &node.block)
+    }
+
+    // whether the block is part of the active chain
+    pub fn is_active(&self, hash: &Hash) -> bool {
+        self.tree.get(hash).is_some_and(|node| {
+            self.blocks
+                .get(node.block.index as usize)
+                .is_some_and(|block| &block.hash == hash)
+        })
+    }
+
+    /*
+    Function returns the proof that the transaction is in the block, what a light client
+    checks against the block's header (see spv.rs).
+    None when the block is not on the active chain or does not have the transaction.
+    */
+    pub fn merkle_proof(
+        &self,
+        block_hash: &Hash,
+        tx_hash: &Hash,
+    ) -> Option<MerkleProof> {
+        if !self.is_active(block_hash) {
+            return None;
+        }
+        let block = self.get_block(block_hash)?;
+        let position = block
+            .transactions
+            .iter()
+            .position(|transaction| &transaction.hash() == tx_hash)?;
+        block.merkle_proof(position)
+    }
+
+    // total work of the branch from the genesis block up to the given block
+    pub fn chain_work(&self, hash: &Hash) -> Option<u128> {
+        self.tree.get(hash).map(|node| node.chain_work)
+    }
+
+    // total work of the active chain, 0 before the genesis block
+    pub fn tip_work(&self) -> u128 {
+        self.last_block()
+            .and_then(|block| self.chain_work(&block.hash))
+            .unwrap_or(0)
+    }
+
+    /*
+    Function returns a receiver that gets a ReorgEvent every time the active chain changes.
+    Dropping the receiver is enough to unsubscribe.
+    */
+    pub fn subscribe(&mut self) -> Receiver<ReorgEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /*
+    Function returns the difficulty the next block on top of the tip must carry,
+    a miner should build its block with this value before it starts mining.
+    */
+    pub fn next_difficulty(&self) -> u128 {
+        self.difficulty_after(self.last_block().map(|block| &block.hash))
+    }
+
+    // same as next_difficulty, for a block on top of any block of the tree
+    fn difficulty_after(&self, parent: Option<&Hash>) -> u128 {
+        let Some(last) = parent.and_then(|hash| self.get_block(hash)) else {
+            return self.params.initial_difficulty;
+        };
+
+        // the window needs at least two blocks to measure time between them
+        let interval = self.params.retarget_interval.max(2) as usize;
+        let height = last.index as usize + 1;
+        if !height.is_multiple_of(interval) {
+            return last.difficulty;
+        }
+
+        // walk back to the first block of the window, the parent may sit on a side branch
+        let mut first = last;
+        for _ in 1..interval {
+            first = &self.tree[&first.prev_block_hash].block;
+        }
+        let timespan = last.timestamp.saturating_sub(first.timestamp);
+        self.params.retarget(last.difficulty, timespan)
+    }
+
+    /*
+    Function verifies the block against its parent and adds it to the block tree.
+    When the block makes its branch the one with the most work, the chain switches to that branch,
+    and only then are the block's transactions verified against the ledger.
+    If any check fails, the block is dropped and the chain stays on the heaviest valid branch.
+    */
+    pub fn update_with_block(
+        &mut self,
+        block: Block,
+    ) -> Result<(), BlockValidationErr> {
+        if self.tree.contains_key(&block.hash) {
+            return Err(BlockValidationErr::DuplicateBlock);
+        }
+
+        let chain_work = self.check_header(&block)?;
+        self.insert_node(block, chain_work);
+
+        let old_tip = self.last_block().map(|block| block.hash.clone());
+        let result = self.activate_best_chain();
+        self.notify_tip_change(old_tip);
+
+        result
+    }
+
+    // same checks as update_with_block for a block on top of the tip, without adding the block
+    pub fn verify_block(
+        &self,
+        block: &Block,
+    ) -> Result<(), BlockValidationErr> {
+        self.check_block(block).map(|_| ())
+    }
+
+    /*
+    Function runs every check on a block that extends the tip without touching the chain,
+    and returns how the block would change the ledger.
+    Splitting check and apply lets the caller do something in between,
+    like writing the block to disk before the chain accepts it (see storage.rs).
+    */
+    pub(crate) fn check_block(
+        &self,
+        block: &Block,
+    ) -> Result<LedgerChanges, BlockValidationErr> {
+        self.check_header(block)?;
+
+        // transactions can only be checked against the ledger of the tip
+        if let Some(tip) = self.last_block() {
+            if block.prev_block_hash != tip.hash {
+                return Err(BlockValidationErr::MismatchedPreviousHash);
+            }
+        }
+
+        self.verify_transactions(block)
+    }
+
+    // block must have passed check_block against the current tip
+    pub(crate) fn apply_block(&mut self, block: Block, changes: LedgerChanges) {
+        let chain_work =
+            self.tip_work().saturating_add(block_work(block.difficulty));
+        let old_tip = self.last_block().map(|block| block.hash.clone());
+
+        self.insert_node(block.clone(), chain_work);
+        self.connect_block(block, changes);
+        self.notify_tip_change(old_tip);
+    }
+
+    /*
+    Function checks the block against its parent in the block tree,
+    everything except the transactions, and returns the work of the branch ending at the block.
+    */
+    fn check_header(&self, block: &Block) -> Result<u128, BlockValidationErr> {
+        let parent = match self.get_block(&block.prev_block_hash) {
+            Some(parent) => {
+                if block.index != parent.index + 1 {
+                    return Err(BlockValidationErr::MismatchedIndex);
+                }
+                Some(parent)
+            }
+            // only the very first block may come without a parent
+            None if self.tree.is_empty() => {
+                if block.index != 0 {
+                    return Err(BlockValidationErr::MismatchedIndex);
+                }
+                // genesis block has nothing to point to
+                if block.prev_block_hash != vec![0; 32] {
+                    return Err(BlockValidationErr::InvalidGenesisBlockFormat);
+                }
+                None
+            }
+            None => return Err(BlockValidationErr::MismatchedPreviousHash),
+        };
+
+        // a block hashed with another algorithm belongs to another chain
+        if block.hash_algorithm != self.params.hash_algorithm {
+            return Err(BlockValidationErr::MismatchedHashAlgorithm);
+        }
+
+        // the stored hash must be re-producible from the block's contents,
+        // otherwise someone modified the block after it was mined
+        if block.hash != block.hash() {
+            return Err(BlockValidationErr::InvalidHash);
+        }
+
+        if !check_difficulty(&block.hash, block.difficulty) {
+            return Err(BlockValidationErr::DifficultyNotMet);
+        }
+
+        // the hash only covers the merkle root, so the transactions could still
+        // be swapped after mining unless they match the root
+        if block.merkle_root != block.compute_merkle_root() {
+            return Err(BlockValidationErr::InvalidMerkleRoot);
+        }
+
+        // the last node of an odd level is paired with itself, so repeating the last transactions
+        // gives the same root and the same block hash (CVE-2012-2459), such a copy of a valid block
+        // is refused here, before it takes the valid block's place in the tree
+        if block.has_duplicate_transactions() {
+            return Err(BlockValidationErr::DuplicateTransaction);
+        }
+
+        if let Some(parent) = parent {
+            if block.timestamp <= parent.timestamp {
+                return Err(BlockValidationErr::AchronologicalTimestamp);
+            }
+        }
+
+        // meeting its own difficulty is not enough, the block must not pick an easier one
+        let parent_hash = parent.map(|parent| &parent.hash);
+        if block.difficulty != self.difficulty_after(parent_hash) {
+            return Err(BlockValidationErr::MismatchedDifficulty);
+        }
+
+        let parent_work = parent_hash
+            .and_then(|hash| self.chain_work(hash))
+            .unwrap_or(0);
+        Ok(parent_work.saturating_add(block_work(block.difficulty)))
+    }
+
+    /*
+    Function keeps switching to the branch with the most work until the active chain is that branch.
+    A branch that fails on the way is dropped, so the next round picks the heaviest branch left,
+    which in the worst case is the active chain we started from.
+    Returns the first error met on the way.
+    */
+    fn activate_best_chain(&mut self) -> Result<(), BlockValidationErr> {
+        let mut result = Ok(());
+        while let Some(best_tip) = self.heavier_tip() {
+            if let Err(err) = self.reorganize(&best_tip) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /*
+    Function adds the block to the tree. The best block only changes when the new one beats it,
+    every other block of the tree already lost against the best one.
+    */
+    fn insert_node(&mut self, block: Block, chain_work: u128) {
+        let node = BlockNode {
+            block,
+            chain_work,
+            sequence: self.next_sequence,
+        };
+        self.next_sequence += 1;
+
+        let hash = node.block.hash.clone();
+        let beats_best = self
+            .best_tip
+            .as_ref()
+            .is_none_or(|best| node.rank() > self.tree[best].rank());
+        if beats_best {
+            self.best_tip = Some(hash.clone());
+        }
+        self.children
+            .entry(node.block.prev_block_hash.clone())
+            .or_default()
+            .push(hash.clone());
+        self.tree.insert(hash, node);
+    }
+
+    // the best block of the tree while the chain has not switched to it yet
+    fn heavier_tip(&self) -> Option<Hash> {
+        let best = self.best_tip.as_ref()?;
+        let tip = self.last_block().map(|block| &block.hash);
+        (tip != Some(best)).then(|| best.clone())
+    }
+
+    /*
+    Function disconnects the active blocks down to the fork point with the branch ending at `tip`,
+    then connects the blocks of that branch one by one.
+    When a block of the branch is invalid, it is removed from the tree with all its descendants,
+    and the active chain is left at the block before it.
+    */
+    fn reorganize(&mut self, tip: &Hash) -> Result<(), BlockValidationErr> {
+        let old_tip = self.last_block().map(|block| block.hash.clone());
+
+        // blocks of the branch that are not active yet, from the tip down
+        let mut branch = vec![];
+        let mut hash = tip.clone();
+        while !self.is_active(&hash) {
+            let block = &self.tree[&hash].block;
+            branch.push(hash.clone());
+            if block.index == 0 {
+                break;
+            }
+            hash = block.prev_block_hash.clone();
+        }
+
+        let fork_len = self.tree[branch.last().unwrap_or(tip)].block.index;
+        while self.blocks.len() > fork_len as usize {
+            self.disconnect_tip();
+        }
+
+        for hash in branch.iter().rev() {
+            let block = self.tree[hash].block.clone();
+            match self.verify_transactions(&block) {
+                Ok(changes) => self.connect_block(block, changes),
+                Err(err) => {
+                    // `tip` goes with the subtree, and the block that beat every other one
+                    // before `tip` came along is the tip we started from
+                    self.remove_subtree(hash);
+                    self.best_tip = old_tip;
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn connect_block(&mut self, block: Block, changes: LedgerChanges) {
+        let undo = match changes {
+            // all checks passed, now it is safe to move the outputs around in the UTXO set
+            LedgerChanges::Utxo { spent, created } => {
+                let mut spent_outputs = Vec::with_capacity(spent.len());
+                for out_point in spent {
+                    if let Some(output) =
+                        self.unspent_outputs.remove(&out_point)
+                    {
+                        spent_outputs.push((out_point, output));
+                    }
+                }
+                self.unspent_outputs.extend(created);
+                Undo::Utxo(spent_outputs)
+            }
+            LedgerChanges::Account(touched) => Undo::Account(
+                touched
+                    .into_iter()
+                    .map(|(address, account)| {
+                        let previous =
+                            self.accounts.insert(address.clone(), account);
+                        (address, previous)
+                    })
+                    .collect(),
+            ),
+        };
+
+        self.undo.insert(block.hash.clone(), undo);
+        self.blocks.push(block);
+    }
+
+    // the reverse of connect_block for the tip of the active chain
+    fn disconnect_tip(&mut self) {
+        let Some(block) = self.blocks.pop() else {
+            return;
+        };
+
+        match self.undo.remove(&block.hash) {
+            Some(Undo::Utxo(spent)) => {
+                for transaction in &block.transactions {
+                    let tx_hash = transaction.hash();
+                    for index in 0..transaction.outputs.len() {
+                        self.unspent_outputs.remove(&OutPoint::new(
+                            tx_hash.clone(),
+                            index as u32,
+                        ));
+                    }
+                }
+                self.unspent_outputs.extend(spent);
+            }
+            Some(Undo::Account(previous)) => {
+                for (address, account) in previous {
+                    match account {
+                        Some(account) => self.accounts.insert(address, account),
+                        None => self.accounts.remove(&address),
+                    };
+                }
+            }
+            None => {}
+        }
+    }
+
+    // removes an invalid block and everything built on top of it from the tree
+    fn remove_subtree(&mut self, root: &Hash) {
+        if let Some(node) = self.tree.get(root) {
+            let parent = &node.block.prev_block_hash;
+            if let Some(siblings) = self.children.get_mut(parent) {
+                siblings.retain(|sibling| sibling != root);
+            }
+        }
+
+        let mut invalid = vec![root.clone()];
+        while let Some(hash) = invalid.pop() {
+            self.tree.remove(&hash);
+            invalid.extend(self.children.remove(&hash).unwrap_or_default());
+        }
+    }
+
+    /*
+    Function compares the active chain with the old tip and tells the subscribers what changed.
+    The blocks of the old branch are still in the tree, because a block that was active once was valid.
+    */
+    fn notify_tip_change(&mut self, old_tip: Option<Hash>) {
+        if self.subscribers.is_empty()
+            || self.last_block().map(|block| &block.hash) == old_tip.as_ref()
+        {
+            return;
+        }
+
+        let mut disconnected = vec![];
+        let mut fork_point = old_tip;
+        while let Some(hash) =
+            fork_point.as_ref().filter(|hash| !self.is_active(hash))
+        {
+            let block = self.tree[hash].block.clone();
+            fork_point =
+                (block.index > 0).then(|| block.prev_block_hash.clone());
+            disconnected.push(block);
+        }
+
+        let fork_len = fork_point
+            .map_or(0, |hash| self.tree[&hash].block.index as usize + 1);
+        let event = ReorgEvent {
+            disconnected,
+            connected: self.blocks[fork_len..].to_vec(),
+        };
+
+        // a failed send means the receiver was dropped
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /*
+    Function checks every transaction of the block against the ledger
+    without modifying it, and returns how the block changes the ledger.
+    */
+    fn verify_transactions(
+        &self,
+        block: &Block,
+    ) -> Result<LedgerChanges, BlockValidationErr> {
+        match self.params.ledger {
+            LedgerMode::Utxo => {
+                // a UTXO chain has no accounts to commit to
+                if block.state_root != vec![0; 32] {
+                    return Err(BlockValidationErr::InvalidStateRoot);
+                }
+                let (spent, created) = self.verify_utxo_transactions(block)?;
+                Ok(LedgerChanges::Utxo { spent, created })
+            }
+            LedgerMode::Account => {
+                let (accounts, touched) =
+                    self.apply_account_transactions(block)?;
+                let state_root =
+                    account::state_root(&accounts, self.params.hash_algorithm);
+                if block.state_root != state_root {
+                    return Err(BlockValidationErr::InvalidStateRoot);
+                }
+                Ok(LedgerChanges::Account(
+                    touched
+                        .into_iter()
+                        .map(|address| {
+                            let account = accounts[&address];
+                            (address, account)
+                        })
+                        .collect(),
+                ))
+            }
+        }
+    }
+
+    /*
+    Function returns the out points spent by the block
+    together with the outputs created by the block.
+    */
+    fn verify_utxo_transactions(
+        &self,
+        block: &Block,
+    ) -> Result<
+        (HashSet<OutPoint>, HashMap<OutPoint, Output>),
+        BlockValidationErr,
+    > {
+        let (coinbase, transactions) = split_coinbase(block)?;
+
+        let mut block_spent: HashSet<OutPoint> = HashSet::new();
+        let mut block_created: HashMap<OutPoint, Output> = HashMap::new();
+        let mut total_fee: u64 = 0;
+        collect_outputs(coinbase, &mut block_created);
+
+        for transaction in transactions {
+            let fee = check_transaction(&self.unspent_outputs, transaction)?;
+
+            // every transaction is valid on its own against the UTXO set,
+            // but two of them must not spend the same output
+            let input_outpoints = transaction.input_outpoints();
+            if !input_outpoints.is_disjoint(&block_spent) {
+                return Err(BlockValidationErr::DoubleSpend);
+            }
+
+            total_fee = total_fee
+                .checked_add(fee)
+                .ok_or(BlockValidationErr::InvalidCoinbaseValue)?;
+
+            block_spent.extend(input_outpoints);
+            collect_outputs(transaction, &mut block_created);
+        }
+        self.check_coinbase_value(block, coinbase, total_fee)?;
+
+        Ok((block_spent, block_created))
+    }
+
+    /*
+    Function applies the block's transfers one after the other to a copy of the accounts,
+    each one is checked against the state left by the transfers before it.
+    Returns the accounts after the block, and the addresses the block touched.
+    The same transfer twice in a block fails the second time, its nonce is used up by then.
+    */
+    fn apply_account_transactions(
+        &self,
+        block: &Block,
+    ) -> Result<(HashMap<Address, Account>, HashSet<Address>), BlockValidationErr>
+    {
+        let (coinbase, transactions) = split_coinbase(block)?;
+
+        let mut accounts = self.accounts.clone();
+        let mut touched: HashSet<Address> = HashSet::new();
+        let mut total_fee: u64 = 0;
+
+        for transaction in transactions {
+            let fee = account::check_transaction(&accounts, transaction)?;
+            total_fee = total_fee
+                .checked_add(fee)
+                .ok_or(BlockValidationErr::InvalidCoinbaseValue)?;
+            touched
+                .extend(account::apply_transaction(&mut accounts, transaction));
+        }
+        self.check_coinbase_value(block, coinbase, total_fee)?;
+        touched.extend(account::apply_transaction(&mut accounts, coinbase));
+
+        Ok((accounts, touched))
+    }
+
+    // the miner can collect the new coins and all the fees, but nothing more
+    fn check_coinbase_value(
+        &self,
+        block: &Block,
+        coinbase: &Transaction,
+        total_fee: u64,
+    ) -> Result<(), BlockValidationErr> {
+        let max_reward = self
+            .params
+            .block_subsidy(block.index)
+            .saturating_add(total_fee);
+        if coinbase.output_value() > max_reward {
+            return Err(BlockValidationErr::InvalidCoinbaseValue);
+        }
+        Ok(())
+    }
+
+    /*
+    Function returns the state root a block on top of the tip must carry,
+    a miner sets it after picking the block's transactions and before mining.
+    On a UTXO chain it is filled with zeros.
+    */
+    pub fn state_root_after(
+        &self,
+        block: &Block,
+    ) -> Result<Hash, BlockValidationErr> {
+        match self.params.ledger {
+            LedgerMode::Utxo => Ok(vec![0; 32]),
+            LedgerMode::Account => {
+                let (accounts, _) = self.apply_account_transactions(block)?;
+                Ok(account::state_root(&accounts, self.params.hash_algorithm))
+            }
+        }
+    }
+
+    /*
+    Function checks a regular transaction on its own against the ledger of the tip,
+    the same way it would be checked inside the next block, and returns its fee.
+    A mempool runs the same checks against the tip together with its own transactions (see mempool.rs).
+    */
+    pub fn verify_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<u64, BlockValidationErr> {
+        match self.params.ledger {
+            LedgerMode::Utxo => {
+                check_transaction(&self.unspent_outputs, transaction)
+            }
+            LedgerMode::Account => {
+                account::check_transaction(&self.accounts, transaction)
+            }
+        }
+    }
+}
+
+/*
+Function runs the coinbase checks both ledgers share and splits the block's transactions
+into its coinbase and the regular transactions after it.
+*/
+pub(crate) fn split_coinbase(
+    block: &Block,
+) -> Result<(&Transaction, &[Transaction]), BlockValidationErr> {
+    let Some((coinbase, transactions)) = block.transactions.split_first()
+    else {
+        return Err(BlockValidationErr::MissingCoinbase);
+    };
+    if !coinbase.is_coinbase() {
+        return Err(BlockValidationErr::MissingCoinbase);
+    }
+    if coinbase.coinbase_height() != Some(block.index) {
+        return Err(BlockValidationErr::MismatchedCoinbaseHeight);
+    }
+    Ok((coinbase, transactions))
+}
+
+/*
+Function checks a regular transaction against the given UTXO set and returns its fee.
+It does not need the chain itself, so an audit (see audit.rs) can run it
+against a UTXO set it re-built on its own.
+*/
+pub(crate) fn check_transaction(
+    unspent_outputs: &HashMap<OutPoint, Output>,
+    transaction: &Transaction,
+) -> Result<u64, BlockValidationErr> {
+    if transaction.is_coinbase() {
+        return Err(BlockValidationErr::UnexpectedCoinbase);
+    }
+
+    // input_outpoints is a set, so a shorter set means the transaction
+    // lists the same output more than once
+    if transaction.input_outpoints().len() != transaction.inputs.len() {
+        return Err(BlockValidationErr::DoubleSpend);
+    }
+
+    verify_inputs(unspent_outputs, transaction)?;
+
+    transaction
+        .fee()
+        .ok_or(BlockValidationErr::InsufficientInputValue)
+}
+
+/*
+Function checks that every input of the transaction spends an unspent output
+with the right value, and that the spender owns that output.
+*/
+fn verify_inputs(
+    unspent_outputs: &HashMap<OutPoint, Output>,
+    transaction: &Transaction,
+) -> Result<(), BlockValidationErr> {
+    let signing_bytes = transaction.signing_bytes();
+
+    for input in &transaction.inputs {
+        let spent = unspent_outputs
+            .get(&input.prev_out)
+            .ok_or(BlockValidationErr::InvalidInput)?;
+
+        if spent.value != input.value {
+            return Err(BlockValidationErr::InvalidInput);
+        }
+
+        if wallet::public_key_to_address(&input.public_key) != spent.to_addr {
+            return Err(BlockValidationErr::MismatchedPublicKey);
+        }
+
+        if !wallet::verify_signature(
+            &input.public_key,
+            &signing_bytes,
+            &input.signature,
+        ) {
+            return Err(BlockValidationErr::InvalidSignature);
+        }
+    }
+
+    Ok(())
+}
+
+/*
+Function records every output of the transaction under its out point,
+the position of the output in the outputs vector tells one output from another.
+*/
+pub(crate) fn collect_outputs(
+    transaction: &Transaction,
+    created: &mut HashMap<OutPoint, Output>,
+) {
+    let tx_hash = transaction.hash();
+    for (index, output) in transaction.outputs.iter().enumerate() {
+        created.insert(
+            OutPoint::new(tx_hash.clone(), index as u32),
+            output.clone(),
+        );
+    }
+}
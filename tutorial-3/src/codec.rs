@@ -0,0 +1,358 @@
+/*
+Definition of the canonical binary format.
+
+Hashtable::bytes only goes one way: it turns a struct into bytes to be hashed,
+but there is no way back and it does not even record where one field ends and the next begins.
+To store blocks on disk or send them to a peer we need an encoding that can be decoded again,
+so every struct gets an Encode and a Decode implementation here, all following these rules:
+
+- integers are written little-endian with their fixed width (the same u32_bytes, u64_bytes
+  and u128_bytes helpers from lib.rs)
+- byte strings (hashes, keys, signatures) and text (addresses) are written as a u32 length followed by the bytes
+- vectors are written as a u32 item count followed by every item
+- fields are written in the order they are declared in the struct
+- an enum is written as a u8 tag naming the variant, followed by the variant's fields
+
+An encoded value handed to the outside world starts with one extra byte: the format version.
+When the format changes the version is bumped, so old data is rejected instead of being misread
+(version 2 added the hash algorithm at the end of a block, version 3 the state root after the merkle root).
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    block::{Block, BlockHeader},
+    hashtable::HashAlgorithm,
+    merkle::MerkleProof,
+    transactions::{Input, OutPoint, Output, Transaction},
+    u128_bytes, u32_bytes, u64_bytes,
+};
+
+pub const CODEC_VERSION: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // the bytes end in the middle of a value
+    UnexpectedEof,
+    // the leading version byte is not CODEC_VERSION
+    UnsupportedVersion(u8),
+    // there are bytes left after the value was decoded
+    TrailingBytes,
+    // a text field is not valid UTF-8
+    InvalidUtf8,
+    // the leading tag byte of an enum value does not name any of its variants
+    InvalidTag(u8),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported codec version {}", version)
+            }
+            DecodeError::TrailingBytes => {
+                write!(f, "trailing bytes after decoded value")
+            }
+            DecodeError::InvalidUtf8 => write!(f, "text field is not utf-8"),
+            DecodeError::InvalidTag(tag) => {
+                write!(f, "invalid enum tag {}", tag)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub trait Encode {
+    // append the value's fields to buf, without the version byte
+    fn encode_to(&self, buf: &mut Vec<u8>);
+
+    // the value as a standalone, versioned byte string
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![CODEC_VERSION];
+        self.encode_to(&mut buf);
+        buf
+    }
+}
+
+pub trait Decode: Sized {
+    // read the value's fields from the reader, without the version byte
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError>;
+
+    // the reverse of Encode::encode, every byte must belong to the value
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let version = reader.read_u8()?;
+        if version != CODEC_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let value = Self::decode_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(value)
+    }
+}
+
+/*
+Reader walks through the bytes from front to back,
+every read either takes exactly the bytes it needs or fails with UnexpectedEof.
+*/
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u128(&mut self) -> Result<u128, DecodeError> {
+        Ok(u128::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn read_string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.read_bytes()?)
+            .map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub fn read_vec<T: Decode>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let cnt = self.read_u32()? as usize;
+        // every item takes at least one byte, so a count larger than the bytes left
+        // can only come from corrupted input, do not let it reserve memory
+        let mut items = Vec::with_capacity(cnt.min(self.bytes.len()));
+        for _ in 0..cnt {
+            items.push(T::decode_from(self)?);
+        }
+        Ok(items)
+    }
+}
+
+pub fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend(&u32_bytes(&(bytes.len() as u32)));
+    buf.extend(bytes);
+}
+
+pub fn write_vec<T: Encode>(buf: &mut Vec<u8>, items: &[T]) {
+    buf.extend(&u32_bytes(&(items.len() as u32)));
+    for item in items {
+        item.encode_to(buf);
+    }
+}
+
+impl Encode for Output {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, self.to_addr.as_bytes());
+        buf.extend(&u64_bytes(&self.value));
+    }
+}
+
+impl Decode for Output {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Output {
+            to_addr: reader.read_string()?,
+            value: reader.read_u64()?,
+        })
+    }
+}
+
+impl Encode for OutPoint {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, &self.tx_hash);
+        buf.extend(&u32_bytes(&self.index));
+    }
+}
+
+impl Decode for OutPoint {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(OutPoint {
+            tx_hash: reader.read_bytes()?,
+            index: reader.read_u32()?,
+        })
+    }
+}
+
+impl Encode for Input {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.prev_out.encode_to(buf);
+        buf.extend(&u64_bytes(&self.value));
+        write_bytes(buf, &self.signature);
+        write_bytes(buf, &self.public_key);
+    }
+}
+
+impl Decode for Input {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Input {
+            prev_out: OutPoint::decode_from(reader)?,
+            value: reader.read_u64()?,
+            signature: reader.read_bytes()?,
+            public_key: reader.read_bytes()?,
+        })
+    }
+}
+
+impl Encode for Transaction {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        write_vec(buf, &self.inputs);
+        write_vec(buf, &self.outputs);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Transaction {
+            inputs: reader.read_vec()?,
+            outputs: reader.read_vec()?,
+        })
+    }
+}
+
+impl Encode for HashAlgorithm {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for HashAlgorithm {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0 => Ok(HashAlgorithm::Sha256),
+            1 => Ok(HashAlgorithm::DoubleSha256),
+            2 => Ok(HashAlgorithm::Keccak256),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+/*
+The stored hash and merkle root are encoded as they are instead of being re-calculated on decode,
+that way a block that was tampered with stays detectable by Blockchain::update_with_block.
+*/
+impl Encode for Block {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.extend(&u32_bytes(&self.index));
+        buf.extend(&u128_bytes(&self.timestamp));
+        write_bytes(buf, &self.hash);
+        write_bytes(buf, &self.prev_block_hash);
+        write_bytes(buf, &self.merkle_root);
+        write_bytes(buf, &self.state_root);
+        write_vec(buf, &self.transactions);
+        buf.extend(&u64_bytes(&self.nonce));
+        buf.extend(&u128_bytes(&self.difficulty));
+        self.hash_algorithm.encode_to(buf);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Block {
+            index: reader.read_u32()?,
+            timestamp: reader.read_u128()?,
+            hash: reader.read_bytes()?,
+            prev_block_hash: reader.read_bytes()?,
+            merkle_root: reader.read_bytes()?,
+            state_root: reader.read_bytes()?,
+            transactions: reader.read_vec()?,
+            nonce: reader.read_u64()?,
+            difficulty: reader.read_u128()?,
+            hash_algorithm: HashAlgorithm::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.extend(&u32_bytes(&self.index));
+        buf.extend(&u128_bytes(&self.timestamp));
+        write_bytes(buf, &self.hash);
+        write_bytes(buf, &self.prev_block_hash);
+        write_bytes(buf, &self.merkle_root);
+        write_bytes(buf, &self.state_root);
+        buf.extend(&u64_bytes(&self.nonce));
+        buf.extend(&u128_bytes(&self.difficulty));
+        self.hash_algorithm.encode_to(buf);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            index: reader.read_u32()?,
+            timestamp: reader.read_u128()?,
+            hash: reader.read_bytes()?,
+            prev_block_hash: reader.read_bytes()?,
+            merkle_root: reader.read_bytes()?,
+            state_root: reader.read_bytes()?,
+            nonce: reader.read_u64()?,
+            difficulty: reader.read_u128()?,
+            hash_algorithm: HashAlgorithm::decode_from(reader)?,
+        })
+    }
+}
+
+// the siblings are hashes, a u32 count followed by every hash as a byte string
+impl Encode for MerkleProof {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.extend(&u32_bytes(&(self.index as u32)));
+        buf.extend(&u32_bytes(&(self.siblings.len() as u32)));
+        for sibling in &self.siblings {
+            write_bytes(buf, sibling);
+        }
+        self.algorithm.encode_to(buf);
+    }
+}
+
+impl Decode for MerkleProof {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let index = reader.read_u32()? as usize;
+        let cnt = reader.read_u32()?;
+        // the count is not trusted to reserve memory, same as read_vec
+        let mut siblings = vec![];
+        for _ in 0..cnt {
+            siblings.push(reader.read_bytes()?);
+        }
+        Ok(MerkleProof {
+            index,
+            siblings,
+            algorithm: HashAlgorithm::decode_from(reader)?,
+        })
+    }
+}
@@ -1,18 +1,66 @@
 /*
 Define the Hashtable trait, which provides an interface for hashing functionality.
 
-This trait includes two functions:
+This trait includes three functions:
 1. `bytes`: This function must be implemented by any struct that uses this trait.
     It defines how the struct's internal data(variables or objects) will be serialized into a vector of bytes.
 
-2.`hash`: This function provides a default implementation for computing the hash of the serialized byte data.
-    It uses the SHA-256 algorithm form the `crypto_hash` library to produce a cryptographic hash of the output
-    from the `bytes` function. This ensures that the hash computation is consistent across implementaiton.
+2. `hash_with`: This function provides a default implementation for computing the hash of the serialized byte data
+    with any of the supported algorithms (see HashAlgorithm below).
+
+3.`hash`: This function provides the default hash of a struct, which is SHA-256 from the `crypto_hash` library.
+    This ensures that the hash computation is consistent across implementaiton,
+    a struct that records its own algorithm (like Block) overrides it to use that one instead.
+
+Which algorithm a chain uses is one of its parameters (see ChainParams in blockchain.rs),
+it covers the block hashes and the Merkle trees of the blocks:
+- Sha256: a single SHA-256, what this project always used
+- DoubleSha256: SHA-256 applied twice, SHA-256(SHA-256(bytes)), the way Bitcoin hashes its blocks
+- Keccak256: the Keccak-256 Ethereum uses. Note this is not the standardized SHA3-256,
+  Ethereum adopted Keccak before NIST changed its padding, so the two give different digests.
+
+Transaction hashes, and the addresses derived from public keys (see wallet.rs), always use SHA-256,
+a transaction is referenced by its hash (see OutPoint) no matter which chain it ends up in.
 */
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum HashAlgorithm {
+    // the values are the tags used in hashed bytes and in the codec
+    #[default]
+    Sha256 = 0,
+    DoubleSha256 = 1,
+    Keccak256 = 2,
+}
+
+impl HashAlgorithm {
+    // every supported algorithm produces 32 bytes digests
+    pub fn digest(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => {
+                crypto_hash::digest(crypto_hash::Algorithm::SHA256, bytes)
+            }
+            HashAlgorithm::DoubleSha256 => crypto_hash::digest(
+                crypto_hash::Algorithm::SHA256,
+                &crypto_hash::digest(crypto_hash::Algorithm::SHA256, bytes),
+            ),
+            HashAlgorithm::Keccak256 => Keccak256::digest(bytes).to_vec(),
+        }
+    }
+}
+
 pub trait Hashtable {
     fn bytes(&self) -> Vec<u8>;
 
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Vec<u8> {
+        algorithm.digest(&self.bytes())
+    }
+
     fn hash(&self) -> Vec<u8> {
-        crypto_hash::digest(crypto_hash::Algorithm::SHA256, &self.bytes())
+        self.hash_with(HashAlgorithm::Sha256)
     }
 }
@@ -90,9 +90,32 @@ pub fn u128_bytes(u: &u128) -> [u8; 16] {
     ]
 }
 
-// declare block, blockchain, hashtable and transacitons as modules in the scope of the project
+/*
+Function interprets the lowest 16 bytes of a hash as a little-endian u128,
+the byte at index 16 is the least significant one and byte 31 is the most significant one.
+This is the value we compare against a block's difficulty: the smaller the value the "harder" the hash.
+*/
+pub fn difficulty_bytes_as_u128(v: &Hash) -> u128 {
+    v[16..32]
+        .iter()
+        .rev()
+        .fold(0u128, |acc, byte| (acc << 8) | *byte as u128)
+}
+
+// declare account, audit, block, blockchain, codec, hashtable, mempool, merkle, miner, network, node, spv, storage, transacitons and wallet as modules in the scope of the project
 // we set those mods to public in order to let them available in the scope of tests/
+pub mod account;
+pub mod audit;
 pub mod block;
 pub mod blockchain;
+pub mod codec;
 pub mod hashtable;
+pub mod mempool;
+pub mod merkle;
+pub mod miner;
+pub mod network;
+pub mod node;
+pub mod spv;
+pub mod storage;
 pub mod transactions;
+pub mod wallet;
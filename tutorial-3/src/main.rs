@@ -1,3 +1,481 @@
-fn main() {
-    println!("Hello, world!");
+/*
+Command line interface of the blockchain.
+
+Everything is kept in a data directory (./chain-data unless --data-dir names another one):
+- blocks.dat and index.dat: the chain itself (see storage.rs)
+- wallets/<address>.key: the hex-encoded secret key of every wallet made by new-wallet
+- pending.json: transfers made by send that no mined block has included yet
+
+A session could look like this:
+
+    cargo run -- init
+    cargo run -- new-wallet                    # prints alice's address
+    cargo run -- new-wallet                    # prints bob's address
+    cargo run -- mine <alice> 3 --threads 4     # prints the hashrate at the end
+    cargo run -- send <alice> <bob> 70 --fee 5
+    cargo run -- mine <alice>
+    cargo run -- balance <bob>                 # 70
+    cargo run -- print-chain
+    cargo run -- audit                         # re-checks every stored block
+
+The node command runs one node of a local network (see node.rs) instead, for example three nodes on one machine:
+
+    cargo run -- node --listen 127.0.0.1:8000 --mine <address>
+    cargo run -- node --listen 127.0.0.1:8001 --peer 127.0.0.1:8000
+    cargo run -- node --listen 127.0.0.1:8002 --peer 127.0.0.1:8000 --peer 127.0.0.1:8001
+
+All the nodes build the same genesis block from the default chain parameters,
+the first one mines and the other two download its chain and follow it.
+Nodes keep their chain in memory only, they do not touch the data directory.
+*/
+
+use std::collections::HashSet;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use blockchain::{
+    blockchain::ChainParams,
+    mempool::Mempool,
+    miner::Miner,
+    node::{Node, NodeConfig, NodeErrors, MAX_BLOCK_SIZE},
+    storage::{PersistentBlockchain, BLOCK_FILE},
+    transactions::{Input, OutPoint, Output, Transaction},
+    wallet::Wallet,
+};
+
+const USAGE: &str = "usage: tutorial-3 [--data-dir <dir>] <command>
+
+commands:
+    init                                      create the chain with its genesis block
+    new-wallet                                create a wallet and print its address
+    mine <address> [<count>] [--threads <n>]  mine count blocks (default 1) paying the address
+    send <from> <to> <amount> [--fee <fee>]   make a transfer, it is mined by the next mine
+    balance <address>                         print the unspent coins of the address
+    print-chain                               print every block and its transactions
+    audit                                     re-check the whole chain and report what is wrong
+    node --listen <addr> [--peer <addr>]... [--mine <address>] [--threads <n>]
+                                              run a node of a local network
+
+--threads sets the mining threads, one per core by default";
+
+const DEFAULT_DATA_DIR: &str = "chain-data";
+const WALLET_DIR: &str = "wallets";
+const PENDING_FILE: &str = "pending.json";
+
+// how often the node's status is printed
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+enum Command {
+    Init,
+    NewWallet,
+    Mine {
+        address: String,
+        count: u32,
+        threads: usize,
+    },
+    Send {
+        from: String,
+        to: String,
+        amount: u64,
+        fee: u64,
+    },
+    Balance {
+        address: String,
+    },
+    PrintChain,
+    Audit,
+    Node(NodeConfig),
+}
+
+/*
+Every option takes a value, and may appear anywhere after the program name,
+the remaining arguments are the command and its positional arguments.
+*/
+fn parse_args() -> anyhow::Result<(PathBuf, Command)> {
+    let mut positional = vec![];
+    let mut options = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args
+                .next()
+                .with_context(|| format!("{} needs a value", arg))?;
+            options.push((arg, value));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let mut command = match positional
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["init"] => Command::Init,
+        ["new-wallet"] => Command::NewWallet,
+        ["mine", address] => Command::Mine {
+            address: address.to_string(),
+            count: 1,
+            threads: Miner::default().threads(),
+        },
+        ["mine", address, count] => Command::Mine {
+            address: address.to_string(),
+            count: parse_number(count)?,
+            threads: Miner::default().threads(),
+        },
+        ["send", from, to, amount] => Command::Send {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount: parse_number(amount)?,
+            fee: 0,
+        },
+        ["balance", address] => Command::Balance {
+            address: address.to_string(),
+        },
+        ["print-chain"] => Command::PrintChain,
+        ["audit"] => Command::Audit,
+        ["node"] => Command::Node(NodeConfig {
+            listen_addr: "127.0.0.1:8000".parse()?,
+            peers: vec![],
+            params: ChainParams::default(),
+            miner: None,
+            mining_threads: Miner::default().threads(),
+        }),
+        _ => bail!("{}", USAGE),
+    };
+
+    let mut data_dir = PathBuf::from(DEFAULT_DATA_DIR);
+    for (flag, value) in options {
+        match (flag.as_str(), &mut command) {
+            ("--data-dir", _) => data_dir = PathBuf::from(value),
+            ("--fee", Command::Send { fee, .. }) => {
+                *fee = parse_number(&value)?
+            }
+            ("--listen", Command::Node(config)) => {
+                config.listen_addr = parse_addr(&value)?
+            }
+            ("--peer", Command::Node(config)) => {
+                config.peers.push(parse_addr(&value)?)
+            }
+            ("--mine", Command::Node(config)) => config.miner = Some(value),
+            ("--threads", Command::Mine { threads, .. }) => {
+                *threads = parse_number(&value)?
+            }
+            ("--threads", Command::Node(config)) => {
+                config.mining_threads = parse_number(&value)?
+            }
+            _ => bail!("unknown argument {}\n{}", flag, USAGE),
+        }
+    }
+
+    Ok((data_dir, command))
+}
+
+fn parse_number<T: FromStr>(value: &str) -> anyhow::Result<T> {
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("{} is not a valid number", value))
+}
+
+fn parse_addr(value: &str) -> anyhow::Result<SocketAddr> {
+    value
+        .parse()
+        .with_context(|| format!("{} is not a socket address", value))
+}
+
+fn open_chain(data_dir: &Path) -> anyhow::Result<PersistentBlockchain> {
+    if !data_dir.join(BLOCK_FILE).exists() {
+        bail!("no chain in {}, run init first", data_dir.display());
+    }
+    PersistentBlockchain::open(data_dir, ChainParams::default()).with_context(
+        || format!("cannot open the chain in {}", data_dir.display()),
+    )
+}
+
+fn wallet_path(data_dir: &Path, address: &str) -> PathBuf {
+    data_dir.join(WALLET_DIR).join(format!("{}.key", address))
+}
+
+fn load_wallet(data_dir: &Path, address: &str) -> anyhow::Result<Wallet> {
+    let path = wallet_path(data_dir, address);
+    let secret = fs::read_to_string(&path).with_context(|| {
+        format!("no wallet for {} in {}", address, data_dir.display())
+    })?;
+    let secret: [u8; 32] = hex::decode(secret.trim())
+        .ok()
+        .and_then(|secret| secret.try_into().ok())
+        .with_context(|| {
+            format!("{} does not hold a secret key", path.display())
+        })?;
+
+    let wallet = Wallet::from_secret_bytes(&secret);
+    if wallet.address() != address {
+        bail!("{} holds the key of another address", path.display());
+    }
+    Ok(wallet)
+}
+
+/*
+Pending transfers are admitted to a fresh mempool again every time they are loaded,
+the ones a mined block has spent in the meantime are dropped on the way.
+They are saved in the order they arrived, so each one comes after the transfers it depends on.
+*/
+fn load_pending(
+    data_dir: &Path,
+    chain: &PersistentBlockchain,
+) -> anyhow::Result<Mempool> {
+    let mut mempool = Mempool::new();
+    let path = data_dir.join(PENDING_FILE);
+    if !path.exists() {
+        return Ok(mempool);
+    }
+
+    let transactions: Vec<Transaction> =
+        serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+    for transaction in transactions {
+        let _ = mempool.add(chain.chain(), transaction);
+    }
+    Ok(mempool)
+}
+
+fn save_pending(data_dir: &Path, mempool: &Mempool) -> anyhow::Result<()> {
+    let transactions: Vec<&Transaction> = mempool
+        .sorted_by_arrival()
+        .into_iter()
+        .map(|entry| &entry.transaction)
+        .collect();
+    fs::write(
+        data_dir.join(PENDING_FILE),
+        serde_json::to_vec_pretty(&transactions)?,
+    )?;
+    Ok(())
+}
+
+fn init(data_dir: &Path) -> anyhow::Result<()> {
+    let params = ChainParams::default();
+    let mut chain = PersistentBlockchain::open(data_dir, params.clone())?;
+    if !chain.chain().is_empty() {
+        bail!("{} already holds a chain", data_dir.display());
+    }
+
+    let genesis = params.genesis_block();
+    println!("{:?}", genesis);
+    chain.update_with_block(genesis)?;
+    Ok(())
+}
+
+fn new_wallet(data_dir: &Path) -> anyhow::Result<()> {
+    let wallet = Wallet::new();
+    let path = wallet_path(data_dir, &wallet.address());
+    fs::create_dir_all(data_dir.join(WALLET_DIR))?;
+    fs::write(path, hex::encode(wallet.secret_bytes()))?;
+    println!("{}", wallet.address());
+    Ok(())
+}
+
+fn mine(
+    data_dir: &Path,
+    address: &str,
+    count: u32,
+    threads: usize,
+) -> anyhow::Result<()> {
+    let mut chain = open_chain(data_dir)?;
+    let mut mempool = load_pending(data_dir, &chain)?;
+    let miner = Miner::new(threads);
+
+    for _ in 0..count {
+        let template =
+            mempool.block_template(chain.chain(), address, MAX_BLOCK_SIZE);
+        let index = template.index;
+        let Some(block) = miner.mine(template) else {
+            bail!("no nonce meets the difficulty of block {}", index);
+        };
+        chain.update_with_block(block.clone())?;
+        mempool.remove_block_transactions(chain.chain(), &block);
+        println!("{:?}", block);
+    }
+
+    let stats = miner.stats();
+    println!(
+        "{} hashes in {:.2?} on {} threads, {:.0} hashes/s",
+        stats.hashes,
+        stats.elapsed,
+        miner.threads(),
+        stats.hashrate()
+    );
+    if let Some(expected) =
+        stats.expected_block_time(chain.chain().next_difficulty())
+    {
+        println!("expected time to the next block: {:.2?}", expected);
+    }
+
+    save_pending(data_dir, &mempool)
+}
+
+/*
+Function spends the sender's largest unspent outputs until they cover the amount and the fee,
+whatever is left over goes back to the sender as change.
+Outputs already spent by a pending transfer are skipped, so that both can be mined.
+*/
+fn send(
+    data_dir: &Path,
+    from: &str,
+    to: &str,
+    amount: u64,
+    fee: u64,
+) -> anyhow::Result<()> {
+    let wallet = load_wallet(data_dir, from)?;
+    let chain = open_chain(data_dir)?;
+    let mut mempool = load_pending(data_dir, &chain)?;
+
+    let pending_spent: HashSet<OutPoint> = mempool
+        .sorted_by_fee_rate()
+        .iter()
+        .flat_map(|entry| entry.transaction.input_outpoints())
+        .collect();
+    let mut spendable: Vec<_> = chain
+        .chain()
+        .unspent_outputs_of(from)
+        .into_iter()
+        .filter(|(out_point, _)| !pending_spent.contains(out_point))
+        .collect();
+    spendable.sort_by_key(|(_, output)| std::cmp::Reverse(output.value));
+
+    let needed = amount.checked_add(fee).context("amount and fee overflow")?;
+    let mut inputs = vec![];
+    let mut total = 0;
+    for (out_point, output) in spendable {
+        if total >= needed {
+            break;
+        }
+        inputs.push(Input::new(
+            out_point.tx_hash.clone(),
+            out_point.index,
+            output.value,
+        ));
+        total += output.value;
+    }
+    if total < needed {
+        bail!("{} can spend {} coins, {} needed", from, total, needed);
+    }
+
+    let mut outputs = vec![Output::new(to.to_owned(), amount)];
+    if total > needed {
+        outputs.push(Output::new(from.to_owned(), total - needed));
+    }
+    let mut transaction = Transaction::new(inputs, outputs);
+    wallet.sign_transaction(&mut transaction);
+
+    let tx_hash = mempool.add(chain.chain(), transaction)?;
+    save_pending(data_dir, &mempool)?;
+    println!("{}", hex::encode(tx_hash));
+    Ok(())
+}
+
+fn balance(data_dir: &Path, address: &str) -> anyhow::Result<()> {
+    let chain = open_chain(data_dir)?;
+    println!("{}", chain.chain().balance(address));
+    Ok(())
+}
+
+fn print_chain(data_dir: &Path) -> anyhow::Result<()> {
+    let chain = open_chain(data_dir)?;
+    for block in &chain.chain().blocks {
+        println!("{:?}", block);
+        for transaction in &block.transactions {
+            println!("    {:?}", transaction);
+        }
+    }
+    Ok(())
+}
+
+fn audit(data_dir: &Path) -> anyhow::Result<()> {
+    let report = open_chain(data_dir)?.chain().audit();
+    println!(
+        "blocks: {}, transactions: {}, supply: {} of {}",
+        report.blocks_checked,
+        report.transactions_checked,
+        report.total_supply,
+        report.max_supply
+    );
+    for finding in &report.findings {
+        let tx = finding.tx_hash.as_ref().map_or(String::new(), |hash| {
+            format!(", transaction {}", hex::encode(hash))
+        });
+        println!(
+            "{:?} failed at block {}{}: {}",
+            finding.check, finding.block_index, tx, finding.reason
+        );
+    }
+    if !report.is_clean() {
+        bail!("the chain in {} failed the audit", data_dir.display());
+    }
+    Ok(())
+}
+
+fn run_node(config: NodeConfig) -> anyhow::Result<()> {
+    let node = Node::bind(config)?;
+    let handle = node.handle();
+    println!("node listening on {}", node.local_addr());
+
+    thread::spawn(move || loop {
+        thread::sleep(STATUS_INTERVAL);
+        let Some(status) = handle.status() else {
+            return;
+        };
+        println!(
+            "blocks: {}, tip: {}, mempool: {}, peers: {}",
+            status.block_count,
+            hex::encode(&status.tip),
+            status.mempool_len,
+            status.peer_count
+        );
+        if let Some(stats) = status.mining {
+            println!("mining: {:.0} hashes/s", stats.hashrate());
+        }
+        let errors = status.errors;
+        if errors != NodeErrors::default() {
+            println!(
+                "rejected: {} transactions, {} blocks, lost peers: {}, failed connects: {}",
+                errors.rejected_transactions,
+                errors.rejected_blocks,
+                errors.lost_peers,
+                errors.failed_connects
+            );
+        }
+    });
+
+    node.run();
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let (data_dir, command) = parse_args()?;
+    match command {
+        Command::Init => init(&data_dir),
+        Command::NewWallet => new_wallet(&data_dir),
+        Command::Mine {
+            address,
+            count,
+            threads,
+        } => mine(&data_dir, &address, count, threads),
+        Command::Send {
+            from,
+            to,
+            amount,
+            fee,
+        } => send(&data_dir, &from, &to, amount, fee),
+        Command::Balance { address } => balance(&data_dir, &address),
+        Command::PrintChain => print_chain(&data_dir),
+        Command::Audit => audit(&data_dir),
+        Command::Node(config) => run_node(config),
+    }
 }
@@ -0,0 +1,413 @@
+/*
+Definition of the Mempool.
+
+A transaction that was signed and broadcast is not in the chain yet, it waits in the mempool
+of every node until a miner puts it into a block. The mempool only keeps transactions that
+could be mined once the pool transactions before them are:
+- it must be valid against the ledger of the current tip together with the pool transactions,
+  so it may spend an output created by a pool transaction (see Blockchain::verify_transaction)
+- it must not spend an output that another transaction of the pool already spends,
+  the first one to arrive keeps the output, the later one is a conflict and is refused
+
+On an account chain (see account.rs) the input of a transfer is the sender's address and nonce,
+so the same rule keeps one transfer per nonce of a sender, and a sender's next transfer
+carries the nonce the sender has after the pool transfers, not the one of the tip.
+
+Miners are paid by the fees, and a block has limited room, so what matters is not the fee itself
+but the fee per byte of the transaction in its serialized form (see codec.rs):
+
+    fee rate = fee / encoded size
+
+block_template fills a block with the transactions of the highest fee rate until it is full,
+skipping the ones that still wait for a pool transaction the block does not hold.
+
+The pool follows the chain through its ReorgEvents (see Blockchain::subscribe):
+- transactions mined in a connected block leave the pool, together with the ones spending the same outputs
+- transactions of a disconnected block go back into the pool, as long as they are still valid
+Either way the pool is admitted again in the order its transactions arrived,
+a transaction always arrives after the ones it depends on, so it finds them in the pool.
+*/
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    account::{self, Account},
+    block::Block,
+    blockchain::{
+        self, BlockValidationErr, Blockchain, LedgerMode, ReorgEvent,
+    },
+    codec::Encode,
+    hashtable::Hashtable,
+    now,
+    transactions::{OutPoint, Output, Transaction},
+    Address, Hash,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolError {
+    // the transaction is already in the pool
+    Known,
+    // the transaction spends an output that a transaction of the pool already spends
+    Conflict,
+    // the transaction is not valid against the UTXO set of the tip
+    Invalid(BlockValidationErr),
+}
+
+impl Display for MempoolError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MempoolError::Known => write!(f, "transaction is already known"),
+            MempoolError::Conflict => {
+                write!(f, "transaction conflicts with a pool transaction")
+            }
+            MempoolError::Invalid(err) => {
+                write!(f, "transaction is invalid: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolEntry {
+    pub transaction: Transaction,
+    pub fee: u64,
+    // size of the encoded transaction in bytes
+    pub size: usize,
+    // the order in which transactions entered the pool
+    pub sequence: u64,
+}
+
+impl MempoolEntry {
+    /*
+    Function compares the fee rates of two entries without dividing,
+    a / b > c / d is the same as a * d > c * b for positive sizes, and it keeps the fractions.
+    */
+    pub fn cmp_fee_rate(&self, other: &MempoolEntry) -> Ordering {
+        let own = self.fee as u128 * other.size as u128;
+        let others = other.fee as u128 * self.size as u128;
+        own.cmp(&others)
+    }
+}
+
+#[derive(Default)]
+pub struct Mempool {
+    entries: HashMap<Hash, MempoolEntry>,
+    // which pool transaction spends each out point
+    spent: HashMap<OutPoint, Hash>,
+    // outputs created by the pool transactions, only used by LedgerMode::Utxo
+    created: HashMap<OutPoint, Output>,
+    // accounts touched by the pool transactions as they are after all of them,
+    // only used by LedgerMode::Account
+    accounts: HashMap<Address, Account>,
+    next_sequence: u64,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, tx_hash: &Hash) -> bool {
+        self.entries.contains_key(tx_hash)
+    }
+
+    pub fn get(&self, tx_hash: &Hash) -> Option<&MempoolEntry> {
+        self.entries.get(tx_hash)
+    }
+
+    /*
+    Function checks the transaction against the tip of the chain and the pool,
+    and keeps it when it could be mined after the pool transactions. Returns the transaction hash.
+    */
+    pub fn add(
+        &mut self,
+        chain: &Blockchain,
+        transaction: Transaction,
+    ) -> Result<Hash, MempoolError> {
+        let tx_hash = transaction.hash();
+        if self.entries.contains_key(&tx_hash) {
+            return Err(MempoolError::Known);
+        }
+
+        // checked before the ledger, on an account chain a second transfer with the same nonce
+        // is a conflict, not a transfer with an outdated nonce
+        let input_outpoints = transaction.input_outpoints();
+        if input_outpoints
+            .iter()
+            .any(|out_point| self.spent.contains_key(out_point))
+        {
+            return Err(MempoolError::Conflict);
+        }
+
+        let fee = match chain.params.ledger {
+            LedgerMode::Utxo => {
+                // the outputs the transaction spends, found in the pool or in the chain
+                let spent_outputs: HashMap<OutPoint, Output> = input_outpoints
+                    .iter()
+                    .filter_map(|out_point| {
+                        let output = self
+                            .created
+                            .get(out_point)
+                            .or_else(|| chain.get_unspent(out_point))?;
+                        Some((out_point.clone(), output.clone()))
+                    })
+                    .collect();
+                blockchain::check_transaction(&spent_outputs, &transaction)
+            }
+            LedgerMode::Account => account::check_transaction(
+                &pending_accounts(chain, &self.accounts, &transaction),
+                &transaction,
+            ),
+        }
+        .map_err(MempoolError::Invalid)?;
+
+        match chain.params.ledger {
+            LedgerMode::Utxo => {
+                blockchain::collect_outputs(&transaction, &mut self.created)
+            }
+            LedgerMode::Account => {
+                apply_pending(chain, &mut self.accounts, &transaction)
+            }
+        }
+        for out_point in input_outpoints {
+            self.spent.insert(out_point, tx_hash.clone());
+        }
+        let size = transaction.encode().len();
+        self.entries.insert(
+            tx_hash.clone(),
+            MempoolEntry {
+                transaction,
+                fee,
+                size,
+                sequence: self.next_sequence,
+            },
+        );
+        self.next_sequence += 1;
+
+        Ok(tx_hash)
+    }
+
+    // all the entries, the highest fee rate first
+    pub fn sorted_by_fee_rate(&self) -> Vec<&MempoolEntry> {
+        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| b.cmp_fee_rate(a));
+        entries
+    }
+
+    // all the entries in the order they entered the pool, each one after those it depends on
+    pub fn sorted_by_arrival(&self) -> Vec<&MempoolEntry> {
+        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        entries.sort_by_key(|entry| entry.sequence);
+        entries
+    }
+
+    /*
+    Function drops the transactions of a block that joined the chain,
+    and the pool transactions that can never be mined now: the ones spending an output the block spent,
+    and the ones depending on those. The chain must already have the block.
+    */
+    pub fn remove_block_transactions(
+        &mut self,
+        chain: &Blockchain,
+        block: &Block,
+    ) {
+        self.readmit(chain, std::slice::from_ref(block), vec![]);
+    }
+
+    /*
+    Function brings the pool up to date with a change of the active chain.
+    When blocks were disconnected, the outputs the pool relied on may be gone with them,
+    so their transactions and the ones already in the pool are all admitted again
+    against the new tip, oldest first, and whatever is no longer valid is dropped.
+    */
+    pub fn update_with_reorg(
+        &mut self,
+        chain: &Blockchain,
+        event: &ReorgEvent,
+    ) {
+        // disconnected goes from the old tip down, and the coinbase cannot live without its block
+        let disconnected = event
+            .disconnected
+            .iter()
+            .rev()
+            .flat_map(|block| block.transactions.iter().skip(1).cloned())
+            .collect();
+        self.readmit(chain, &event.connected, disconnected);
+    }
+
+    /*
+    Function empties the pool and admits again the given transactions followed by the pool's own,
+    in the order they arrived, leaving out the ones mined by the connected blocks.
+    Every transaction is checked against the new tip and the transactions admitted before it,
+    so one depending on a transaction that is gone fails just like that transaction did.
+    */
+    fn readmit(
+        &mut self,
+        chain: &Blockchain,
+        connected: &[Block],
+        mut pending: Vec<Transaction>,
+    ) {
+        let mined: HashSet<Hash> = connected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(|transaction| transaction.hash())
+            .collect();
+
+        let mut entries: Vec<MempoolEntry> =
+            self.entries.drain().map(|(_, entry)| entry).collect();
+        entries.sort_by_key(|entry| entry.sequence);
+        pending.extend(entries.into_iter().map(|entry| entry.transaction));
+        self.spent.clear();
+        self.created.clear();
+        self.accounts.clear();
+
+        for transaction in pending {
+            if mined.contains(&transaction.hash()) {
+                continue;
+            }
+            // a transaction that is no longer valid is simply forgotten
+            let _ = self.add(chain, transaction);
+        }
+    }
+
+    /*
+    Function builds the next block on top of the chain's tip: a coinbase paying the subsidy plus the fees
+    to `miner`, followed by the transactions with the highest fee rate, as long as the encoded transactions
+    fit into `max_size` bytes. The block carries the difficulty and the state root the chain expects,
+    so the only thing left is to call Block::mine on it.
+    */
+    pub fn block_template(
+        &self,
+        chain: &Blockchain,
+        miner: &str,
+        max_size: usize,
+    ) -> Block {
+        let (index, prev_block_hash, timestamp) = match chain.last_block() {
+            Some(tip) => (
+                tip.index + 1,
+                tip.hash.clone(),
+                now().max(tip.timestamp + 1),
+            ),
+            None => (0, vec![0; 32], now()),
+        };
+
+        // output values have a fixed width, so the size of the coinbase does not depend on the fees
+        let mut coinbase = Transaction::coinbase(
+            index,
+            vec![Output::new(miner.to_owned(), 0)],
+        );
+        let mut size = coinbase.encode().len();
+        let mut fees: u64 = 0;
+        let mut transactions = vec![];
+
+        /*
+        A transaction depending on another pool transaction waits for it: on a UTXO chain the outputs
+        a block spends must already be in the chain, so it waits for the next block, on an account
+        chain it may follow the transfers before it in the same block. So the best transaction
+        that fits and is valid after the ones already picked goes next, until none is left.
+        */
+        let mut candidates = self.sorted_by_fee_rate();
+        let mut accounts: HashMap<Address, Account> = HashMap::new();
+        while let Some(position) = candidates.iter().position(|entry| {
+            size + entry.size <= max_size
+                && is_ready(chain, &accounts, &entry.transaction)
+        }) {
+            let entry = candidates.remove(position);
+            size += entry.size;
+            fees = fees.saturating_add(entry.fee);
+            if chain.params.ledger == LedgerMode::Account {
+                apply_pending(chain, &mut accounts, &entry.transaction);
+            }
+            transactions.push(entry.transaction.clone());
+        }
+
+        coinbase.outputs[0].value =
+            chain.params.block_subsidy(index).saturating_add(fees);
+        transactions.insert(0, coinbase);
+
+        let mut block = Block::new(
+            index,
+            timestamp,
+            prev_block_hash,
+            transactions,
+            0,
+            chain.next_difficulty(),
+        )
+        .with_hash_algorithm(chain.params.hash_algorithm);
+
+        // every picked transaction is valid after the ones before it, so this only fails
+        // for a template that is not meant to be valid anyway
+        if let Ok(state_root) = chain.state_root_after(&block) {
+            block.state_root = state_root;
+        }
+        block
+    }
+}
+
+/*
+Function returns the accounts the transaction touches as they are after `accounts`,
+the accounts it does not hold are taken from the tip of the chain.
+*/
+fn pending_accounts(
+    chain: &Blockchain,
+    accounts: &HashMap<Address, Account>,
+    transaction: &Transaction,
+) -> HashMap<Address, Account> {
+    let senders = transaction.inputs.iter().map(account::sender_address);
+    let recipients = transaction
+        .outputs
+        .iter()
+        .map(|output| output.to_addr.clone());
+    senders
+        .chain(recipients)
+        .map(|address| {
+            let account = accounts
+                .get(&address)
+                .copied()
+                .unwrap_or_else(|| chain.account(&address));
+            (address, account)
+        })
+        .collect()
+}
+
+// moves the transaction's value between the accounts it touches, on top of `accounts`
+fn apply_pending(
+    chain: &Blockchain,
+    accounts: &mut HashMap<Address, Account>,
+    transaction: &Transaction,
+) {
+    let touched = pending_accounts(chain, accounts, transaction);
+    accounts.extend(touched);
+    account::apply_transaction(accounts, transaction);
+}
+
+// whether the transaction can go into a block after the ones holding the changes of `accounts`
+fn is_ready(
+    chain: &Blockchain,
+    accounts: &HashMap<Address, Account>,
+    transaction: &Transaction,
+) -> bool {
+    match chain.params.ledger {
+        LedgerMode::Utxo => transaction
+            .inputs
+            .iter()
+            .all(|input| chain.is_unspent(&input.prev_out)),
+        LedgerMode::Account => account::check_transaction(
+            &pending_accounts(chain, accounts, transaction),
+            transaction,
+        )
+        .is_ok(),
+    }
+}
@@ -0,0 +1,140 @@
+/*
+Definition of the Merkle tree.
+
+Instead of hashing every transaction's bytes into the block hash, the block only stores
+the root of a Merkle tree built over its transaction hashes:
+
+                root = H(H01 + H23)
+               /                   \
+        H01 = H(H0 + H1)      H23 = H(H2 + H3)
+         /        \             /        \
+       H0         H1          H2         H3      <- transaction hashes (leaves)
+
+When a level has an odd number of nodes, the last node is paired with itself, same as Bitcoin.
+Also same as Bitcoin, that makes [T0, T1, T2] and [T0, T1, T2, T2] share a root (CVE-2012-2459),
+which is why a block listing a transaction twice is invalid (see Blockchain::check_header).
+Pairs are hashed with the block's hash algorithm (see hashtable.rs), SHA-256 unless the chain picks another one.
+
+The nice property is that proving a transaction belongs to a block only needs
+the hashes along the path from the leaf to the root (the siblings), log2(n) hashes instead of
+the whole block. This is what a light client (SPV node) relies on: it only keeps
+block headers and asks full nodes for such proofs.
+*/
+
+use crate::{hashtable::HashAlgorithm, Hash};
+
+/*
+The inclusion proof of the leaf at `index`.
+siblings[0] is the sibling of the leaf, siblings[1] the sibling of their parent, and so on up to the root.
+Whether a sibling sits on the left or on the right is given by the bits of the index.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<Hash>,
+    // algorithm the tree was built with
+    pub algorithm: HashAlgorithm,
+}
+
+fn hash_pair(left: &Hash, right: &Hash, algorithm: HashAlgorithm) -> Hash {
+    let mut bytes: Vec<u8> = Vec::with_capacity(left.len() + right.len());
+    bytes.extend(left);
+    bytes.extend(right);
+    algorithm.digest(&bytes)
+}
+
+// hash every pair of nodes of one level into the level above it
+fn next_level(level: &[Hash], algorithm: HashAlgorithm) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_pair(left, right, algorithm),
+            [last] => hash_pair(last, last, algorithm),
+            _ => unreachable!("chunks(2) yields one or two nodes"),
+        })
+        .collect()
+}
+
+/*
+Function returns the root of the tree built over the given leaves.
+A block without transactions has no leaves, its root is filled with zeros.
+*/
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    merkle_root_with(leaves, HashAlgorithm::Sha256)
+}
+
+pub fn merkle_root_with(leaves: &[Hash], algorithm: HashAlgorithm) -> Hash {
+    if leaves.is_empty() {
+        return vec![0; 32];
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level, algorithm);
+    }
+    level.remove(0)
+}
+
+/*
+Function collects the siblings on the path from the leaf at index up to the root,
+returns None when there is no leaf at that index.
+*/
+pub fn merkle_proof(leaves: &[Hash], index: usize) -> Option<MerkleProof> {
+    merkle_proof_with(leaves, index, HashAlgorithm::Sha256)
+}
+
+pub fn merkle_proof_with(
+    leaves: &[Hash],
+    index: usize,
+    algorithm: HashAlgorithm,
+) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut siblings = vec![];
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        // the last node of an odd level is its own sibling
+        let sibling = if position & 1 == 0 {
+            level.get(position + 1).unwrap_or(&level[position])
+        } else {
+            &level[position - 1]
+        };
+        siblings.push(sibling.clone());
+
+        level = next_level(&level, algorithm);
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        index,
+        siblings,
+        algorithm,
+    })
+}
+
+/*
+Function re-calculates the root from the leaf and the siblings in the proof, with the proof's algorithm,
+the leaf is included only when we end up with exactly the expected root.
+*/
+pub fn verify_merkle_proof(
+    leaf: &Hash,
+    proof: &MerkleProof,
+    root: &Hash,
+) -> bool {
+    let mut current = leaf.clone();
+    let mut position = proof.index;
+    for sibling in &proof.siblings {
+        current = if position & 1 == 0 {
+            hash_pair(&current, sibling, proof.algorithm)
+        } else {
+            hash_pair(sibling, &current, proof.algorithm)
+        };
+        position /= 2;
+    }
+
+    // any bit left in the index means the proof is too short for that leaf
+    position == 0 && &current == root
+}
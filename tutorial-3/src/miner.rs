@@ -0,0 +1,271 @@
+/*
+Definition of the parallel Miner.
+
+Block::mine tries one nonce after the other on a single thread. Every nonce is an independent guess,
+so the work splits perfectly: the nonce space is cut into one disjoint range per thread,
+
+    thread 0: 0 .. n/k,   thread 1: n/k .. 2n/k,   ...,   thread k-1: (k-1)n/k .. n
+
+and every thread mines its own copy of the block in its own range (see Block::mine_range).
+The first thread that finds a solution raises a shared cancel flag, the other threads see it
+after their current batch of nonces and give up, their work is worthless once the block is found.
+
+While the threads are busy, the template they mine can go stale: a new transaction paying a better fee
+arrives, or another miner extends the chain. The caller hands newer templates to the miner over a channel
+(see Miner::mine_with_updates), which cancels the round and starts again on the new template.
+
+The miner also counts every hash its threads try, which gives its hashrate, and from the hashrate
+the expected time to find a block: a hash meets the difficulty with a chance of 1 / block_work(difficulty)
+(see block.rs), so it takes block_work(difficulty) / hashrate seconds on average.
+*/
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::block::{block_work, Block};
+
+// nonces a thread tries between two looks at the cancel flag
+const MINING_BATCH: u64 = 1_000;
+
+// how long the coordinating thread waits for a new template before checking on the threads again
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/*
+Hashes tried over some time.
+The derived comparisons compare both fields, two stats are not ordered by their hashrate.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiningStats {
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningStats {
+    // hashes per second, 0 before any time passed
+    pub fn hashrate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.hashes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /*
+    Function returns how long it takes on average to mine a block of the given difficulty at this hashrate,
+    None while there is no hashrate to go by (or the answer does not fit into a Duration).
+    */
+    pub fn expected_block_time(&self, difficulty: u128) -> Option<Duration> {
+        let hashrate = self.hashrate();
+        if hashrate <= 0.0 {
+            return None;
+        }
+        Duration::try_from_secs_f64(block_work(difficulty) as f64 / hashrate)
+            .ok()
+    }
+}
+
+// how a round of mining on one template ended
+enum Round {
+    Solved(Block),
+    // a newer template arrived
+    Refreshed(Block),
+    // the miner was stopped, the updates channel was closed, or every range was exhausted
+    Stopped,
+}
+
+/*
+The Miner only keeps counters and flags behind atomics, so it can be shared in an Arc:
+one thread mines while another one reads the stats or stops it.
+*/
+pub struct Miner {
+    threads: usize,
+    // hashes tried by all threads since the miner was created
+    hashes: AtomicU64,
+    started: Instant,
+    // once set, the miner does not mine anymore
+    stopped: AtomicBool,
+}
+
+impl Default for Miner {
+    // one thread per core
+    fn default() -> Self {
+        Self::new(
+            thread::available_parallelism().map_or(1, |threads| threads.get()),
+        )
+    }
+}
+
+impl Miner {
+    // a miner always runs at least one thread
+    pub fn new(threads: usize) -> Self {
+        Miner {
+            threads: threads.max(1),
+            hashes: AtomicU64::new(0),
+            started: Instant::now(),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // everything the miner did since it was created
+    pub fn stats(&self) -> MiningStats {
+        MiningStats {
+            hashes: self.hashes.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    /*
+    Function stops the miner for good, a running mine call returns None
+    after the current batch of nonces.
+    */
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /*
+    Function mines the block with all the threads and returns it with its nonce and hash set,
+    None when no nonce meets the difficulty or the miner was stopped.
+    */
+    pub fn mine(&self, block: Block) -> Option<Block> {
+        // the sender stays alive until the end, so no update ever arrives and nothing closes the channel
+        let (_sender, updates) = mpsc::channel();
+        self.mine_with_updates(block, &updates)
+    }
+
+    /*
+    Same as mine, but every block arriving on `updates` replaces the template being mined,
+    when several arrived in the meantime only the newest one counts.
+    Closing the channel stops the mining, the function returns None then.
+    */
+    pub fn mine_with_updates(
+        &self,
+        template: Block,
+        updates: &Receiver<Block>,
+    ) -> Option<Block> {
+        let mut template = template;
+        loop {
+            match self.mine_round(&template, updates) {
+                Round::Solved(block) => return Some(block),
+                Round::Refreshed(next) => template = next,
+                Round::Stopped => return None,
+            }
+        }
+    }
+
+    /*
+    Function runs one thread per nonce range on the template, while the calling thread
+    watches the updates. Every thread is joined before the function returns.
+    */
+    fn mine_round(&self, template: &Block, updates: &Receiver<Block>) -> Round {
+        let cancel = AtomicBool::new(false);
+        let solution: Mutex<Option<Block>> = Mutex::new(None);
+        let running = AtomicUsize::new(self.threads);
+
+        let next = thread::scope(|scope| {
+            for nonces in nonce_ranges(self.threads) {
+                let (cancel, solution, running) =
+                    (&cancel, &solution, &running);
+                scope.spawn(move || {
+                    self.mine_range(template.clone(), nonces, cancel, solution);
+                    running.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+
+            let next = loop {
+                if cancel.load(Ordering::Relaxed)
+                    || running.load(Ordering::Relaxed) == 0
+                    || self.is_stopped()
+                {
+                    break None;
+                }
+                match updates.recv_timeout(POLL_INTERVAL) {
+                    Ok(mut next) => {
+                        while let Ok(newer) = updates.try_recv() {
+                            next = newer;
+                        }
+                        break Some(next);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break None,
+                }
+            };
+            // whatever ended the round, the threads have no reason to go on
+            cancel.store(true, Ordering::Relaxed);
+            next
+        });
+
+        // a solution found while the template was being replaced is still a valid block
+        if let Some(block) = solution.into_inner().unwrap() {
+            return Round::Solved(block);
+        }
+        match next {
+            Some(next) => Round::Refreshed(next),
+            None => Round::Stopped,
+        }
+    }
+
+    // what a single thread does: mine its range in batches until it is done or cancelled
+    fn mine_range(
+        &self,
+        mut block: Block,
+        nonces: Range<u64>,
+        cancel: &AtomicBool,
+        solution: &Mutex<Option<Block>>,
+    ) {
+        let mut start = nonces.start;
+        while start < nonces.end
+            && !cancel.load(Ordering::Relaxed)
+            && !self.is_stopped()
+        {
+            let end = start.saturating_add(MINING_BATCH).min(nonces.end);
+            let solved = block.mine_range(start..end, None);
+            let tried = if solved {
+                block.nonce - start + 1
+            } else {
+                end - start
+            };
+            self.hashes.fetch_add(tried, Ordering::Relaxed);
+
+            if solved {
+                // two threads may solve the block at about the same time, the first one wins
+                if !cancel.swap(true, Ordering::Relaxed) {
+                    *solution.lock().unwrap() = Some(block);
+                }
+                return;
+            }
+            start = end;
+        }
+    }
+}
+
+/*
+Function cuts the whole nonce space into `threads` disjoint ranges of about the same size,
+the last one also takes what is left of the division.
+*/
+pub fn nonce_ranges(threads: usize) -> Vec<Range<u64>> {
+    let threads = threads.max(1) as u64;
+    let size = u64::MAX / threads;
+    (0..threads)
+        .map(|i| {
+            let end = if i + 1 == threads {
+                u64::MAX
+            } else {
+                (i + 1) * size
+            };
+            i * size..end
+        })
+        .collect()
+}
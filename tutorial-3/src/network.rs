@@ -0,0 +1,267 @@
+/*
+Definition of the wire protocol spoken between nodes (see node.rs).
+
+Nodes talk over plain TCP. Every message travels as one frame:
+
+    [u32 payload length][payload = message encoded by codec.rs]
+
+and the payload starts with a u8 tag naming the message, followed by its fields.
+
+A connection starts with a handshake, each side sends Version first and answers the other's Version with Verack:
+
+    A -> B: Version { version, block_count }
+    B -> A: Version { version, block_count }
+    A -> B: Verack
+    B -> A: Verack
+
+Peers speaking another protocol version are dropped. block_count tells the other side
+whether it is behind and should start an initial block download.
+
+After the handshake, new transactions and blocks are announced by hash only, and the receiver
+asks for the ones it does not have yet:
+
+    A -> B: Inv [Block(hash)]
+    B -> A: GetData [Block(hash)]
+    A -> B: Block(block)
+
+An initial block download goes the same way, except that it starts from a height:
+GetBlocks { from_height } is answered with an Inv of the hashes of the sender's active chain from that height on,
+at most MAX_INV_ITEMS at a time.
+
+A light client (see spv.rs) does the same handshake, but never downloads whole blocks.
+It asks for the headers from a height on, and for the Merkle proof of a transaction it was paid with:
+
+    C -> A: GetHeaders { from_height }
+    A -> C: Headers [header, ...]                           at most MAX_HEADERS
+    C -> A: GetMerkleProof { block_hash, tx_hash }
+    A -> C: MerkleProof { block_hash, tx_hash, proof }      proof is None when the block does not have the transaction
+
+Version 2 of the protocol added these four messages.
+*/
+
+use std::io::{self, Read, Write};
+
+use crate::{
+    block::{Block, BlockHeader},
+    codec::{self, Decode, DecodeError, Encode, Reader},
+    merkle::MerkleProof,
+    transactions::Transaction,
+    u32_bytes, Hash,
+};
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+// a frame larger than this is refused instead of being allocated
+pub const MAX_MESSAGE_LEN: u32 = 32 * 1024 * 1024;
+
+// most hashes announced by one Inv in reply to GetBlocks
+pub const MAX_INV_ITEMS: usize = 500;
+
+// most headers sent by one Headers in reply to GetHeaders
+pub const MAX_HEADERS: usize = 2_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InvItem {
+    Tx(Hash),
+    Block(Hash),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    // first message on a connection, block_count is the length of the sender's active chain
+    Version {
+        version: u32,
+        block_count: u32,
+    },
+    // the sender accepted the other side's Version
+    Verack,
+    // the sender has these transactions or blocks
+    Inv(Vec<InvItem>),
+    // the sender wants these transactions or blocks
+    GetData(Vec<InvItem>),
+    // the sender wants the hashes of the active chain from this height on
+    GetBlocks {
+        from_height: u32,
+    },
+    Block(Block),
+    Tx(Transaction),
+    // the sender wants the headers of the active chain from this height on
+    GetHeaders {
+        from_height: u32,
+    },
+    Headers(Vec<BlockHeader>),
+    // the sender wants the proof that the transaction is in the block
+    GetMerkleProof {
+        block_hash: Hash,
+        tx_hash: Hash,
+    },
+    MerkleProof {
+        block_hash: Hash,
+        tx_hash: Hash,
+        // None when the block is not on the active chain or does not have the transaction
+        proof: Option<MerkleProof>,
+    },
+}
+
+impl Encode for InvItem {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            InvItem::Tx(hash) => {
+                buf.push(0);
+                codec::write_bytes(buf, hash);
+            }
+            InvItem::Block(hash) => {
+                buf.push(1);
+                codec::write_bytes(buf, hash);
+            }
+        }
+    }
+}
+
+impl Decode for InvItem {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0 => Ok(InvItem::Tx(reader.read_bytes()?)),
+            1 => Ok(InvItem::Block(reader.read_bytes()?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl Encode for Message {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Message::Version {
+                version,
+                block_count,
+            } => {
+                buf.push(0);
+                buf.extend(&u32_bytes(version));
+                buf.extend(&u32_bytes(block_count));
+            }
+            Message::Verack => buf.push(1),
+            Message::Inv(items) => {
+                buf.push(2);
+                codec::write_vec(buf, items);
+            }
+            Message::GetData(items) => {
+                buf.push(3);
+                codec::write_vec(buf, items);
+            }
+            Message::GetBlocks { from_height } => {
+                buf.push(4);
+                buf.extend(&u32_bytes(from_height));
+            }
+            Message::Block(block) => {
+                buf.push(5);
+                block.encode_to(buf);
+            }
+            Message::Tx(transaction) => {
+                buf.push(6);
+                transaction.encode_to(buf);
+            }
+            Message::GetHeaders { from_height } => {
+                buf.push(7);
+                buf.extend(&u32_bytes(from_height));
+            }
+            Message::Headers(headers) => {
+                buf.push(8);
+                codec::write_vec(buf, headers);
+            }
+            Message::GetMerkleProof {
+                block_hash,
+                tx_hash,
+            } => {
+                buf.push(9);
+                codec::write_bytes(buf, block_hash);
+                codec::write_bytes(buf, tx_hash);
+            }
+            Message::MerkleProof {
+                block_hash,
+                tx_hash,
+                proof,
+            } => {
+                buf.push(10);
+                codec::write_bytes(buf, block_hash);
+                codec::write_bytes(buf, tx_hash);
+                // a u8 flag tells whether a proof follows
+                match proof {
+                    Some(proof) => {
+                        buf.push(1);
+                        proof.encode_to(buf);
+                    }
+                    None => buf.push(0),
+                }
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode_from(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0 => Ok(Message::Version {
+                version: reader.read_u32()?,
+                block_count: reader.read_u32()?,
+            }),
+            1 => Ok(Message::Verack),
+            2 => Ok(Message::Inv(reader.read_vec()?)),
+            3 => Ok(Message::GetData(reader.read_vec()?)),
+            4 => Ok(Message::GetBlocks {
+                from_height: reader.read_u32()?,
+            }),
+            5 => Ok(Message::Block(Block::decode_from(reader)?)),
+            6 => Ok(Message::Tx(Transaction::decode_from(reader)?)),
+            7 => Ok(Message::GetHeaders {
+                from_height: reader.read_u32()?,
+            }),
+            8 => Ok(Message::Headers(reader.read_vec()?)),
+            9 => Ok(Message::GetMerkleProof {
+                block_hash: reader.read_bytes()?,
+                tx_hash: reader.read_bytes()?,
+            }),
+            10 => Ok(Message::MerkleProof {
+                block_hash: reader.read_bytes()?,
+                tx_hash: reader.read_bytes()?,
+                proof: match reader.read_u8()? {
+                    0 => None,
+                    1 => Some(MerkleProof::decode_from(reader)?),
+                    tag => return Err(DecodeError::InvalidTag(tag)),
+                },
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+pub fn write_message(
+    writer: &mut impl Write,
+    message: &Message,
+) -> io::Result<()> {
+    let payload = message.encode();
+    let mut frame = u32_bytes(&(payload.len() as u32)).to_vec();
+    frame.extend(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/*
+Function blocks until a whole frame is read,
+a frame that cannot be decoded is reported as InvalidData, the connection is useless after that.
+*/
+pub fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", len),
+        ));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    Message::decode(&payload)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
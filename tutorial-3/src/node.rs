@@ -0,0 +1,766 @@
+/*
+Definition of the peer-to-peer Node.
+
+A node owns a Blockchain and a Mempool, listens for other nodes on a TCP port and keeps
+a connection to each of its peers. Everything the node reacts to arrives as a NodeEvent
+on a single channel and is handled by one loop (Node::run), so the chain and the pool
+are never shared between threads:
+
+    accept thread       --Connected-->
+    reader thread x N   --Message / Disconnected-->     Node::run  --write_message-->  peers
+    NodeHandle          --Command-->
+    miner thread        --Mined-->
+
+Every connection gets a reader thread that turns the incoming frames into NodeEvents,
+while the loop writes to the peers directly.
+
+What the loop does with the messages (see network.rs for the messages themselves):
+- after the handshake, a peer with a longer chain is asked for its blocks from our height on
+- announced transactions and blocks we do not have are requested with GetData
+- a new valid transaction or block is announced to every other peer
+- headers and Merkle proofs are served to light clients (see spv.rs)
+- a block whose parent we do not know is kept aside as an orphan while its parent is requested,
+  and connected as soon as the parent shows up, when too many orphans pile up the oldest is dropped
+- a requested block that does not arrive in time is given up on, the download goes on from our height
+
+Nothing the peers send can stop the node, whatever it refuses or loses on the way is only counted
+in NodeErrors, part of the node's status.
+
+A node started with a miner address also mines: a miner thread runs a parallel Miner (see miner.rs)
+on a block template built from the node's mempool, and the node sends it a fresh template whenever
+the chain or the mempool changes. A mined block comes back as an event and is handled like any other new block.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    block::Block,
+    blockchain::{BlockValidationErr, Blockchain, ChainParams, ReorgEvent},
+    mempool::{Mempool, MempoolError},
+    miner::{Miner, MiningStats},
+    network::{
+        read_message, write_message, InvItem, Message, MAX_HEADERS,
+        MAX_INV_ITEMS, PROTOCOL_VERSION,
+    },
+    transactions::Transaction,
+    Address, Hash,
+};
+
+// size limit of the blocks this node mines
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+
+// orphan blocks kept while waiting for their parents
+const MAX_ORPHANS: usize = 100;
+
+// how long a peer has to deliver a block we asked for, and how often that is checked
+const BLOCK_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+const REQUEST_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// a peer that is not up yet is retried this many times, this long apart
+const CONNECT_ATTEMPTS: u32 = 20;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+type PeerId = usize;
+
+pub struct NodeConfig {
+    // use port 0 to let the operating system pick a free port
+    pub listen_addr: SocketAddr,
+    pub peers: Vec<SocketAddr>,
+    pub params: ChainParams,
+    // address paid by the blocks this node mines, None for a node that does not mine
+    pub miner: Option<Address>,
+    // threads of the node's miner, ignored by a node that does not mine
+    pub mining_threads: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    // length of the active chain
+    pub block_count: usize,
+    pub tip: Hash,
+    pub mempool_len: usize,
+    pub peer_count: usize,
+    // None for a node that does not mine
+    pub mining: Option<MiningStats>,
+    pub errors: NodeErrors,
+}
+
+// what went wrong since the node started, counted instead of stopping the node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeErrors {
+    // transactions the mempool refused, the ones it already had are not counted
+    pub rejected_transactions: usize,
+    // blocks the chain refused, except the ones it already had and the orphans
+    pub rejected_blocks: usize,
+    // peers dropped because writing to them failed
+    pub lost_peers: usize,
+    // peers still unreachable after CONNECT_ATTEMPTS attempts
+    pub failed_connects: usize,
+}
+
+enum Command {
+    Connect(SocketAddr),
+    SubmitTransaction(Transaction),
+    SubmitBlock(Block),
+    Status(Sender<NodeStatus>),
+    Shutdown,
+}
+
+enum NodeEvent {
+    Connected(TcpStream),
+    Message(PeerId, Message),
+    Disconnected(PeerId),
+    ConnectFailed,
+    Command(Command),
+    Mined(Block),
+}
+
+/*
+NodeHandle lets other threads talk to a running node, it can be cloned freely.
+Once the node stopped, the commands are silently dropped.
+*/
+#[derive(Clone)]
+pub struct NodeHandle {
+    local_addr: SocketAddr,
+    sender: Sender<NodeEvent>,
+}
+
+impl NodeHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn connect(&self, addr: SocketAddr) {
+        self.send(Command::Connect(addr));
+    }
+
+    pub fn submit_transaction(&self, transaction: Transaction) {
+        self.send(Command::SubmitTransaction(transaction));
+    }
+
+    pub fn submit_block(&self, block: Block) {
+        self.send(Command::SubmitBlock(block));
+    }
+
+    // None when the node is no longer running
+    pub fn status(&self) -> Option<NodeStatus> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Command::Status(sender));
+        receiver.recv().ok()
+    }
+
+    pub fn shutdown(&self) {
+        self.send(Command::Shutdown);
+    }
+
+    fn send(&self, command: Command) {
+        let _ = self.sender.send(NodeEvent::Command(command));
+    }
+}
+
+struct Peer {
+    stream: TcpStream,
+    // set once the peer's Version was accepted
+    handshaked: bool,
+    // the longest chain we know the peer has
+    block_count: u32,
+    // blocks asked from this peer that did not arrive yet, and when they were asked for
+    requested: HashMap<Hash, Instant>,
+}
+
+pub struct Node {
+    local_addr: SocketAddr,
+    miner: Option<Address>,
+    chain: Blockchain,
+    chain_events: Receiver<ReorgEvent>,
+    mempool: Mempool,
+    peers: HashMap<PeerId, Peer>,
+    next_peer_id: PeerId,
+    // blocks waiting for their parent, keyed by the parent's hash
+    orphans: HashMap<Hash, Vec<Block>>,
+    // hashes of the blocks in orphans, oldest first
+    orphan_order: VecDeque<Hash>,
+    // the miner and the channel feeding its thread new templates, None for a node that does not mine
+    mining: Option<(Arc<Miner>, Sender<Block>)>,
+    events: Receiver<NodeEvent>,
+    sender: Sender<NodeEvent>,
+    stopped: Arc<AtomicBool>,
+    errors: NodeErrors,
+}
+
+impl Node {
+    /*
+    Function starts listening and connecting to the configured peers,
+    nothing is handled before Node::run is called though.
+    */
+    pub fn bind(config: NodeConfig) -> io::Result<Node> {
+        let listener = TcpListener::bind(config.listen_addr)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, events) = mpsc::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        spawn_acceptor(listener, sender.clone(), stopped.clone());
+        for addr in config.peers {
+            spawn_connector(addr, sender.clone(), stopped.clone());
+        }
+
+        let mining = config.miner.is_some().then(|| {
+            let miner = Arc::new(Miner::new(config.mining_threads));
+            let (templates, receiver) = mpsc::channel();
+            spawn_miner(miner.clone(), receiver, sender.clone());
+            (miner, templates)
+        });
+
+        let mut chain = Blockchain::with_params(config.params.clone());
+        let chain_events = chain.subscribe();
+        chain
+            .update_with_block(config.params.genesis_block())
+            .expect("genesis block follows its own parameters");
+
+        Ok(Node {
+            local_addr,
+            miner: config.miner,
+            chain,
+            chain_events,
+            mempool: Mempool::new(),
+            peers: HashMap::new(),
+            next_peer_id: 0,
+            orphans: HashMap::new(),
+            orphan_order: VecDeque::new(),
+            mining,
+            events,
+            sender,
+            stopped,
+            errors: NodeErrors::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn handle(&self) -> NodeHandle {
+        NodeHandle {
+            local_addr: self.local_addr,
+            sender: self.sender.clone(),
+        }
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    // runs the node on the current thread until NodeHandle::shutdown is called
+    pub fn run(mut self) {
+        self.refresh_template();
+        loop {
+            match self.events.recv_timeout(REQUEST_CHECK_INTERVAL) {
+                Ok(NodeEvent::Command(Command::Shutdown)) => break,
+                Ok(event) => self.handle_event(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.expire_requests();
+        }
+
+        self.stop();
+    }
+
+    fn handle_event(&mut self, event: NodeEvent) {
+        match event {
+            NodeEvent::Connected(stream) => self.add_peer(stream),
+            NodeEvent::Message(peer_id, message) => {
+                self.handle_message(peer_id, message)
+            }
+            NodeEvent::Disconnected(peer_id) => self.remove_peer(peer_id),
+            NodeEvent::ConnectFailed => self.errors.failed_connects += 1,
+            NodeEvent::Command(command) => self.handle_command(command),
+            NodeEvent::Mined(block) => self.accept_mined_block(block),
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Connect(addr) => {
+                spawn_connector(addr, self.sender.clone(), self.stopped.clone())
+            }
+            Command::SubmitTransaction(transaction) => {
+                self.accept_transaction(transaction, None)
+            }
+            Command::SubmitBlock(block) => self.accept_block(block, None),
+            Command::Status(reply) => {
+                let _ = reply.send(self.status());
+            }
+            // handled by run
+            Command::Shutdown => {}
+        }
+    }
+
+    fn status(&self) -> NodeStatus {
+        NodeStatus {
+            block_count: self.chain.len(),
+            tip: self
+                .chain
+                .last_block()
+                .map(|block| block.hash.clone())
+                .unwrap_or_default(),
+            mempool_len: self.mempool.len(),
+            peer_count: self.peers.len(),
+            mining: self.mining.as_ref().map(|(miner, _)| miner.stats()),
+            errors: self.errors,
+        }
+    }
+
+    fn add_peer(&mut self, stream: TcpStream) {
+        let Ok(reader) = stream.try_clone() else {
+            return;
+        };
+
+        let peer_id = self.next_peer_id;
+        self.next_peer_id += 1;
+        spawn_reader(peer_id, reader, self.sender.clone());
+
+        self.peers.insert(
+            peer_id,
+            Peer {
+                stream,
+                handshaked: false,
+                block_count: 0,
+                requested: HashMap::new(),
+            },
+        );
+        self.send(
+            peer_id,
+            &Message::Version {
+                version: PROTOCOL_VERSION,
+                block_count: self.chain.len() as u32,
+            },
+        );
+    }
+
+    fn remove_peer(&mut self, peer_id: PeerId) {
+        if let Some(peer) = self.peers.remove(&peer_id) {
+            // wakes the reader thread up if it is still blocked on the socket
+            let _ = peer.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    // a peer we can no longer write to is dropped
+    fn send(&mut self, peer_id: PeerId, message: &Message) {
+        let Some(peer) = self.peers.get_mut(&peer_id) else {
+            return;
+        };
+        if write_message(&mut peer.stream, message).is_err() {
+            self.errors.lost_peers += 1;
+            self.remove_peer(peer_id);
+        }
+    }
+
+    // announce to every peer that finished its handshake, except the one we got it from
+    fn broadcast(&mut self, item: InvItem, except: Option<PeerId>) {
+        let peer_ids: Vec<PeerId> = self
+            .peers
+            .iter()
+            .filter(|(peer_id, peer)| {
+                peer.handshaked && Some(**peer_id) != except
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        let message = Message::Inv(vec![item]);
+        for peer_id in peer_ids {
+            self.send(peer_id, &message);
+        }
+    }
+
+    fn handle_message(&mut self, peer_id: PeerId, message: Message) {
+        let Some(peer) = self.peers.get_mut(&peer_id) else {
+            return;
+        };
+
+        // nothing but the handshake is accepted from a peer we do not know yet
+        if !peer.handshaked {
+            match message {
+                Message::Version {
+                    version: PROTOCOL_VERSION,
+                    block_count,
+                } => {
+                    peer.handshaked = true;
+                    peer.block_count = block_count;
+                    self.send(peer_id, &Message::Verack);
+                    self.request_blocks(peer_id);
+                }
+                _ => self.remove_peer(peer_id),
+            }
+            return;
+        }
+
+        match message {
+            Message::Version { .. } | Message::Verack => {}
+            Message::Inv(items) => self.handle_inv(peer_id, items),
+            Message::GetData(items) => self.handle_get_data(peer_id, items),
+            Message::GetBlocks { from_height } => {
+                let hashes = self
+                    .chain
+                    .blocks
+                    .iter()
+                    .skip(from_height as usize)
+                    .take(MAX_INV_ITEMS)
+                    .map(|block| InvItem::Block(block.hash.clone()))
+                    .collect::<Vec<InvItem>>();
+                if !hashes.is_empty() {
+                    self.send(peer_id, &Message::Inv(hashes));
+                }
+            }
+            Message::Block(block) => {
+                if let Some(peer) = self.peers.get_mut(&peer_id) {
+                    peer.requested.remove(&block.hash);
+                    peer.block_count = peer.block_count.max(block.index + 1);
+                }
+                self.accept_block(block, Some(peer_id));
+                self.request_blocks(peer_id);
+            }
+            Message::Tx(transaction) => {
+                self.accept_transaction(transaction, Some(peer_id))
+            }
+            Message::GetHeaders { from_height } => {
+                let headers = self
+                    .chain
+                    .blocks
+                    .iter()
+                    .skip(from_height as usize)
+                    .take(MAX_HEADERS)
+                    .map(Block::header)
+                    .collect();
+                self.send(peer_id, &Message::Headers(headers));
+            }
+            Message::GetMerkleProof {
+                block_hash,
+                tx_hash,
+            } => {
+                let proof = self.chain.merkle_proof(&block_hash, &tx_hash);
+                self.send(
+                    peer_id,
+                    &Message::MerkleProof {
+                        block_hash,
+                        tx_hash,
+                        proof,
+                    },
+                );
+            }
+            // only light clients ask for these, a node never does
+            Message::Headers(_) | Message::MerkleProof { .. } => {}
+        }
+    }
+
+    fn handle_inv(&mut self, peer_id: PeerId, items: Vec<InvItem>) {
+        let wanted: Vec<InvItem> = items
+            .into_iter()
+            .filter(|item| match item {
+                InvItem::Tx(hash) => !self.mempool.contains(hash),
+                InvItem::Block(hash) => {
+                    !self.chain.contains_block(hash) && !self.is_orphan(hash)
+                }
+            })
+            .collect();
+        if wanted.is_empty() {
+            return;
+        }
+
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            let now = Instant::now();
+            for item in &wanted {
+                if let InvItem::Block(hash) = item {
+                    peer.requested.insert(hash.clone(), now);
+                }
+            }
+        }
+        self.send(peer_id, &Message::GetData(wanted));
+    }
+
+    fn handle_get_data(&mut self, peer_id: PeerId, items: Vec<InvItem>) {
+        for item in items {
+            let message = match item {
+                InvItem::Tx(hash) => self
+                    .mempool
+                    .get(&hash)
+                    .map(|entry| Message::Tx(entry.transaction.clone())),
+                InvItem::Block(hash) => {
+                    self.chain.get_block(&hash).cloned().map(Message::Block)
+                }
+            };
+            if let Some(message) = message {
+                self.send(peer_id, &message);
+            }
+        }
+    }
+
+    /*
+    Function continues the initial block download from a peer,
+    once the blocks asked so far arrived and the peer still has a longer chain than ours.
+    */
+    fn request_blocks(&mut self, peer_id: PeerId) {
+        let from_height = self.chain.len() as u32;
+        let Some(peer) = self.peers.get(&peer_id) else {
+            return;
+        };
+        if peer.requested.is_empty() && peer.block_count > from_height {
+            self.send(peer_id, &Message::GetBlocks { from_height });
+        }
+    }
+
+    /*
+    Function gives up on the blocks the peers did not deliver in time, a peer may never send them,
+    and the initial block download would wait for them forever. The download then goes on from our height,
+    which asks the peer again for whatever we still miss.
+    */
+    fn expire_requests(&mut self) {
+        let now = Instant::now();
+        let mut expired = vec![];
+        for (peer_id, peer) in self.peers.iter_mut() {
+            let before = peer.requested.len();
+            peer.requested.retain(|_, requested_at| {
+                now.duration_since(*requested_at) < BLOCK_REQUEST_TIMEOUT
+            });
+            if peer.requested.len() < before {
+                expired.push(*peer_id);
+            }
+        }
+        for peer_id in expired {
+            self.request_blocks(peer_id);
+        }
+    }
+
+    fn accept_transaction(
+        &mut self,
+        transaction: Transaction,
+        from: Option<PeerId>,
+    ) {
+        match self.mempool.add(&self.chain, transaction) {
+            Ok(tx_hash) => {
+                // a better template may be possible now
+                self.refresh_template();
+                self.broadcast(InvItem::Tx(tx_hash), from);
+            }
+            Err(MempoolError::Known) => {}
+            Err(_) => self.errors.rejected_transactions += 1,
+        }
+    }
+
+    /*
+    Function adds the block to the chain and announces it,
+    then connects the orphans that were waiting for it, and the ones waiting for those, and so on.
+    */
+    fn accept_block(&mut self, block: Block, from: Option<PeerId>) {
+        let mut pending = vec![block];
+
+        while let Some(block) = pending.pop() {
+            let hash = block.hash.clone();
+            let prev_block_hash = block.prev_block_hash.clone();
+
+            match self.chain.update_with_block(block.clone()) {
+                Ok(()) => {
+                    self.broadcast(InvItem::Block(hash.clone()), from);
+                    if let Some(children) = self.orphans.remove(&hash) {
+                        self.orphan_order.retain(|orphan| {
+                            children.iter().all(|child| &child.hash != orphan)
+                        });
+                        pending.extend(children);
+                    }
+                }
+                Err(BlockValidationErr::DuplicateBlock) => {}
+                // we miss an ancestor of the block, ask the peer that sent it
+                Err(BlockValidationErr::MismatchedPreviousHash) => {
+                    self.add_orphan(block);
+                    if let Some(peer_id) = from {
+                        self.send(
+                            peer_id,
+                            &Message::GetData(vec![InvItem::Block(
+                                prev_block_hash,
+                            )]),
+                        );
+                    }
+                }
+                Err(_) => self.errors.rejected_blocks += 1,
+            }
+        }
+
+        self.sync_mempool();
+    }
+
+    fn is_orphan(&self, hash: &Hash) -> bool {
+        self.orphans
+            .values()
+            .any(|blocks| blocks.iter().any(|block| &block.hash == hash))
+    }
+
+    // once there are MAX_ORPHANS, the oldest orphan makes room, its parent is unlikely to still show up
+    fn add_orphan(&mut self, block: Block) {
+        if self.is_orphan(&block.hash) {
+            return;
+        }
+        if self.orphan_order.len() >= MAX_ORPHANS {
+            self.evict_oldest_orphan();
+        }
+        self.orphan_order.push_back(block.hash.clone());
+        self.orphans
+            .entry(block.prev_block_hash.clone())
+            .or_default()
+            .push(block);
+    }
+
+    fn evict_oldest_orphan(&mut self) {
+        let Some(oldest) = self.orphan_order.pop_front() else {
+            return;
+        };
+        self.orphans.retain(|_, children| {
+            children.retain(|child| child.hash != oldest);
+            !children.is_empty()
+        });
+    }
+
+    // let the mempool follow whatever happened to the active chain
+    fn sync_mempool(&mut self) {
+        let mut changed = false;
+        while let Ok(event) = self.chain_events.try_recv() {
+            self.mempool.update_with_reorg(&self.chain, &event);
+            changed = true;
+        }
+        // the template was built on top of the old tip
+        if changed {
+            self.refresh_template();
+        }
+    }
+
+    fn accept_mined_block(&mut self, block: Block) {
+        let old_tip = self.chain.last_block().map(|block| block.hash.clone());
+        self.accept_block(block, None);
+        // a stale block leaves the tip alone, the miner still needs something to work on
+        if self.chain.last_block().map(|block| &block.hash) == old_tip.as_ref()
+        {
+            self.refresh_template();
+        }
+    }
+
+    // hand the miner thread a template built from the current tip and mempool
+    fn refresh_template(&self) {
+        let (Some(address), Some((_, templates))) = (&self.miner, &self.mining)
+        else {
+            return;
+        };
+        let template =
+            self.mempool
+                .block_template(&self.chain, address, MAX_BLOCK_SIZE);
+        let _ = templates.send(template);
+    }
+
+    fn stop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some((miner, _)) = &self.mining {
+            miner.stop();
+        }
+        // the acceptor is blocked on accept, a connection wakes it up to see the flag
+        let _ = TcpStream::connect(self.local_addr);
+
+        let peer_ids: Vec<PeerId> = self.peers.keys().copied().collect();
+        for peer_id in peer_ids {
+            self.remove_peer(peer_id);
+        }
+    }
+}
+
+fn spawn_acceptor(
+    listener: TcpListener,
+    sender: Sender<NodeEvent>,
+    stopped: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if sender.send(NodeEvent::Connected(stream)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn spawn_connector(
+    addr: SocketAddr,
+    sender: Sender<NodeEvent>,
+    stopped: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        for _ in 0..CONNECT_ATTEMPTS {
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Ok(stream) = TcpStream::connect(addr) {
+                let _ = sender.send(NodeEvent::Connected(stream));
+                return;
+            }
+            thread::sleep(CONNECT_RETRY_DELAY);
+        }
+        let _ = sender.send(NodeEvent::ConnectFailed);
+    });
+}
+
+/*
+The miner thread waits for a template, mines it (and whatever newer templates arrive meanwhile)
+and sends the mined block back to the node, then waits for the next template.
+It ends once the miner is stopped or the node dropped its end of the channel.
+*/
+fn spawn_miner(
+    miner: Arc<Miner>,
+    templates: Receiver<Block>,
+    sender: Sender<NodeEvent>,
+) {
+    thread::spawn(move || {
+        while let Ok(template) = templates.recv() {
+            let Some(block) = miner.mine_with_updates(template, &templates)
+            else {
+                return;
+            };
+            if sender.send(NodeEvent::Mined(block)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn spawn_reader(peer_id: PeerId, stream: TcpStream, sender: Sender<NodeEvent>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            match read_message(&mut reader) {
+                Ok(message) => {
+                    if sender
+                        .send(NodeEvent::Message(peer_id, message))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                Err(_) => {
+                    let _ = sender.send(NodeEvent::Disconnected(peer_id));
+                    return;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,287 @@
+/*
+Definition of the light client, the "Light Node (SPV Node)" of the notes in transactions.rs.
+
+A full node downloads every block and replays every transaction. A light client cannot afford that,
+it only downloads the block headers (see BlockHeader in block.rs) into a HeaderChain and checks
+what a header alone can prove:
+- linkage: every header points to the hash of the header before it, and sits at the next index
+- timestamps: every header is later than the header before it
+- the hash: the stored hash is the hash of the header's fields, with the chain's hash algorithm
+- proof of work: the hash meets the difficulty, and the difficulty follows the retarget rule
+  of the chain parameters, the same rule Blockchain uses
+
+Those checks cost a few hashes per block, but the light client learns nothing about the transactions.
+Simplified Payment Verification (SPV), as described in the Bitcoin paper, closes that gap for the
+transactions the client cares about: someone claiming to have paid us names the block and the transaction,
+a full node sends the Merkle proof of that transaction (see merkle.rs), and we check the proof
+against the merkle root of the header we stored ourselves. A valid proof means the transaction is in a block
+that somebody spent the proof of work on, and every header stacked on top of it is one more confirmation.
+
+What the light client cannot check is whether the transaction is valid (its inputs could be double-spent),
+it trusts that miners would not spend work on an invalid block. It also follows a single chain of headers,
+a header that does not extend its tip is refused, so a reorganization of the full node it syncs from
+shows up as an error rather than being followed.
+
+LightClient talks to a full node over the wire protocol of network.rs to fetch headers and proofs.
+*/
+
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpStream};
+
+use crate::{
+    block::{check_difficulty, BlockHeader},
+    blockchain::{BlockValidationErr, ChainParams},
+    hashtable::Hashtable,
+    merkle::{verify_merkle_proof, MerkleProof},
+    network::{read_message, write_message, Message, PROTOCOL_VERSION},
+    Hash,
+};
+
+pub struct HeaderChain {
+    pub params: ChainParams,
+    // from the genesis block up to the tip
+    headers: Vec<BlockHeader>,
+}
+
+impl HeaderChain {
+    pub fn new(params: ChainParams) -> Self {
+        HeaderChain {
+            params,
+            headers: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+
+    // a linear search from the tip, recent blocks are the ones asked about the most
+    pub fn get_header(&self, hash: &Hash) -> Option<&BlockHeader> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|header| &header.hash == hash)
+    }
+
+    /*
+    Function checks the header against the tip and appends it.
+    The checks are the ones Blockchain runs on a block's header, in the same order.
+    */
+    pub fn add_header(
+        &mut self,
+        header: BlockHeader,
+    ) -> Result<(), BlockValidationErr> {
+        match self.tip() {
+            Some(tip) => {
+                if header.prev_block_hash != tip.hash {
+                    return Err(BlockValidationErr::MismatchedPreviousHash);
+                }
+                if header.index != tip.index + 1 {
+                    return Err(BlockValidationErr::MismatchedIndex);
+                }
+            }
+            None => {
+                if header.index != 0 {
+                    return Err(BlockValidationErr::MismatchedIndex);
+                }
+                if header.prev_block_hash != vec![0; 32] {
+                    return Err(BlockValidationErr::InvalidGenesisBlockFormat);
+                }
+            }
+        }
+
+        if header.hash_algorithm != self.params.hash_algorithm {
+            return Err(BlockValidationErr::MismatchedHashAlgorithm);
+        }
+        if header.hash != header.hash() {
+            return Err(BlockValidationErr::InvalidHash);
+        }
+        if !check_difficulty(&header.hash, header.difficulty) {
+            return Err(BlockValidationErr::DifficultyNotMet);
+        }
+        if self
+            .tip()
+            .is_some_and(|tip| header.timestamp <= tip.timestamp)
+        {
+            return Err(BlockValidationErr::AchronologicalTimestamp);
+        }
+        if header.difficulty != self.next_difficulty() {
+            return Err(BlockValidationErr::MismatchedDifficulty);
+        }
+
+        self.headers.push(header);
+        Ok(())
+    }
+
+    // adds the headers in order, stops at the first invalid one
+    pub fn add_headers(
+        &mut self,
+        headers: impl IntoIterator<Item = BlockHeader>,
+    ) -> Result<(), BlockValidationErr> {
+        for header in headers {
+            self.add_header(header)?;
+        }
+        Ok(())
+    }
+
+    /*
+    Function returns the difficulty the next header must carry.
+    This is the rule of Blockchain::next_difficulty, computed from the stored headers.
+    */
+    pub fn next_difficulty(&self) -> u128 {
+        let Some(last) = self.tip() else {
+            return self.params.initial_difficulty;
+        };
+
+        let interval = self.params.retarget_interval.max(2) as usize;
+        let height = self.headers.len();
+        if !height.is_multiple_of(interval) {
+            return last.difficulty;
+        }
+
+        let first = &self.headers[height - interval];
+        let timespan = last.timestamp.saturating_sub(first.timestamp);
+        self.params.retarget(last.difficulty, timespan)
+    }
+
+    /*
+    Function returns how many blocks confirm the given block: 1 for the tip,
+    one more for every header on top of it. None for a block the chain does not have.
+    */
+    pub fn confirmations(&self, block_hash: &Hash) -> Option<u32> {
+        let header = self.get_header(block_hash)?;
+        Some(self.headers.len() as u32 - header.index)
+    }
+
+    /*
+    Function checks that the transaction is in the block, using the proof a full node sent
+    and the merkle root of the header stored here. Returns the confirmations of the block,
+    None when the block is unknown or the proof does not lead to its merkle root.
+    */
+    pub fn verify_transaction(
+        &self,
+        tx_hash: &Hash,
+        block_hash: &Hash,
+        proof: &MerkleProof,
+    ) -> Option<u32> {
+        let header = self.get_header(block_hash)?;
+        if proof.algorithm != header.hash_algorithm
+            || !verify_merkle_proof(tx_hash, proof, &header.merkle_root)
+        {
+            return None;
+        }
+        self.confirmations(block_hash)
+    }
+}
+
+/*
+Connection of a light client to one full node (see node.rs).
+The client answers the handshake, but never announces or relays anything,
+it only asks questions and waits for the answers.
+*/
+pub struct LightClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl LightClient {
+    // connects and runs the handshake, a light client claims to have no blocks
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut client = LightClient { stream, reader };
+
+        client.send(&Message::Version {
+            version: PROTOCOL_VERSION,
+            block_count: 0,
+        })?;
+        let version = client.wait_for(|message| match message {
+            Message::Version { version, .. } => Some(version),
+            _ => None,
+        })?;
+        if version != PROTOCOL_VERSION {
+            return Err(invalid_data(format!(
+                "peer speaks protocol version {}",
+                version
+            )));
+        }
+        client.send(&Message::Verack)?;
+        Ok(client)
+    }
+
+    /*
+    Function downloads the headers the chain does not have yet, until the full node has no more.
+    Returns how many headers were added. An invalid header ends the download with InvalidData,
+    the headers before it stay in the chain.
+    */
+    pub fn sync(&mut self, chain: &mut HeaderChain) -> io::Result<usize> {
+        let start = chain.len();
+        loop {
+            self.send(&Message::GetHeaders {
+                from_height: chain.len() as u32,
+            })?;
+            let headers = self.wait_for(|message| match message {
+                Message::Headers(headers) => Some(headers),
+                _ => None,
+            })?;
+            if headers.is_empty() {
+                return Ok(chain.len() - start);
+            }
+            chain.add_headers(headers).map_err(invalid_data)?;
+        }
+    }
+
+    // asks the full node for the proof that the transaction is in the block
+    pub fn merkle_proof(
+        &mut self,
+        block_hash: &Hash,
+        tx_hash: &Hash,
+    ) -> io::Result<Option<MerkleProof>> {
+        self.send(&Message::GetMerkleProof {
+            block_hash: block_hash.clone(),
+            tx_hash: tx_hash.clone(),
+        })?;
+        self.wait_for(|message| match message {
+            Message::MerkleProof {
+                block_hash: proof_block,
+                tx_hash: proof_tx,
+                proof,
+            } if &proof_block == block_hash && &proof_tx == tx_hash => {
+                Some(proof)
+            }
+            _ => None,
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        write_message(&mut self.stream, message)
+    }
+
+    // reads messages until one is picked, everything else the node sends (like its Inv announcements) is ignored
+    fn wait_for<T>(
+        &mut self,
+        mut pick: impl FnMut(Message) -> Option<T>,
+    ) -> io::Result<T> {
+        loop {
+            if let Some(value) = pick(read_message(&mut self.reader)?) {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
@@ -0,0 +1,381 @@
+/*
+Definition of the on-disk block storage.
+
+A chain directory holds two files:
+
+- blocks.dat: the append-only block file. Every block is written as one record
+    [u32 payload length][4 bytes checksum][payload = block encoded by codec.rs]
+  the checksum is the first 4 bytes of SHA-256(payload length + payload), so a record whose
+  payload was only partially written can be told apart from a complete one,
+  and a damaged length is caught like a damaged payload.
+
+- index.dat: one entry per block in height order
+    [u64 offset of the record in blocks.dat][u32 hash length][block hash]
+  which lets us read a block by height or by hash without scanning blocks.dat.
+
+Blocks are only ever appended, a record is flushed to disk before its index entry is written.
+If the process dies in the middle of an append, what is left behind is at most
+one incomplete record at the end of blocks.dat, and/or a missing index entry.
+So when the store is opened:
+1. blocks.dat is scanned record by record, an incomplete record at the very end is truncated
+   (a broken record followed by a complete one is not a crash leftover but corruption, we refuse to open,
+   even when its damaged length points past the end of the file)
+2. the index is re-built from the scan, and index.dat is re-written when it does not match
+
+PersistentBlockchain puts the store behind a Blockchain: on open every stored block is
+validated again from the genesis block, and new blocks are validated before they are written.
+The block file is a single line of blocks, so it only accepts blocks on top of the tip,
+side branches of the block tree (see blockchain.rs) are not persisted.
+*/
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    block::Block,
+    blockchain::{BlockValidationErr, Blockchain, ChainParams},
+    codec::{self, Decode, DecodeError, Encode},
+    u32_bytes, u64_bytes, Hash,
+};
+
+pub const BLOCK_FILE: &str = "blocks.dat";
+pub const INDEX_FILE: &str = "index.dat";
+
+// length + checksum in front of every record
+const RECORD_HEADER_LEN: u64 = 8;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    // a record in the middle of blocks.dat is broken, the file was modified or damaged
+    Corrupted {
+        offset: u64,
+    },
+    // a stored record passed its checksum but is not a valid encoded block
+    Decode {
+        height: u32,
+        err: DecodeError,
+    },
+    // a block read from disk, or about to be written, failed validation
+    InvalidBlock {
+        height: u32,
+        err: BlockValidationErr,
+    },
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "storage io error: {}", err),
+            StorageError::Corrupted { offset } => {
+                write!(f, "block file is corrupted at offset {}", offset)
+            }
+            StorageError::Decode { height, err } => {
+                write!(f, "block {} could not be decoded: {}", height, err)
+            }
+            StorageError::InvalidBlock { height, err } => {
+                write!(f, "block {} is invalid: {}", height, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+fn checksum(len: &[u8], payload: &[u8]) -> [u8; 4] {
+    let mut bytes = len.to_vec();
+    bytes.extend(payload);
+    let digest = crypto_hash::digest(crypto_hash::Algorithm::SHA256, &bytes);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+// the payload of the complete record at the start of data, None when there is none
+fn complete_record(data: &[u8]) -> Option<&[u8]> {
+    let header_len = RECORD_HEADER_LEN as usize;
+    let header = data.get(..header_len)?;
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let payload = data.get(header_len..header_len + len as usize)?;
+    (checksum(&header[..4], payload) == header[4..]).then_some(payload)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexEntry {
+    offset: u64,
+    hash: Hash,
+}
+
+impl IndexEntry {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = u64_bytes(&self.offset).to_vec();
+        codec::write_bytes(&mut bytes, &self.hash);
+        bytes
+    }
+}
+
+pub struct BlockStore {
+    dir: PathBuf,
+    block_file: File,
+    index_file: File,
+    // index[height] is the block at that height
+    index: Vec<IndexEntry>,
+    heights: HashMap<Hash, u32>,
+    // where the next record will be written
+    end_offset: u64,
+    // bytes of an incomplete trailing record dropped while opening
+    truncated_bytes: u64,
+}
+
+impl BlockStore {
+    /*
+    Function opens the store in the given directory, creating it when it does not exist yet,
+    and repairs whatever an interrupted append left behind.
+    */
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut block_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(BLOCK_FILE))?;
+
+        let mut data = vec![];
+        block_file.read_to_end(&mut data)?;
+        let (index, end_offset) = scan_records(&data)?;
+
+        let truncated_bytes = data.len() as u64 - end_offset;
+        if truncated_bytes > 0 {
+            block_file.set_len(end_offset)?;
+            block_file.sync_all()?;
+        }
+
+        let index_file = open_index(&dir.join(INDEX_FILE), &index)?;
+        let heights = index
+            .iter()
+            .enumerate()
+            .map(|(height, entry)| (entry.hash.clone(), height as u32))
+            .collect();
+
+        Ok(BlockStore {
+            dir,
+            block_file,
+            index_file,
+            index,
+            heights,
+            end_offset,
+            truncated_bytes,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn truncated_bytes(&self) -> u64 {
+        self.truncated_bytes
+    }
+
+    pub fn height_of(&self, hash: &Hash) -> Option<u32> {
+        self.heights.get(hash).copied()
+    }
+
+    /*
+    Function appends the block at the end of the block file and then records it in the index,
+    each write is synced before the next one starts, so a crash leaves at most one broken tail.
+    */
+    pub fn append(&mut self, block: &Block) -> Result<(), StorageError> {
+        let payload = block.encode();
+        let len = u32_bytes(&(payload.len() as u32));
+        let mut record = len.to_vec();
+        record.extend(&checksum(&len, &payload));
+        record.extend(&payload);
+
+        self.block_file.seek(SeekFrom::Start(self.end_offset))?;
+        self.block_file.write_all(&record)?;
+        self.block_file.sync_data()?;
+
+        let entry = IndexEntry {
+            offset: self.end_offset,
+            hash: block.hash.clone(),
+        };
+        self.index_file.write_all(&entry.bytes())?;
+        self.index_file.sync_data()?;
+
+        self.end_offset += record.len() as u64;
+        self.heights
+            .insert(entry.hash.clone(), self.index.len() as u32);
+        self.index.push(entry);
+
+        Ok(())
+    }
+
+    pub fn read_block(
+        &self,
+        height: u32,
+    ) -> Result<Option<Block>, StorageError> {
+        let Some(entry) = self.index.get(height as usize) else {
+            return Ok(None);
+        };
+
+        let mut file = &self.block_file;
+        let mut header = [0u8; RECORD_HEADER_LEN as usize];
+        file.seek(SeekFrom::Start(entry.offset))?;
+        file.read_exact(&mut header)?;
+
+        let len =
+            u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let mut payload = vec![0u8; len as usize];
+        file.read_exact(&mut payload)?;
+
+        Block::decode(&payload)
+            .map(Some)
+            .map_err(|err| StorageError::Decode { height, err })
+    }
+
+    pub fn read_block_by_hash(
+        &self,
+        hash: &Hash,
+    ) -> Result<Option<Block>, StorageError> {
+        match self.height_of(hash) {
+            Some(height) => self.read_block(height),
+            None => Ok(None),
+        }
+    }
+}
+
+/*
+Function walks through the records of the block file and returns the index built from them,
+together with the offset where the last complete record ends.
+*/
+fn scan_records(data: &[u8]) -> Result<(Vec<IndexEntry>, u64), StorageError> {
+    let mut index = vec![];
+    let mut offset = 0usize;
+
+    while offset < data.len() {
+        let Some(payload) = complete_record(&data[offset..]) else {
+            /*
+            The header or the payload was not completely written, or does not match its checksum.
+            That is a torn write only when it is the last thing in the file: an append that died
+            leaves nothing complete behind it. A complete record further on means the broken one
+            was damaged later, a damaged length may even point past the end of the file.
+            */
+            let followed = (offset + 1..data.len())
+                .any(|start| complete_record(&data[start..]).is_some());
+            if followed {
+                return Err(StorageError::Corrupted {
+                    offset: offset as u64,
+                });
+            }
+            break;
+        };
+
+        let height = index.len() as u32;
+        let block = Block::decode(payload)
+            .map_err(|err| StorageError::Decode { height, err })?;
+        index.push(IndexEntry {
+            offset: offset as u64,
+            hash: block.hash,
+        });
+
+        offset += RECORD_HEADER_LEN as usize + payload.len();
+    }
+
+    Ok((index, offset as u64))
+}
+
+/*
+Function opens the index file and checks it against the index re-built from the block file,
+the index is only a shortcut into blocks.dat, so whenever they disagree it is simply written again.
+*/
+fn open_index(path: &Path, index: &[IndexEntry]) -> Result<File, StorageError> {
+    let stored = fs::read(path).unwrap_or_default();
+    let expected: Vec<u8> =
+        index.iter().flat_map(|entry| entry.bytes()).collect();
+
+    if stored != expected {
+        let mut file = File::create(path)?;
+        file.write_all(&expected)?;
+        file.sync_all()?;
+    }
+
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    Ok(file)
+}
+
+/*
+A Blockchain whose blocks are kept in a BlockStore.
+Opening it replays every stored block through update_with_block,
+so data that was modified on disk is rejected instead of trusted.
+*/
+pub struct PersistentBlockchain {
+    chain: Blockchain,
+    store: BlockStore,
+}
+
+impl PersistentBlockchain {
+    pub fn open(
+        dir: impl AsRef<Path>,
+        params: ChainParams,
+    ) -> Result<Self, StorageError> {
+        let store = BlockStore::open(dir)?;
+        let mut chain = Blockchain::with_params(params);
+
+        for height in 0..store.len() as u32 {
+            let block = store
+                .read_block(height)?
+                .expect("height is inside the store");
+            chain
+                .update_with_block(block)
+                .map_err(|err| StorageError::InvalidBlock { height, err })?;
+        }
+
+        Ok(PersistentBlockchain { chain, store })
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    pub fn store(&self) -> &BlockStore {
+        &self.store
+    }
+
+    /*
+    Function validates the block first, writes it to disk second, and only then
+    lets the in-memory chain accept it, so the disk never holds a block the chain would refuse.
+    */
+    pub fn update_with_block(
+        &mut self,
+        block: Block,
+    ) -> Result<(), StorageError> {
+        let changes = self.chain.check_block(&block).map_err(|err| {
+            StorageError::InvalidBlock {
+                height: block.index,
+                err,
+            }
+        })?;
+        self.store.append(&block)?;
+        self.chain.apply_block(block, changes);
+
+        Ok(())
+    }
+}
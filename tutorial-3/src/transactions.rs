@@ -1,7 +1,12 @@
 use std::collections::HashSet;
 use std::fmt::{self, Debug, Formatter};
 
-use crate::{block::Block, hashtable::Hashtable, u64_bytes, Address, Hash};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::Block, hashtable::Hashtable, u32_bytes, u64_bytes, wallet, Address,
+    Hash,
+};
 
 /*
 In a blockchain, a transaction represents a record of a state change, typically a transfer of value (liek cryptocurrency or tokens).
@@ -90,6 +95,7 @@ Maybe we can divide into different perspecitve by roles?
 > And what a user need to validate during the process of he/she trying to purchasing a coin in the blockchain?
 */
 
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Output {
     // operator's address
     pub to_addr: Address,
@@ -120,11 +126,122 @@ impl Hashtable for Output {
     }
 }
 
+/*
+An OutPoint points at one output of a previous transaction:
+the hash of that transaction, and the position of the output in its outputs vector.
+This pair is unique across the whole chain, so it is the key of the blockchain's UTXO set.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub tx_hash: Hash,
+    pub index: u32,
+}
+
+impl OutPoint {
+    pub fn new(tx_hash: Hash, index: u32) -> Self {
+        OutPoint { tx_hash, index }
+    }
+
+    /*
+    The coinbase does not spend anything, so its only input points at the "null" transaction hash.
+    Like Bitcoin's BIP34 we put the block height into the index, otherwise two coinbases paying
+    the same value to the same miner would end up with the same transaction hash.
+    */
+    pub fn coinbase(height: u32) -> Self {
+        OutPoint {
+            tx_hash: vec![0; 32],
+            index: height,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.tx_hash.iter().all(|byte| *byte == 0)
+    }
+}
+
+impl Hashtable for OutPoint {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+        bytes.extend(&self.tx_hash);
+        bytes.extend(&u32_bytes(&self.index));
+
+        bytes
+    }
+}
+
+/*
+An Input spends the output referenced by prev_out.
+
+- value: the value of the spent output. The input repeats it so that the signature
+  commits to the amount being spent (Bitcoin does the same since BIP143),
+  and the blockchain checks it against the value stored in its UTXO set.
+- public_key: the key whose address (see wallet::public_key_to_address) must match
+  the spent output's to_addr, which proves the spender is the receiver of that output.
+- signature: signature of the transaction's signing_bytes made with the private key of public_key.
+*/
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    pub prev_out: OutPoint,
+    pub value: u64,
+    pub signature: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+impl Input {
+    // an unsigned input, signature and public_key are filled by Wallet::sign_transaction
+    pub fn new(prev_tx_hash: Hash, prev_output_index: u32, value: u64) -> Self {
+        Input {
+            prev_out: OutPoint::new(prev_tx_hash, prev_output_index),
+            value,
+            signature: vec![],
+            public_key: vec![],
+        }
+    }
+
+    /*
+    An unsigned input of an account transfer (see account.rs), there is no output to point at,
+    so prev_out carries the sender's address (as bytes) and the sender's nonce instead.
+    The value is everything the sender pays: the outputs plus the fee.
+    */
+    pub fn account(sender: &str, nonce: u32, value: u64) -> Self {
+        Input::new(sender.as_bytes().to_vec(), nonce, value)
+    }
+
+    pub fn coinbase(height: u32) -> Self {
+        Input {
+            prev_out: OutPoint::coinbase(height),
+            value: 0,
+            signature: vec![],
+            public_key: vec![],
+        }
+    }
+
+    // the part of the input that is covered by the signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+        bytes.extend(self.prev_out.bytes());
+        bytes.extend(&u64_bytes(&self.value));
+
+        bytes
+    }
+}
+
+impl Hashtable for Input {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.signing_bytes();
+        bytes.extend(&self.signature);
+        bytes.extend(&self.public_key);
+
+        bytes
+    }
+}
+
 /*
 Define Transaction
 */
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
-    pub inputs: Vec<Output>,
+    pub inputs: Vec<Input>,
     pub outputs: Vec<Output>,
 }
 
@@ -132,7 +249,7 @@ pub struct Transaction {
 Add functions associated with struct Transaction
 fun1: input_value: accumulate each input item's value together, to calculate total spending value in current Transaction.
 fun2: output_value: accumuate each output item's value together, to calculate total receiving value in current Transaction.
-fun3: input_hashes: traverse each Output item that stores in vector of Vec<Output>, and get its hash value append to HashSet
+fun3: input_hashes: traverse each Input item that stores in vector of Vec<Input>, and get its hash value append to HashSet
 fun4: output_hashes: traverse each Output item that stores in vector of Vec<Output>, and get its hash value append to HashSet
 */
 impl Transaction {
@@ -140,16 +257,33 @@ impl Transaction {
      * here we provide an implementaion of creating a new instance of the Transaction struct.
      * refering to the implemnetaiton of the new function that defined in the Block.
      */
-    pub fn new(inputs: Vec<Output>, outputs: Vec<Output>) -> Self {
+    pub fn new(inputs: Vec<Input>, outputs: Vec<Output>) -> Self {
         Transaction { inputs, outputs }
     }
 
+    // the coinbase of the block at the given height, paying the newly minted coins to outputs
+    pub fn coinbase(height: u32, outputs: Vec<Output>) -> Self {
+        Transaction {
+            inputs: vec![Input::coinbase(height)],
+            outputs,
+        }
+    }
+
+    /*
+      The values come from whoever built the transaction, a plain sum of u64::MAX and 1 would panic
+      in a debug build and wrap around to 0 in a release build, letting the outputs look cheaper than they are.
+      The sums saturate instead: a saturated output value is more than any real input value can cover.
+    */
     pub fn input_value(&self) -> u64 {
-        self.inputs.iter().map(|input| input.value).sum()
+        self.inputs
+            .iter()
+            .fold(0, |total, input| total.saturating_add(input.value))
     }
 
     pub fn output_value(&self) -> u64 {
-        self.outputs.iter().map(|output| output.value).sum()
+        self.outputs
+            .iter()
+            .fold(0, |total, output| total.saturating_add(output.value))
     }
 
     pub fn input_hashes(&self) -> HashSet<Hash> {
@@ -171,7 +305,7 @@ impl Transaction {
       The purpose of coinbase is to reward the miner(or validator) of the block with newly created coins and possibly include transaction fees.
 
       - Coinbase transactions: with its inputs(spending) empty, this introduces new coins to the system(and the new coins are the reward of the miner).
-            - inputs: None
+            - inputs: a single input that points at the null transaction hash (see OutPoint::coinbase), it spends nothing.
             - outputs: transfers the newly minted coins to the miner's address.
 
       - Regular transactions: with both non-empty inputs(spending) and outputs(receiving),
@@ -180,23 +314,90 @@ impl Transaction {
 
     */
     pub fn is_coinbase(&self) -> bool {
-        self.inputs.len() == 0
+        self.inputs.len() == 1 && self.inputs[0].prev_out.is_null()
+    }
+
+    // the height written into the coinbase input, None for regular transactions
+    pub fn coinbase_height(&self) -> Option<u32> {
+        if self.is_coinbase() {
+            Some(self.inputs[0].prev_out.index)
+        } else {
+            None
+        }
     }
 
+    /*
+      Signing bytes are what every input's signature signs: which outputs are spent, their values,
+      and where the coins go. Signatures and public keys are left out, because a signature
+      cannot sign itself, so each input can be signed independently of the others.
+    */
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+        bytes.extend(
+            self.inputs
+                .iter()
+                .flat_map(|item| item.signing_bytes())
+                .collect::<Vec<u8>>(),
+        );
+        bytes.extend(
+            self.outputs
+                .iter()
+                .flat_map(|item| item.bytes())
+                .collect::<Vec<u8>>(),
+        );
+
+        bytes
+    }
+
+    /*
+      The fee is what the sender leaves behind for the miner: the spending value that is not
+      sent to any output. A regular transaction must never create more value than it spends,
+      so when the outputs exceed the inputs there is no valid fee and we return None.
+      A coinbase spends nothing, its outputs are newly minted coins instead of fees.
+    */
+    pub fn fee(&self) -> Option<u64> {
+        if self.is_coinbase() {
+            return Some(0);
+        }
+        self.input_value().checked_sub(self.output_value())
+    }
+
+    /*
+      These are the checks that can be done by looking at the transaction alone,
+      whether the inputs are still unspent can only be answered by the blockchain's UTXO set.
+      1. the same output should not be spent twice inside one transaction
+      2. the transaction should not spend more than its inputs provide
+      3. every input should carry a signature made by its public key
+    */
     pub fn is_validate(&self) -> bool {
-        // todo: add more validation details here to verify
-        // 1. whether this transaction is validated
-        // 2. whether the inputs and outputs in the scope fo the transaction will be
-        //    ready to be calcuated as expeted.
-        true
+        if self.is_coinbase() {
+            return true;
+        }
+        let signing_bytes = self.signing_bytes();
+        self.input_outpoints().len() == self.inputs.len()
+            && self.fee().is_some()
+            && self.inputs.iter().all(|input| {
+                wallet::verify_signature(
+                    &input.public_key,
+                    &signing_bytes,
+                    &input.signature,
+                )
+            })
+    }
+
+    pub fn input_outpoints(&self) -> HashSet<OutPoint> {
+        self.inputs
+            .iter()
+            .map(|input| input.prev_out.clone())
+            .collect::<HashSet<OutPoint>>()
     }
 
     pub fn input_total_value(&self) -> u64 {
-        self.inputs.iter().map(|item| item.value).sum()
+        self.input_value()
     }
 
     pub fn output_total_value(&self) -> u64 {
-        self.outputs.iter().map(|item| item.value).sum()
+        self.output_value()
     }
 }
 
@@ -226,8 +427,8 @@ impl Hashtable for Transaction {
     fn bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
 
-        // here we iterate each element:Output that stores in vector of input: Vec<Output>
-        // and invoke each element:Output's hash bytes function to get its bytes vector
+        // here we iterate each element:Input that stores in vector of input: Vec<Input>
+        // and invoke each element:Input's hash bytes function to get its bytes vector
         // and then append the vectors to bytes: Vec<u8>
         bytes.extend(
             self.inputs
@@ -0,0 +1,111 @@
+/*
+Definition of the Wallet.
+
+A wallet holds an Ed25519 key pair. The private (signing) key never leaves the wallet,
+while the public key is shared inside the inputs of the transactions it signs.
+
+The address is what other users send coins to, it is derived from the public key:
+address = hex(first 20 bytes of SHA-256(public key))
+So an address reveals nothing about the key until its owner spends from it,
+and anyone can check that a public key belongs to an address by hashing it again.
+*/
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+
+use crate::{transactions::Transaction, Address};
+
+// length of an address in bytes before it is hex-encoded
+pub const ADDRESS_LEN: usize = 20;
+
+pub struct Wallet {
+    signing_key: SigningKey,
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wallet {
+    // generate a brand new key pair from the operating system's random source
+    pub fn new() -> Self {
+        Wallet {
+            signing_key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    // restore a wallet from its 32 bytes secret key
+    pub fn from_secret_bytes(secret: &[u8; 32]) -> Self {
+        Wallet {
+            signing_key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.signing_key.to_bytes()
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        self.signing_key.verifying_key().to_bytes().to_vec()
+    }
+
+    pub fn address(&self) -> Address {
+        public_key_to_address(&self.public_key())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+
+    /*
+    Function signs every input of the transaction with this wallet's key.
+    All inputs sign the same signing bytes, so the order in which they are signed does not matter,
+    and a transaction spending outputs of different owners can be signed by each of them in turn
+    through sign_transaction_input.
+    */
+    pub fn sign_transaction(&self, transaction: &mut Transaction) {
+        for index in 0..transaction.inputs.len() {
+            self.sign_transaction_input(transaction, index);
+        }
+    }
+
+    pub fn sign_transaction_input(
+        &self,
+        transaction: &mut Transaction,
+        index: usize,
+    ) {
+        let signature = self.sign(&transaction.signing_bytes());
+        let input = &mut transaction.inputs[index];
+        input.signature = signature;
+        input.public_key = self.public_key();
+    }
+}
+
+pub fn public_key_to_address(public_key: &[u8]) -> Address {
+    let digest =
+        crypto_hash::digest(crypto_hash::Algorithm::SHA256, public_key);
+    hex::encode(&digest[..ADDRESS_LEN])
+}
+
+/*
+Function returns false instead of an error for malformed keys or signatures,
+for the caller any of them just means the spend is not authorized.
+*/
+pub fn verify_signature(
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> bool {
+    let Ok(public_key) = <[u8; 32]>::try_from(public_key) else {
+        return false;
+    };
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else {
+        return false;
+    };
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return false;
+    };
+    verifying_key.verify_strict(message, &signature).is_ok()
+}
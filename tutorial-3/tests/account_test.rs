@@ -0,0 +1,423 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use blockchain::{
+        account::{self, Account},
+        block::Block,
+        blockchain::{BlockValidationErr, Blockchain, ChainParams, LedgerMode},
+        hashtable::{HashAlgorithm, Hashtable},
+        mempool::Mempool,
+        transactions::{Input, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    // every hash satisfies the maximum difficulty, so mining finds a nonce right away
+    const EASY_DIFFICULTY: u128 = u128::MAX;
+
+    const MAX_BLOCK_SIZE: usize = 100_000;
+
+    fn gen_chain(ledger: LedgerMode) -> Blockchain {
+        Blockchain::with_params(ChainParams {
+            initial_subsidy: 100,
+            initial_difficulty: EASY_DIFFICULTY,
+            ledger,
+            ..ChainParams::default()
+        })
+    }
+
+    // mines the transactions on top of the tip through a block template, paying `miner`
+    fn mine_block(
+        chain: &mut Blockchain,
+        miner: &str,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let mut mempool = Mempool::new();
+        for transaction in transactions {
+            mempool.add(chain, transaction).unwrap();
+        }
+        let mut block = mempool.block_template(chain, miner, MAX_BLOCK_SIZE);
+        assert!(block.mine());
+        chain.update_with_block(block.clone()).unwrap();
+        block
+    }
+
+    /*
+    Block on top of the tip without going through the mempool, so it may carry invalid transactions.
+    The coinbase only claims the subsidy, and the state root is the one the chain expects when there is one.
+    */
+    fn gen_block(chain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+        let tip = chain.last_block().unwrap();
+        let coinbase = Transaction::coinbase(
+            tip.index + 1,
+            vec![Output::new("miner_address".to_owned(), 100)],
+        );
+        let mut block = Block::new(
+            tip.index + 1,
+            tip.timestamp + 1,
+            tip.hash.clone(),
+            [vec![coinbase], transactions].concat(),
+            0,
+            EASY_DIFFICULTY,
+        );
+        block.state_root =
+            chain.state_root_after(&block).unwrap_or(vec![0; 32]);
+        block.hash = block.hash();
+        block
+    }
+
+    /*
+    The same payment on either ledger: on a UTXO chain it spends every output of the sender
+    and sends the rest back as change, on an account chain it carries the sender's nonce.
+    */
+    fn gen_transfer(
+        chain: &Blockchain,
+        from: &Wallet,
+        to: &str,
+        value: u64,
+        fee: u64,
+    ) -> Transaction {
+        let mut transaction = match chain.params.ledger {
+            LedgerMode::Utxo => {
+                let unspent = chain.unspent_outputs_of(&from.address());
+                let total: u64 =
+                    unspent.iter().map(|(_, output)| output.value).sum();
+                let inputs = unspent
+                    .iter()
+                    .map(|(out_point, output)| {
+                        Input::new(
+                            out_point.tx_hash.clone(),
+                            out_point.index,
+                            output.value,
+                        )
+                    })
+                    .collect();
+                let mut outputs = vec![Output::new(to.to_owned(), value)];
+                if total > value + fee {
+                    outputs
+                        .push(Output::new(from.address(), total - value - fee));
+                }
+                Transaction::new(inputs, outputs)
+            }
+            LedgerMode::Account => {
+                let nonce = chain.account(&from.address()).nonce;
+                Transaction::new(
+                    vec![Input::account(&from.address(), nonce, value + fee)],
+                    vec![Output::new(to.to_owned(), value)],
+                )
+            }
+        };
+        from.sign_transaction(&mut transaction);
+        transaction
+    }
+
+    // account chain whose genesis block pays alice 100 coins
+    fn gen_account_chain() -> (Blockchain, Wallet) {
+        let alice = Wallet::new();
+        let mut chain = gen_chain(LedgerMode::Account);
+        mine_block(&mut chain, &alice.address(), vec![]);
+        (chain, alice)
+    }
+
+    #[test]
+    fn test_same_workload_under_both_ledgers() {
+        for ledger in [LedgerMode::Utxo, LedgerMode::Account] {
+            let alice = Wallet::new();
+            let bob = Wallet::new();
+            let mut chain = gen_chain(ledger);
+
+            mine_block(&mut chain, &alice.address(), vec![]);
+            let transfer = gen_transfer(&chain, &alice, &bob.address(), 60, 10);
+            mine_block(&mut chain, "miner_address", vec![transfer]);
+
+            // two senders in the same block
+            let transfers = vec![
+                gen_transfer(&chain, &bob, "carol", 20, 5),
+                gen_transfer(&chain, &alice, "carol", 10, 0),
+            ];
+            mine_block(&mut chain, "miner_address", transfers);
+
+            assert_eq!(chain.balance(&alice.address()), 20, "{:?}", ledger);
+            assert_eq!(chain.balance(&bob.address()), 35, "{:?}", ledger);
+            assert_eq!(chain.balance("carol"), 30, "{:?}", ledger);
+            assert_eq!(chain.balance("miner_address"), 215, "{:?}", ledger);
+
+            let report = chain.audit();
+            assert!(report.is_clean(), "{:?}", report.findings);
+            assert_eq!(report.total_supply, 300);
+
+            let tip = chain.last_block().unwrap();
+            match ledger {
+                LedgerMode::Utxo => {
+                    assert!(chain.accounts().is_empty());
+                    assert_eq!(tip.state_root, vec![0; 32]);
+                }
+                LedgerMode::Account => {
+                    assert!(chain.unspent_outputs().is_empty());
+                    assert_eq!(chain.account(&alice.address()).nonce, 2);
+                    assert_eq!(chain.account(&bob.address()).nonce, 1);
+                    assert_eq!(chain.account("carol").nonce, 0);
+                    assert_eq!(tip.state_root, chain.state_root());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reject_replayed_transfer() {
+        let (mut chain, alice) = gen_account_chain();
+        let transfer = gen_transfer(&chain, &alice, "bob", 60, 10);
+        assert_eq!(chain.verify_transaction(&transfer), Ok(10));
+
+        // the same transfer twice inside one block, refused before its nonce is even looked at
+        let block = gen_block(&chain, vec![transfer.clone(), transfer.clone()]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::DuplicateTransaction)
+        );
+
+        // a transfer that skips a nonce
+        let mut skipped = Transaction::new(
+            vec![Input::account(&alice.address(), 1, 10)],
+            vec![Output::new("bob".to_owned(), 10)],
+        );
+        alice.sign_transaction(&mut skipped);
+        assert_eq!(
+            chain.verify_transaction(&skipped),
+            Err(BlockValidationErr::InvalidNonce)
+        );
+
+        // once the transfer is mined, the signed bytes are worthless
+        mine_block(&mut chain, "miner_address", vec![transfer.clone()]);
+        assert_eq!(chain.account(&alice.address()).nonce, 1);
+        assert_eq!(
+            chain.verify_transaction(&transfer),
+            Err(BlockValidationErr::InvalidNonce)
+        );
+        let block = gen_block(&chain, vec![transfer]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidNonce)
+        );
+
+        // and the nonce the replay skipped is now the valid one
+        assert_eq!(chain.verify_transaction(&skipped), Ok(0));
+    }
+
+    #[test]
+    fn test_reject_invalid_transfer() {
+        let (chain, alice) = gen_account_chain();
+        let bob = Wallet::new();
+
+        let transfer = gen_transfer(&chain, &alice, "bob", 100, 1);
+        assert_eq!(
+            chain.verify_transaction(&transfer),
+            Err(BlockValidationErr::InsufficientBalance)
+        );
+
+        // bob signs for alice's account
+        let mut stolen = Transaction::new(
+            vec![Input::account(&alice.address(), 0, 50)],
+            vec![Output::new(bob.address(), 50)],
+        );
+        bob.sign_transaction(&mut stolen);
+        assert_eq!(
+            chain.verify_transaction(&stolen),
+            Err(BlockValidationErr::MismatchedPublicKey)
+        );
+
+        let mut tampered = gen_transfer(&chain, &alice, "bob", 50, 0);
+        tampered.outputs[0].to_addr = bob.address();
+        assert_eq!(
+            chain.verify_transaction(&tampered),
+            Err(BlockValidationErr::InvalidSignature)
+        );
+
+        let mut overpaid = Transaction::new(
+            vec![Input::account(&alice.address(), 0, 50)],
+            vec![Output::new("bob".to_owned(), 60)],
+        );
+        alice.sign_transaction(&mut overpaid);
+        assert_eq!(
+            chain.verify_transaction(&overpaid),
+            Err(BlockValidationErr::InsufficientInputValue)
+        );
+
+        // an account transfer has exactly one sender
+        let mut two_senders = Transaction::new(
+            vec![
+                Input::account(&alice.address(), 0, 10),
+                Input::account(&alice.address(), 1, 10),
+            ],
+            vec![Output::new("bob".to_owned(), 20)],
+        );
+        alice.sign_transaction(&mut two_senders);
+        assert_eq!(
+            chain.verify_transaction(&two_senders),
+            Err(BlockValidationErr::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_state_root() {
+        let (chain, alice) = gen_account_chain();
+        let transfer = gen_transfer(&chain, &alice, "bob", 60, 10);
+
+        // a block that keeps the state root of its parent
+        let mut block = gen_block(&chain, vec![transfer.clone()]);
+        assert_ne!(block.state_root, chain.state_root());
+        block.state_root = chain.state_root();
+        block.hash = block.hash();
+        assert_eq!(
+            chain.verify_block(&block),
+            Err(BlockValidationErr::InvalidStateRoot)
+        );
+
+        // a UTXO chain has no accounts, so its blocks carry no state root
+        let mut chain = gen_chain(LedgerMode::Utxo);
+        mine_block(&mut chain, &alice.address(), vec![]);
+        let mut block = gen_block(&chain, vec![]);
+        assert_eq!(block.state_root, vec![0; 32]);
+        block.state_root = vec![1; 32];
+        block.hash = block.hash();
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidStateRoot)
+        );
+
+        // the root covers every field of every account, whatever order they were inserted in
+        let accounts: HashMap<String, Account> = [
+            (
+                "alice",
+                Account {
+                    balance: 30,
+                    nonce: 1,
+                },
+            ),
+            (
+                "bob",
+                Account {
+                    balance: 60,
+                    nonce: 0,
+                },
+            ),
+        ]
+        .map(|(address, account)| (address.to_owned(), account))
+        .into();
+        let root = account::state_root(&accounts, HashAlgorithm::Sha256);
+        let mut changed = accounts.clone();
+        changed.get_mut("alice").unwrap().nonce = 2;
+        assert_ne!(account::state_root(&changed, HashAlgorithm::Sha256), root);
+        assert_ne!(
+            account::state_root(&accounts, HashAlgorithm::Keccak256),
+            root
+        );
+        assert_eq!(
+            account::state_root(&HashMap::new(), HashAlgorithm::Sha256),
+            vec![0; 32]
+        );
+    }
+
+    #[test]
+    fn test_reorg_restores_accounts() {
+        let (mut chain, alice) = gen_account_chain();
+        let mut other = gen_chain(LedgerMode::Account);
+        other
+            .update_with_block(chain.last_block().unwrap().clone())
+            .unwrap();
+
+        let transfer = gen_transfer(&chain, &alice, "bob", 60, 10);
+        mine_block(&mut chain, "miner_address", vec![transfer.clone()]);
+        assert_eq!(chain.balance("bob"), 60);
+
+        // another miner builds a heavier branch without the transfer
+        let branch = [
+            mine_block(&mut other, "other_miner", vec![]),
+            mine_block(&mut other, "other_miner", vec![]),
+        ];
+        for block in branch {
+            chain.update_with_block(block).unwrap();
+        }
+        assert_eq!(chain.last_block(), other.last_block());
+
+        assert_eq!(
+            chain.account(&alice.address()),
+            Account {
+                balance: 100,
+                nonce: 0
+            }
+        );
+        assert!(!chain.accounts().contains_key("bob"));
+        assert!(!chain.accounts().contains_key("miner_address"));
+        assert_eq!(chain.balance("other_miner"), 200);
+        assert_eq!(chain.state_root(), other.state_root());
+        assert!(chain.audit().is_clean());
+
+        // the transfer is valid again on the new branch
+        assert_eq!(chain.verify_transaction(&transfer), Ok(10));
+    }
+
+    #[test]
+    fn test_reorg_readmits_dependent_transfers() {
+        let (mut chain, alice) = gen_account_chain();
+        let events = chain.subscribe();
+        let mut other = gen_chain(LedgerMode::Account);
+        other
+            .update_with_block(chain.last_block().unwrap().clone())
+            .unwrap();
+
+        // alice sends twice, and bob passes on what alice sent him, all in one block
+        let bob = Wallet::new();
+        let first = gen_transfer(&chain, &alice, &bob.address(), 50, 10);
+        let mut second = Transaction::new(
+            vec![Input::account(&alice.address(), 1, 30)],
+            vec![Output::new("carol".to_owned(), 10)],
+        );
+        alice.sign_transaction(&mut second);
+        let mut passed_on = Transaction::new(
+            vec![Input::account(&bob.address(), 0, 50)],
+            vec![Output::new("carol".to_owned(), 20)],
+        );
+        bob.sign_transaction(&mut passed_on);
+
+        // the later transfers pay higher fees, but cannot go before the first one
+        let block = mine_block(
+            &mut chain,
+            "miner_address",
+            vec![first.clone(), second.clone(), passed_on.clone()],
+        );
+        assert_eq!(block.transactions[1], first);
+        assert_eq!(block.transactions.len(), 4);
+        assert_eq!(chain.balance("carol"), 30);
+
+        // another miner builds a heavier branch without the transfers
+        let mut mempool = Mempool::new();
+        let branch = [
+            mine_block(&mut other, "other_miner", vec![]),
+            mine_block(&mut other, "other_miner", vec![]),
+        ];
+        for block in branch {
+            chain.update_with_block(block).unwrap();
+        }
+        for event in events.try_iter() {
+            mempool.update_with_reorg(&chain, &event);
+        }
+
+        // all of them are back, in the order the block had them
+        let readmitted: Vec<&Transaction> = mempool
+            .sorted_by_arrival()
+            .into_iter()
+            .map(|entry| &entry.transaction)
+            .collect();
+        let mined: Vec<&Transaction> = block.transactions[1..].iter().collect();
+        assert_eq!(readmitted, mined);
+
+        // and the next block on the new branch mines them again
+        let mut block =
+            mempool.block_template(&chain, "miner_address", MAX_BLOCK_SIZE);
+        assert!(block.mine());
+        chain.update_with_block(block).unwrap();
+        assert_eq!(chain.balance("carol"), 30);
+        assert_eq!(chain.account(&alice.address()).nonce, 2);
+    }
+}
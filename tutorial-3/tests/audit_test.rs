@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        audit::AuditCheck,
+        block::Block,
+        blockchain::{BlockValidationErr, Blockchain, ChainParams},
+        hashtable::Hashtable,
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    // every hash satisfies the maximum difficulty, so blocks are valid without mining
+    const EASY_DIFFICULTY: u128 = u128::MAX;
+
+    fn gen_block_on(parent: &Block, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(
+            parent.index + 1,
+            parent.timestamp + 1,
+            parent.hash.clone(),
+            transactions,
+            0,
+            EASY_DIFFICULTY,
+        );
+        block.hash = block.hash();
+        block
+    }
+
+    fn gen_coinbase(height: u32, value: u64) -> Transaction {
+        Transaction::coinbase(
+            height,
+            vec![Output::new("miner_address".to_owned(), value)],
+        )
+    }
+
+    /*
+    Chain of three blocks with a subsidy of 100 coins:
+    the genesis block pays alice, block 1 carries alice's transfer of 60 coins to bob
+    with 10 coins of fee, and block 2 only has its coinbase.
+    */
+    fn gen_chain() -> Blockchain {
+        let alice = Wallet::new();
+        let mut chain = Blockchain::with_params(ChainParams {
+            initial_subsidy: 100,
+            initial_difficulty: EASY_DIFFICULTY,
+            ..ChainParams::default()
+        });
+
+        let coinbase =
+            Transaction::coinbase(0, vec![Output::new(alice.address(), 100)]);
+        let alice_out_point = OutPoint::new(coinbase.hash(), 0);
+        let mut genesis =
+            Block::new(0, 1, vec![0; 32], vec![coinbase], 0, EASY_DIFFICULTY);
+        genesis.hash = genesis.hash();
+
+        let mut transfer = Transaction::new(
+            vec![Input::new(
+                alice_out_point.tx_hash,
+                alice_out_point.index,
+                100,
+            )],
+            vec![
+                Output::new("bob".to_owned(), 60),
+                Output::new(alice.address(), 30),
+            ],
+        );
+        alice.sign_transaction(&mut transfer);
+        let block_1 =
+            gen_block_on(&genesis, vec![gen_coinbase(1, 110), transfer]);
+        let block_2 = gen_block_on(&block_1, vec![gen_coinbase(2, 100)]);
+
+        for block in [genesis, block_1, block_2] {
+            chain.update_with_block(block).unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_audit_valid_chain() {
+        let report = Blockchain::new().audit();
+        assert!(report.is_clean());
+        assert_eq!(report.blocks_checked, 0);
+
+        let report = gen_chain().audit();
+        assert!(report.is_clean(), "{:?}", report.findings);
+        assert_eq!(report.blocks_checked, 3);
+        assert_eq!(report.transactions_checked, 4);
+        assert_eq!(report.total_supply, 300);
+        assert_eq!(report.max_supply, 300);
+    }
+
+    #[test]
+    fn test_audit_tampered_transaction() {
+        let mut chain = gen_chain();
+        let transfer = &mut chain.blocks[1].transactions[1];
+        transfer.outputs[0].value = 70;
+        let transfer_hash = transfer.hash();
+        let coinbase_hash = chain.blocks[1].transactions[0].hash();
+
+        let report = chain.audit();
+        // the block hash only covers the merkle root, which no longer matches
+        assert!(report.finding(AuditCheck::HashLinkage).is_none());
+        let finding = report.finding(AuditCheck::MerkleRoot).unwrap();
+        assert_eq!(finding.block_index, 1);
+        assert_eq!(finding.tx_hash, None);
+
+        // the signature does not cover the new output value
+        let finding = report.finding(AuditCheck::LedgerConsistency).unwrap();
+        assert_eq!(finding.block_index, 1);
+        assert_eq!(finding.tx_hash, Some(transfer_hash));
+        assert_eq!(
+            finding.reason,
+            BlockValidationErr::InvalidSignature.to_string()
+        );
+
+        // and without a valid transfer there is no fee for the coinbase to collect
+        let finding = report.finding(AuditCheck::Supply).unwrap();
+        assert_eq!(finding.block_index, 1);
+        assert_eq!(finding.tx_hash, Some(coinbase_hash));
+        assert_eq!(report.findings.len(), 3);
+    }
+
+    #[test]
+    fn test_audit_tampered_headers() {
+        let mut chain = gen_chain();
+
+        // block 1 is moved before the genesis block and hashed again, so its own hash is fine
+        // but block 2 now points to a block that is not there
+        let block_1 = &mut chain.blocks[1];
+        block_1.timestamp = 0;
+        block_1.hash = block_1.hash();
+
+        // block 2 claims an easier difficulty than the chain expects
+        let block_2 = &mut chain.blocks[2];
+        block_2.difficulty = EASY_DIFFICULTY - 1;
+
+        let report = chain.audit();
+        let finding = report.finding(AuditCheck::TimestampOrder).unwrap();
+        assert_eq!(finding.block_index, 1);
+        let finding = report.finding(AuditCheck::HashLinkage).unwrap();
+        assert_eq!(finding.block_index, 2);
+        assert_eq!(finding.reason, BlockValidationErr::InvalidHash.to_string());
+        let finding = report.finding(AuditCheck::ProofOfWork).unwrap();
+        assert_eq!(finding.block_index, 2);
+
+        // fixing block 2's hash reveals the broken link to block 1
+        let block_2 = &mut chain.blocks[2];
+        block_2.difficulty = EASY_DIFFICULTY;
+        block_2.hash = block_2.hash();
+        let finding = chain.audit().finding(AuditCheck::HashLinkage).cloned();
+        assert_eq!(
+            finding.unwrap().reason,
+            BlockValidationErr::MismatchedPreviousHash.to_string()
+        );
+    }
+
+    #[test]
+    fn test_audit_supply() {
+        let mut chain = gen_chain();
+
+        // a block that never went through update_with_block, minting 1000 coins
+        let overpaid = gen_coinbase(3, 1000);
+        let overpaid_hash = overpaid.hash();
+        let block = gen_block_on(chain.last_block().unwrap(), vec![overpaid]);
+        chain.blocks.push(block);
+
+        let report = chain.audit();
+        assert_eq!(report.total_supply, 1300);
+        assert_eq!(report.max_supply, 400);
+        let finding = report.finding(AuditCheck::Supply).unwrap();
+        assert_eq!(finding.block_index, 3);
+        assert_eq!(finding.tx_hash, Some(overpaid_hash));
+
+        // the chain never applied the block, so its ledger is behind the replay
+        let finding = report.finding(AuditCheck::LedgerConsistency).unwrap();
+        assert_eq!(finding.block_index, 3);
+        assert_eq!(finding.tx_hash, None);
+    }
+}
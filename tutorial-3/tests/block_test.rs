@@ -1,11 +1,13 @@
 #[cfg(test)]
 mod tests {
     use anyhow::{anyhow, Ok, Result};
+    use std::sync::atomic::AtomicBool;
+
     use blockchain::{
-        block::Block,
+        block::{check_difficulty, Block},
         hashtable::Hashtable,
         now,
-        transactions::{Output, Transaction},
+        transactions::{Input, Output, Transaction},
     };
     use crypto_hash::hex_digest;
     use rand::Rng;
@@ -91,6 +93,14 @@ mod tests {
         Ok(ret)
     }
 
+    fn gen_random_input() -> Result<Input> {
+        let mut rng = rand::thread_rng();
+        let prev_tx_hash: Vec<u8> = (0..32).map(|_| rng.gen()).collect();
+        let random_value: u16 = rng.gen();
+
+        Ok(Input::new(prev_tx_hash, 0, random_value as u64))
+    }
+
     fn gen_random_transaction() -> Result<Transaction> {
         let mut rng = rand::thread_rng();
         let mut ret: Transaction;
@@ -98,7 +108,9 @@ mod tests {
         let input_cnt_random = rng.gen_range(1..=10);
         let output_cnt_random = rng.gen_range(1..=10);
 
-        let inputs = gen_random_outputs(input_cnt_random).unwrap();
+        let inputs = (0..input_cnt_random)
+            .map(|_| gen_random_input().unwrap())
+            .collect::<Vec<Input>>();
         assert!(inputs.len() > 0, "inputs items generate failed!");
         let outputs = gen_random_outputs(output_cnt_random).unwrap();
         assert!(outputs.len() > 0, "outputs items generate failed!");
@@ -148,4 +160,45 @@ mod tests {
 
         assert_eq!(outer_hash_str, inner_hash_str);
     }
+
+    #[test]
+    fn test_block_mine() {
+        let trans = gen_random_transactions(3).unwrap();
+        // roughly one of every 256 hashes satisfies this target
+        let difficulty = u128::MAX >> 8;
+        let mut block = Block::new(0, now(), vec![0; 32], trans, 0, difficulty);
+
+        assert!(block.mine());
+        assert!(check_difficulty(&block.hash, difficulty));
+
+        // the stored hash is the hash of the block with the found nonce
+        assert_eq!(block.hash, block.hash());
+        println!("mined block {:?}", block);
+    }
+
+    #[test]
+    fn test_block_mine_range() {
+        let trans = gen_random_transactions(3).unwrap();
+        let mut block =
+            Block::new(0, now(), vec![0; 32], trans, 0, u128::MAX >> 4);
+
+        // an empty range has nothing to try
+        assert!(!block.mine_range(10..10, None));
+        assert_eq!(block.hash, vec![0; 32]);
+
+        // the found nonce should always stay inside the given range
+        assert!(block.mine_range(1_000..u64::MAX, None));
+        assert!(block.nonce >= 1_000);
+    }
+
+    #[test]
+    fn test_block_mine_cancel() {
+        let trans = gen_random_transactions(3).unwrap();
+        // no hash is smaller than 0, so only the cancel flag can stop this
+        let mut block = Block::new(0, now(), vec![0; 32], trans, 0, 0);
+        let cancel = AtomicBool::new(true);
+
+        assert!(!block.mine_range(0..u64::MAX, Some(&cancel)));
+        assert_eq!(block.hash, vec![0; 32]);
+    }
 }
@@ -0,0 +1,946 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        block::Block,
+        blockchain::{BlockValidationErr, Blockchain, ChainParams},
+        hashtable::{HashAlgorithm, Hashtable},
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    /*
+    With the maximum difficulty every hash satisfies the target,
+    so we can build valid blocks without mining them.
+    */
+    const EASY_DIFFICULTY: u128 = u128::MAX;
+
+    // chain parameters whose genesis difficulty is EASY_DIFFICULTY
+    fn gen_params() -> ChainParams {
+        ChainParams {
+            initial_difficulty: EASY_DIFFICULTY,
+            ..ChainParams::default()
+        }
+    }
+
+    fn gen_coinbase(height: u32, to_addr: &str, value: u64) -> Transaction {
+        Transaction::coinbase(
+            height,
+            vec![Output::new(to_addr.to_owned(), value)],
+        )
+    }
+
+    fn gen_block_with_transactions(
+        index: u32,
+        timestamp: u128,
+        prev_block_hash: Vec<u8>,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let mut block = Block::new(
+            index,
+            timestamp,
+            prev_block_hash,
+            transactions,
+            0,
+            EASY_DIFFICULTY,
+        );
+        block.hash = block.hash();
+        block
+    }
+
+    fn gen_block(
+        index: u32,
+        timestamp: u128,
+        prev_block_hash: Vec<u8>,
+    ) -> Block {
+        let coinbase = gen_coinbase(index, "miner_address", 50);
+        gen_block_with_transactions(
+            index,
+            timestamp,
+            prev_block_hash,
+            vec![coinbase],
+        )
+    }
+
+    // build a block on top of the chain's current tip
+    fn gen_next_block(
+        chain: &Blockchain,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let last = chain.last_block().unwrap();
+        gen_block_with_transactions(
+            last.index + 1,
+            last.timestamp + 1,
+            last.hash.clone(),
+            transactions,
+        )
+    }
+
+    // coinbase paying the miner for the block on top of the chain's current tip
+    fn gen_next_coinbase(chain: &Blockchain, value: u64) -> Transaction {
+        let height = chain.last_block().unwrap().index + 1;
+        gen_coinbase(height, "miner_address", value)
+    }
+
+    fn gen_chain(len: u32) -> Blockchain {
+        let mut chain = Blockchain::with_params(gen_params());
+        let mut prev_block_hash = vec![0; 32];
+        for i in 0..len {
+            let block = gen_block(i, i as u128 + 1, prev_block_hash);
+            prev_block_hash = block.hash.clone();
+            chain.update_with_block(block).unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_append_valid_blocks() {
+        let chain = gen_chain(5);
+        assert_eq!(chain.len(), 5);
+        assert_eq!(chain.last_block().unwrap().index, 4);
+
+        // every block should point back to its predecessor
+        for pair in chain.blocks.windows(2) {
+            assert_eq!(pair[1].prev_block_hash, pair[0].hash);
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_genesis() {
+        let mut chain = Blockchain::with_params(gen_params());
+        let block = gen_block(0, 1, vec![1; 32]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidGenesisBlockFormat)
+        );
+        assert!(chain.is_empty());
+    }
+
+    #[test]
+    fn test_reject_mismatched_index() {
+        let mut chain = gen_chain(2);
+        let prev_hash = chain.last_block().unwrap().hash.clone();
+        let block = gen_block(5, 100, prev_hash);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MismatchedIndex)
+        );
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn test_reject_mismatched_prev_hash() {
+        let mut chain = gen_chain(2);
+        let block = gen_block(2, 100, vec![7; 32]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MismatchedPreviousHash)
+        );
+    }
+
+    #[test]
+    fn test_reject_achronological_timestamp() {
+        let mut chain = gen_chain(2);
+        let last = chain.last_block().unwrap();
+        let block = gen_block(2, last.timestamp, last.hash.clone());
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::AchronologicalTimestamp)
+        );
+    }
+
+    #[test]
+    fn test_reject_tampered_block() {
+        let mut chain = gen_chain(1);
+        let prev_hash = chain.last_block().unwrap().hash.clone();
+        let mut block = gen_block(1, 100, prev_hash);
+
+        // modify the block after its hash was calculated
+        block.nonce += 1;
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidHash)
+        );
+    }
+
+    #[test]
+    fn test_reject_mismatched_merkle_root() {
+        let mut chain = gen_chain(1);
+        let prev_hash = chain.last_block().unwrap().hash.clone();
+        let mut block = gen_block(1, 100, prev_hash);
+
+        // the header hash only covers the root, so swapping the transactions
+        // keeps the hash valid but breaks the root
+        block.transactions = vec![gen_coinbase(1, "another_miner", 50)];
+        assert_eq!(block.hash, block.hash());
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidMerkleRoot)
+        );
+    }
+
+    #[test]
+    fn test_reject_difficulty_not_met() {
+        let mut chain = Blockchain::with_params(gen_params());
+        let mut block = gen_block(0, 1, vec![0; 32]);
+
+        // no hash is smaller than 0, so this target can never be met
+        block.difficulty = 0;
+        block.hash = block.hash();
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::DifficultyNotMet)
+        );
+    }
+
+    /*
+    Chain with a genesis block that pays 100 to alice,
+    so the following blocks have an unspent output to play with.
+    Returns the chain, alice's wallet and the out point of her 100 coins.
+    */
+    fn gen_chain_paying_alice() -> (Blockchain, Wallet, OutPoint) {
+        let alice = Wallet::new();
+        let mut chain = Blockchain::with_params(ChainParams {
+            initial_subsidy: 100,
+            ..gen_params()
+        });
+        let coinbase = gen_coinbase(0, &alice.address(), 100);
+        let alice_out_point = OutPoint::new(coinbase.hash(), 0);
+        let genesis =
+            gen_block_with_transactions(0, 1, vec![0; 32], vec![coinbase]);
+        chain.update_with_block(genesis).unwrap();
+        (chain, alice, alice_out_point)
+    }
+
+    // transaction spending the given out points, signed by the wallet
+    fn gen_transfer(
+        from: &Wallet,
+        spends: &[(&OutPoint, u64)],
+        outputs: Vec<Output>,
+    ) -> Transaction {
+        let inputs = spends
+            .iter()
+            .map(|(out_point, value)| {
+                Input::new(out_point.tx_hash.clone(), out_point.index, *value)
+            })
+            .collect();
+        let mut transaction = Transaction::new(inputs, outputs);
+        from.sign_transaction(&mut transaction);
+        transaction
+    }
+
+    #[test]
+    fn test_spend_unspent_output() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        assert!(chain.is_unspent(&alice_out_point));
+        let bob = Wallet::new();
+
+        // alice sends 60 to bob, 30 back to herself and leaves 10 as fee
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![
+                Output::new(bob.address(), 60),
+                Output::new(alice.address(), 30),
+            ],
+        );
+        assert_eq!(transfer.fee(), Some(10));
+        assert!(transfer.is_validate());
+        let bob_out_point = OutPoint::new(transfer.hash(), 0);
+        let change_out_point = OutPoint::new(transfer.hash(), 1);
+
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 110), transfer],
+        );
+        chain.update_with_block(block).unwrap();
+
+        assert!(!chain.is_unspent(&alice_out_point));
+        assert_eq!(chain.get_unspent(&bob_out_point).unwrap().value, 60);
+        assert_eq!(chain.get_unspent(&change_out_point).unwrap().value, 30);
+        assert_eq!(chain.unspent_outputs().len(), 3);
+        assert_eq!(chain.balance(&alice.address()), 30);
+        assert_eq!(chain.balance(&bob.address()), 60);
+        assert_eq!(chain.unspent_outputs_of(&bob.address()).len(), 1);
+
+        // the output is gone now, spending it again in a later block should fail
+        let replay = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new(alice.address(), 100)],
+        );
+        let block =
+            gen_next_block(&chain, vec![gen_next_coinbase(&chain, 50), replay]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_reject_unknown_input() {
+        let (mut chain, alice, _) = gen_chain_paying_alice();
+        let unknown = OutPoint::new(vec![9; 32], 0);
+        let transfer = gen_transfer(
+            &alice,
+            &[(&unknown, 1_000)],
+            vec![Output::new(alice.address(), 1_000)],
+        );
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), transfer],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidInput)
+        );
+        assert_eq!(chain.len(), 1);
+    }
+
+    #[test]
+    fn test_reject_mismatched_input_value() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+
+        // the spent output holds 100, claiming more is not allowed
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 1_000)],
+            vec![Output::new(alice.address(), 1_000)],
+        );
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), transfer],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_reject_spend_by_other_wallet() {
+        let (mut chain, _, alice_out_point) = gen_chain_paying_alice();
+        let mallory = Wallet::new();
+
+        // mallory signs with her own key, but the output belongs to alice
+        let theft = gen_transfer(
+            &mallory,
+            &[(&alice_out_point, 100)],
+            vec![Output::new(mallory.address(), 100)],
+        );
+        assert!(theft.is_validate());
+        let block =
+            gen_next_block(&chain, vec![gen_next_coinbase(&chain, 50), theft]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MismatchedPublicKey)
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_signature() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        let mallory = Wallet::new();
+
+        // mallory redirects alice's signed payment to herself
+        let mut transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new(alice.address(), 100)],
+        );
+        transfer.outputs[0].to_addr = mallory.address();
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), transfer],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_reject_double_spend() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        let to_bob = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new("bob".to_owned(), 100)],
+        );
+        let to_carol = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new("carol".to_owned(), 100)],
+        );
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), to_bob, to_carol],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::DoubleSpend)
+        );
+
+        // the rejected block should leave the UTXO set untouched
+        assert!(chain.is_unspent(&alice_out_point));
+
+        // the same output listed twice in one transaction is a double-spend too
+        let twice = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100), (&alice_out_point, 100)],
+            vec![Output::new("bob".to_owned(), 200)],
+        );
+        assert!(!twice.is_validate());
+        let block =
+            gen_next_block(&chain, vec![gen_next_coinbase(&chain, 50), twice]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::DoubleSpend)
+        );
+    }
+
+    #[test]
+    fn test_reject_insufficient_input_value() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new("bob".to_owned(), 101)],
+        );
+        assert_eq!(transfer.fee(), None);
+        assert!(!transfer.is_validate());
+
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), transfer],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InsufficientInputValue)
+        );
+    }
+
+    #[test]
+    fn test_reject_overflowing_output_value() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        // the outputs add up past u64::MAX, a wrapping sum would make them worth 99
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![
+                Output::new("bob".to_owned(), u64::MAX),
+                Output::new("bob".to_owned(), 100),
+            ],
+        );
+        assert_eq!(transfer.output_value(), u64::MAX);
+        assert_eq!(transfer.fee(), None);
+        assert!(!transfer.is_validate());
+
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), transfer],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InsufficientInputValue)
+        );
+    }
+
+    #[test]
+    fn test_reject_duplicated_last_transaction() {
+        // alice owns two outputs of the genesis block, and spends each in its own transaction
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let mut chain = Blockchain::with_params(ChainParams {
+            initial_subsidy: 100,
+            ..gen_params()
+        });
+        let coinbase = Transaction::coinbase(
+            0,
+            vec![
+                Output::new(alice.address(), 50),
+                Output::new(alice.address(), 50),
+            ],
+        );
+        let out_points =
+            [0, 1].map(|index| OutPoint::new(coinbase.hash(), index));
+        let genesis =
+            gen_block_with_transactions(0, 1, vec![0; 32], vec![coinbase]);
+        chain.update_with_block(genesis).unwrap();
+
+        let first = gen_transfer(
+            &alice,
+            &[(&out_points[0], 50)],
+            vec![Output::new(bob.address(), 40)],
+        );
+        let second = gen_transfer(
+            &alice,
+            &[(&out_points[1], 50)],
+            vec![Output::new(bob.address(), 45)],
+        );
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 115), first, second.clone()],
+        );
+
+        // three transactions, the last one repeated: same Merkle root, same block hash
+        let mut mutated = block.clone();
+        mutated.transactions.push(second);
+        assert_eq!(mutated.compute_merkle_root(), block.merkle_root);
+        assert_eq!(mutated.hash(), block.hash);
+        assert_eq!(
+            chain.update_with_block(mutated),
+            Err(BlockValidationErr::DuplicateTransaction)
+        );
+
+        // the copy did not take the place of the real block
+        chain.update_with_block(block.clone()).unwrap();
+        assert_eq!(chain.last_block(), Some(&block));
+        assert_eq!(chain.balance(&bob.address()), 85);
+    }
+
+    #[test]
+    fn test_reject_missing_coinbase() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+
+        // a block without any transaction has no coinbase
+        let block = gen_next_block(&chain, vec![]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MissingCoinbase)
+        );
+
+        // a coinbase that is not the first transaction does not count
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new("bob".to_owned(), 100)],
+        );
+        let block = gen_next_block(
+            &chain,
+            vec![transfer, gen_next_coinbase(&chain, 50)],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MissingCoinbase)
+        );
+    }
+
+    #[test]
+    fn test_reject_second_coinbase() {
+        let (mut chain, _, _) = gen_chain_paying_alice();
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 50), gen_next_coinbase(&chain, 40)],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::UnexpectedCoinbase)
+        );
+    }
+
+    #[test]
+    fn test_reject_mismatched_coinbase_height() {
+        let (mut chain, _, _) = gen_chain_paying_alice();
+        let block =
+            gen_next_block(&chain, vec![gen_coinbase(7, "miner_address", 50)]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MismatchedCoinbaseHeight)
+        );
+    }
+
+    #[test]
+    fn test_coinbase_value_limit() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        let transfer = || {
+            gen_transfer(
+                &alice,
+                &[(&alice_out_point, 100)],
+                vec![Output::new("bob".to_owned(), 90)],
+            )
+        };
+
+        // subsidy 100 plus fee 10 is the most the miner can take
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 111), transfer()],
+        );
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidCoinbaseValue)
+        );
+
+        let block = gen_next_block(
+            &chain,
+            vec![gen_next_coinbase(&chain, 110), transfer()],
+        );
+        assert!(chain.update_with_block(block).is_ok());
+    }
+
+    #[test]
+    fn test_block_subsidy_halving() {
+        let params = ChainParams {
+            initial_subsidy: 50,
+            halving_interval: 10,
+            ..gen_params()
+        };
+        assert_eq!(params.block_subsidy(0), 50);
+        assert_eq!(params.block_subsidy(9), 50);
+        assert_eq!(params.block_subsidy(10), 25);
+        assert_eq!(params.block_subsidy(20), 12);
+        assert_eq!(params.block_subsidy(30), 6);
+        assert_eq!(params.block_subsidy(10 * 64), 0);
+        assert_eq!(params.block_subsidy(u32::MAX), 0);
+
+        // after the first halving a full reward coinbase is no longer accepted
+        let mut chain = Blockchain::with_params(ChainParams {
+            initial_subsidy: 50,
+            halving_interval: 2,
+            ..gen_params()
+        });
+        let genesis = gen_block(0, 1, vec![0; 32]);
+        chain.update_with_block(genesis).unwrap();
+        let block = gen_next_block(&chain, vec![gen_next_coinbase(&chain, 50)]);
+        chain.update_with_block(block).unwrap();
+
+        let block = gen_next_block(&chain, vec![gen_next_coinbase(&chain, 50)]);
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::InvalidCoinbaseValue)
+        );
+        let block = gen_next_block(&chain, vec![gen_next_coinbase(&chain, 25)]);
+        assert!(chain.update_with_block(block).is_ok());
+    }
+
+    /*
+    Chain parameters that retarget every 4 blocks toward one block per 100 ms,
+    the window of 4 blocks covers 3 intervals, so it is expected to take 300 ms.
+    */
+    fn gen_retarget_params() -> ChainParams {
+        ChainParams {
+            initial_difficulty: u128::MAX >> 4,
+            retarget_interval: 4,
+            target_block_time: 100,
+            max_retarget_factor: 4,
+            ..ChainParams::default()
+        }
+    }
+
+    // mine the next block with the difficulty the chain expects, `delay` ms after the tip
+    fn gen_mined_block(chain: &Blockchain, delay: u128) -> Block {
+        let (index, timestamp, prev_block_hash) = match chain.last_block() {
+            Some(last) => {
+                (last.index + 1, last.timestamp + delay, last.hash.clone())
+            }
+            None => (0, 1, vec![0; 32]),
+        };
+        let coinbase = gen_coinbase(index, "miner_address", 50);
+        let mut block = Block::new(
+            index,
+            timestamp,
+            prev_block_hash,
+            vec![coinbase],
+            0,
+            chain.next_difficulty(),
+        );
+        assert!(block.mine());
+        block
+    }
+
+    #[test]
+    fn test_retarget_clamps_timespan() {
+        let params = gen_retarget_params();
+        let difficulty = 1u128 << 100;
+
+        // right on time keeps the difficulty
+        assert_eq!(params.retarget(difficulty, 300), difficulty);
+        // twice as slow doubles the target, twice as fast halves it
+        assert_eq!(params.retarget(difficulty, 600), difficulty * 2);
+        assert_eq!(params.retarget(difficulty, 150), difficulty / 2);
+        // far outside the expected timespan, the change is clamped to 4 times
+        assert_eq!(params.retarget(difficulty, 0), difficulty / 4);
+        assert_eq!(params.retarget(difficulty, 1_000_000), difficulty * 4);
+        // but never easier than the genesis difficulty
+        assert_eq!(
+            params.retarget(params.initial_difficulty, 1_000_000),
+            params.initial_difficulty
+        );
+        // no precision is lost when difficulty * timespan overflows 128 bits
+        assert_eq!(
+            params.retarget(params.initial_difficulty, 300),
+            params.initial_difficulty
+        );
+    }
+
+    #[test]
+    fn test_retarget_difficulty() {
+        let params = gen_retarget_params();
+        let mut chain = Blockchain::with_params(params.clone());
+        assert_eq!(chain.next_difficulty(), params.initial_difficulty);
+
+        // the first window is mined right on time, nothing changes
+        for _ in 0..4 {
+            let block = gen_mined_block(&chain, 100);
+            chain.update_with_block(block).unwrap();
+        }
+        assert_eq!(chain.next_difficulty(), params.initial_difficulty);
+
+        // the second window is mined 10 times too fast, but the difficulty only moves 4 times
+        for _ in 0..4 {
+            let block = gen_mined_block(&chain, 10);
+            chain.update_with_block(block).unwrap();
+        }
+        // the 30 ms it took are clamped to 300 / 4 = 75 ms
+        let expected = params.retarget(params.initial_difficulty, 75);
+        assert!(expected < params.initial_difficulty / 3);
+        assert_eq!(chain.next_difficulty(), expected);
+
+        // blocks inside a window keep the difficulty of the previous block
+        let block = gen_mined_block(&chain, 100);
+        chain.update_with_block(block).unwrap();
+        assert_eq!(chain.last_block().unwrap().difficulty, expected);
+        assert_eq!(chain.next_difficulty(), expected);
+    }
+
+    #[test]
+    fn test_reject_mismatched_difficulty() {
+        let mut chain = Blockchain::with_params(gen_retarget_params());
+        for _ in 0..8 {
+            let block = gen_mined_block(&chain, 10);
+            chain.update_with_block(block).unwrap();
+        }
+
+        // keeping the old, easier difficulty after a retarget is refused,
+        // even though the hash meets the difficulty the block claims
+        let last = chain.last_block().unwrap();
+        let mut block = Block::new(
+            8,
+            last.timestamp + 100,
+            last.hash.clone(),
+            vec![gen_coinbase(8, "miner_address", 50)],
+            0,
+            last.difficulty,
+        );
+        assert!(block.mine());
+        assert_eq!(
+            chain.update_with_block(block),
+            Err(BlockValidationErr::MismatchedDifficulty)
+        );
+        assert_eq!(chain.len(), 8);
+    }
+
+    #[test]
+    fn test_reject_mismatched_hash_algorithm() {
+        let mut chain = Blockchain::with_params(ChainParams {
+            hash_algorithm: HashAlgorithm::Keccak256,
+            ..gen_retarget_params()
+        });
+        let genesis = chain.params.genesis_block();
+        assert_eq!(genesis.hash_algorithm, HashAlgorithm::Keccak256);
+        chain.update_with_block(genesis).unwrap();
+
+        // a block mined with SHA-256 is valid on its own, but not on this chain
+        let last = chain.last_block().unwrap();
+        let mut block = Block::new(
+            1,
+            last.timestamp + 1,
+            last.hash.clone(),
+            vec![gen_coinbase(1, "miner_address", 50)],
+            0,
+            chain.next_difficulty(),
+        );
+        assert!(block.mine());
+        assert_eq!(
+            chain.update_with_block(block.clone()),
+            Err(BlockValidationErr::MismatchedHashAlgorithm)
+        );
+
+        let mut block = block.with_hash_algorithm(HashAlgorithm::Keccak256);
+        assert!(block.mine());
+        chain.update_with_block(block).unwrap();
+        assert_eq!(chain.len(), 2);
+        assert!(chain.audit().is_clean());
+    }
+
+    // block on top of any block of the tree, its coinbase pays the given miner
+    fn gen_block_on(
+        parent: &Block,
+        miner: &str,
+        extra: Vec<Transaction>,
+    ) -> Block {
+        let index = parent.index + 1;
+        let mut transactions = vec![gen_coinbase(index, miner, 50)];
+        transactions.extend(extra);
+        gen_block_with_transactions(
+            index,
+            parent.timestamp + 1,
+            parent.hash.clone(),
+            transactions,
+        )
+    }
+
+    #[test]
+    fn test_switch_to_heavier_branch() {
+        let mut chain = gen_chain(1);
+        let events = chain.subscribe();
+        let genesis = chain.last_block().unwrap().clone();
+
+        let block_1a = gen_block_on(&genesis, "miner_a", vec![]);
+        let block_2a = gen_block_on(&block_1a, "miner_a", vec![]);
+        chain.update_with_block(block_1a.clone()).unwrap();
+        chain.update_with_block(block_2a.clone()).unwrap();
+        assert_eq!(
+            events.try_recv().unwrap().connected,
+            vec![block_1a.clone()]
+        );
+        assert_eq!(
+            events.try_recv().unwrap().connected,
+            vec![block_2a.clone()]
+        );
+
+        // a competing branch is kept aside until it has more work than the active chain
+        let block_1b = gen_block_on(&genesis, "miner_b", vec![]);
+        let block_2b = gen_block_on(&block_1b, "miner_b", vec![]);
+        let block_3b = gen_block_on(&block_2b, "miner_b", vec![]);
+        chain.update_with_block(block_1b.clone()).unwrap();
+        chain.update_with_block(block_2b.clone()).unwrap();
+        assert_eq!(chain.last_block(), Some(&block_2a));
+        assert!(chain.contains_block(&block_2b.hash));
+        assert!(!chain.is_active(&block_2b.hash));
+        assert!(events.try_recv().is_err());
+        assert_eq!(
+            chain.update_with_block(block_2b.clone()),
+            Err(BlockValidationErr::DuplicateBlock)
+        );
+
+        chain.update_with_block(block_3b.clone()).unwrap();
+        assert_eq!(chain.len(), 4);
+        assert_eq!(chain.last_block(), Some(&block_3b));
+        assert!(chain.is_active(&block_1b.hash));
+        assert!(!chain.is_active(&block_1a.hash));
+        assert_eq!(chain.tip_work(), 4);
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.disconnected, vec![block_2a, block_1a.clone()]);
+        assert_eq!(event.connected, vec![block_1b, block_2b, block_3b]);
+
+        // only the coinbases of the active branch are spendable
+        let out_point =
+            |block: &Block| OutPoint::new(block.transactions[0].hash(), 0);
+        assert!(!chain.is_unspent(&out_point(&block_1a)));
+        assert_eq!(chain.unspent_outputs().len(), 4);
+    }
+
+    #[test]
+    fn test_reorg_restores_spent_outputs() {
+        let (mut chain, alice, alice_out_point) = gen_chain_paying_alice();
+        let genesis = chain.last_block().unwrap().clone();
+        let bob = Wallet::new();
+
+        // on branch a alice pays bob, branch b does not know about that payment
+        let transfer = gen_transfer(
+            &alice,
+            &[(&alice_out_point, 100)],
+            vec![Output::new(bob.address(), 100)],
+        );
+        let bob_out_point = OutPoint::new(transfer.hash(), 0);
+        let block_1a = gen_block_on(&genesis, "miner_a", vec![transfer]);
+        chain.update_with_block(block_1a).unwrap();
+        assert!(!chain.is_unspent(&alice_out_point));
+        assert!(chain.is_unspent(&bob_out_point));
+
+        let block_1b = gen_block_on(&genesis, "miner_b", vec![]);
+        let block_2b = gen_block_on(&block_1b, "miner_b", vec![]);
+        chain.update_with_block(block_1b).unwrap();
+        chain.update_with_block(block_2b.clone()).unwrap();
+
+        assert_eq!(chain.last_block(), Some(&block_2b));
+        assert!(chain.is_unspent(&alice_out_point));
+        assert!(!chain.is_unspent(&bob_out_point));
+        assert_eq!(chain.unspent_outputs().len(), 3);
+    }
+
+    #[test]
+    fn test_reject_invalid_heavier_branch() {
+        let mut chain = gen_chain(1);
+        let genesis = chain.last_block().unwrap().clone();
+        let block_1a = gen_block_on(&genesis, "miner_a", vec![]);
+        chain.update_with_block(block_1a.clone()).unwrap();
+        let utxo_before = chain.unspent_outputs().clone();
+        let events = chain.subscribe();
+
+        // the side branch only gets verified against the UTXO set once it becomes the heaviest,
+        // its second block pays the miner too much
+        let block_1b = gen_block_on(&genesis, "miner_b", vec![]);
+        let mut block_2b = gen_block_with_transactions(
+            2,
+            block_1b.timestamp + 1,
+            block_1b.hash.clone(),
+            vec![gen_coinbase(2, "miner_b", 1_000)],
+        );
+        block_2b.hash = block_2b.hash();
+        let block_3b = gen_block_on(&block_2b, "miner_b", vec![]);
+
+        chain.update_with_block(block_1b.clone()).unwrap();
+        assert_eq!(
+            chain.update_with_block(block_2b.clone()),
+            Err(BlockValidationErr::InvalidCoinbaseValue)
+        );
+
+        // the chain is back on branch a, and the invalid block is gone from the tree
+        assert_eq!(chain.last_block(), Some(&block_1a));
+        assert!(chain.unspent_outputs() == &utxo_before);
+        assert!(chain.contains_block(&block_1b.hash));
+        assert!(!chain.contains_block(&block_2b.hash));
+        assert!(events.try_recv().is_err());
+
+        // and nothing can be built on top of it anymore
+        assert_eq!(
+            chain.update_with_block(block_3b),
+            Err(BlockValidationErr::MismatchedPreviousHash)
+        );
+    }
+
+    #[test]
+    fn test_drop_invalid_subtree_keep_siblings() {
+        let mut chain = gen_chain(1);
+        let genesis = chain.last_block().unwrap().clone();
+        let block_1a = gen_block_on(&genesis, "miner_a", vec![]);
+        let block_2a = gen_block_on(&block_1a, "miner_a", vec![]);
+        chain.update_with_block(block_1a).unwrap();
+        chain.update_with_block(block_2a.clone()).unwrap();
+
+        // branch b forks into an invalid and a valid block, neither is heavier than branch a yet
+        let block_1b = gen_block_on(&genesis, "miner_b", vec![]);
+        let mut invalid_2b = gen_block_with_transactions(
+            2,
+            block_1b.timestamp + 1,
+            block_1b.hash.clone(),
+            vec![gen_coinbase(2, "miner_b", 1_000)],
+        );
+        invalid_2b.hash = invalid_2b.hash();
+        let invalid_3b = gen_block_on(&invalid_2b, "miner_b", vec![]);
+        let block_2c = gen_block_on(&block_1b, "miner_c", vec![]);
+        for block in [&block_1b, &invalid_2b, &block_2c] {
+            chain.update_with_block(block.clone()).unwrap();
+        }
+        assert_eq!(chain.last_block(), Some(&block_2a));
+
+        // the invalid block takes its descendant along, but not its sibling
+        assert_eq!(
+            chain.update_with_block(invalid_3b.clone()),
+            Err(BlockValidationErr::InvalidCoinbaseValue)
+        );
+        assert_eq!(chain.last_block(), Some(&block_2a));
+        assert!(!chain.contains_block(&invalid_2b.hash));
+        assert!(!chain.contains_block(&invalid_3b.hash));
+        assert!(chain.contains_block(&block_2c.hash));
+
+        // which can still become the active chain
+        let block_3c = gen_block_on(&block_2c, "miner_c", vec![]);
+        chain.update_with_block(block_3c.clone()).unwrap();
+        assert_eq!(chain.last_block(), Some(&block_3c));
+        assert!(chain.is_active(&block_1b.hash));
+        assert!(chain.audit().is_clean());
+    }
+}
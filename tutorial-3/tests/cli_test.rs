@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::process::{Command, Output};
+
+    use tempfile::tempdir;
+
+    // runs the binary on the data directory, the way a user would from a shell
+    fn run(data_dir: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_tutorial-3"))
+            .arg("--data-dir")
+            .arg(data_dir)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    // runs a command that has to succeed and returns what it printed
+    fn run_ok(data_dir: &Path, args: &[&str]) -> String {
+        let output = run(data_dir, args);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_init_mine_and_balance() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path();
+
+        run_ok(data_dir, &["init"]);
+        assert!(data_dir.join("blocks.dat").exists());
+
+        let alice = run_ok(data_dir, &["new-wallet"]).trim().to_string();
+        assert!(data_dir
+            .join("wallets")
+            .join(format!("{}.key", alice))
+            .exists());
+        assert_eq!(run_ok(data_dir, &["balance", &alice]).trim(), "0");
+
+        // every mined block pays alice the subsidy
+        run_ok(data_dir, &["mine", &alice, "2", "--threads", "2"]);
+        assert_eq!(run_ok(data_dir, &["balance", &alice]).trim(), "100");
+
+        // the chain is read back from the data directory by every command
+        let chain = run_ok(data_dir, &["print-chain"]);
+        assert_eq!(
+            chain.lines().filter(|line| !line.starts_with(' ')).count(),
+            3
+        );
+        run_ok(data_dir, &["audit"]);
+    }
+
+    #[test]
+    fn test_commands_fail_without_chain() {
+        let dir = tempdir().unwrap();
+
+        let output = run(dir.path(), &["balance", "alice"]);
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("run init first")
+        );
+
+        // a second init does not overwrite the chain
+        run_ok(dir.path(), &["init"]);
+        let output = run(dir.path(), &["init"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("already holds a chain"));
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        block::Block,
+        codec::{Decode, DecodeError, Encode, CODEC_VERSION},
+        hashtable::Hashtable,
+        now,
+        transactions::{Input, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    fn gen_transfer() -> Transaction {
+        let wallet = Wallet::new();
+        let mut transfer = Transaction::new(
+            vec![
+                Input::new(vec![1; 32], 0, 70),
+                Input::new(vec![2; 32], 3, 30),
+            ],
+            vec![
+                Output::new("bob".to_owned(), 60),
+                Output::new(wallet.address(), 35),
+            ],
+        );
+        wallet.sign_transaction(&mut transfer);
+        transfer
+    }
+
+    fn gen_block() -> Block {
+        let coinbase = Transaction::coinbase(
+            3,
+            vec![Output::new("miner_address".to_owned(), 55)],
+        );
+        let mut block = Block::new(
+            3,
+            now(),
+            vec![7; 32],
+            vec![coinbase, gen_transfer()],
+            0,
+            u128::MAX >> 4,
+        );
+        block.mine();
+        block
+    }
+
+    #[test]
+    fn test_output_round_trip() {
+        let output = Output::new("target_address".to_owned(), 40);
+        let bytes = output.encode();
+        assert_eq!(bytes[0], CODEC_VERSION);
+        assert!(Output::decode(&bytes).unwrap() == output);
+    }
+
+    #[test]
+    fn test_transaction_round_trip() {
+        let transfer = gen_transfer();
+        let decoded = Transaction::decode(&transfer.encode()).unwrap();
+
+        assert_eq!(decoded, transfer);
+        assert_eq!(decoded.hash(), transfer.hash());
+        assert!(decoded.is_validate());
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        let block = gen_block();
+        let bytes = block.encode();
+        let decoded = Block::decode(&bytes).unwrap();
+
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.hash(), block.hash());
+        assert_eq!(decoded.hash, block.hash);
+
+        // the encoding is canonical: encoding again gives the very same bytes
+        assert_eq!(decoded.encode(), bytes);
+    }
+
+    #[test]
+    fn test_block_json_round_trip() {
+        let block = gen_block();
+        let json = serde_json::to_string(&block).unwrap();
+        let decoded: Block = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.hash(), block.hash());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_bytes() {
+        let bytes = gen_block().encode();
+
+        // every strict prefix ends in the middle of some field
+        for len in 1..bytes.len() {
+            assert_eq!(
+                Block::decode(&bytes[..len]),
+                Err(DecodeError::UnexpectedEof)
+            );
+        }
+        assert_eq!(Block::decode(&[]), Err(DecodeError::UnexpectedEof));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = CODEC_VERSION + 1;
+        assert_eq!(
+            Block::decode(&wrong_version),
+            Err(DecodeError::UnsupportedVersion(CODEC_VERSION + 1))
+        );
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(Block::decode(&trailing), Err(DecodeError::TrailingBytes));
+    }
+}
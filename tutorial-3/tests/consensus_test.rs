@@ -0,0 +1,624 @@
+/*
+Property-based tests of the consensus rules.
+
+Instead of a handful of hand-written blocks, proptest generates plans for random chains:
+how far apart the blocks are, and which wallet pays which share of an output to whom, with what fee.
+ChainBuilder turns a plan into signed transactions and mined blocks that follow every rule,
+and the properties below then check what has to hold for any such chain,
+and that breaking any single rule gets the block or transaction rejected.
+When a property fails, proptest shrinks the plan down to the smallest chain that still fails.
+*/
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    use blockchain::{
+        block::Block,
+        blockchain::{BlockValidationErr, Blockchain, ChainParams},
+        codec::{Decode, Encode, CODEC_VERSION},
+        hashtable::HashAlgorithm,
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    type Hash = Vec<u8>;
+
+    const WALLETS: usize = 4;
+
+    // easy to mine, with a retarget and a halving every 4 blocks so that short chains go through both
+    fn gen_params() -> ChainParams {
+        ChainParams {
+            initial_subsidy: 1_000,
+            halving_interval: 4,
+            initial_difficulty: u128::MAX >> 4,
+            retarget_interval: 4,
+            target_block_time: 100,
+            ..ChainParams::default()
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct PaymentPlan {
+        from: usize,
+        to: usize,
+        // share of the spent output paid to `to`
+        percent: u64,
+        fee: u64,
+    }
+
+    #[derive(Debug, Clone)]
+    struct BlockPlan {
+        // milliseconds after the previous block
+        delay: u128,
+        payments: Vec<PaymentPlan>,
+    }
+
+    fn payment_plan() -> impl Strategy<Value = PaymentPlan> {
+        (0..WALLETS, 0..WALLETS, 1..100u64, 0..20u64).prop_map(
+            |(from, to, percent, fee)| PaymentPlan {
+                from,
+                to,
+                percent,
+                fee,
+            },
+        )
+    }
+
+    fn block_plan() -> impl Strategy<Value = BlockPlan> {
+        (1..200u128, vec(payment_plan(), 0..4))
+            .prop_map(|(delay, payments)| BlockPlan { delay, payments })
+    }
+
+    fn hash() -> impl Strategy<Value = Hash> {
+        vec(any::<u8>(), 32)
+    }
+
+    struct ChainBuilder {
+        chain: Blockchain,
+        // the same wallets in every run, so a failing case can be replayed
+        wallets: Vec<Wallet>,
+    }
+
+    impl ChainBuilder {
+        fn new() -> Self {
+            ChainBuilder {
+                chain: Blockchain::with_params(gen_params()),
+                wallets: (1..=WALLETS as u8)
+                    .map(|seed| Wallet::from_secret_bytes(&[seed; 32]))
+                    .collect(),
+            }
+        }
+
+        // the chain after every planned block, each of them must be accepted
+        fn with_blocks(plans: &[BlockPlan]) -> Self {
+            let mut builder = Self::new();
+            for plan in plans {
+                let block = builder.next_block(plan);
+                builder.chain.update_with_block(block).unwrap();
+            }
+            builder
+        }
+
+        fn owner_of(&self, out_point: &OutPoint) -> &Wallet {
+            let address = &self.chain.get_unspent(out_point).unwrap().to_addr;
+            self.wallets
+                .iter()
+                .find(|wallet| &wallet.address() == address)
+                .unwrap()
+        }
+
+        /*
+        Function builds the signed transfer of the plan, spending the first unspent output of the sender
+        that is not in `spent` yet. None when the sender has nothing left to spend,
+        or the output is too small to pay anything.
+        */
+        fn payment(
+            &self,
+            plan: &PaymentPlan,
+            spent: &mut HashSet<OutPoint>,
+        ) -> Option<Transaction> {
+            let sender = &self.wallets[plan.from];
+            let mut unspent = self.chain.unspent_outputs_of(&sender.address());
+            // the UTXO set comes in no particular order, sorting keeps the runs reproducible
+            unspent.sort_by_key(|(out_point, _)| {
+                (out_point.tx_hash.clone(), out_point.index)
+            });
+            let (out_point, output) = unspent
+                .into_iter()
+                .find(|(out_point, _)| !spent.contains(*out_point))?;
+
+            let amount = output.value * plan.percent / 100;
+            if amount == 0 {
+                return None;
+            }
+            let fee = plan.fee.min(output.value - amount);
+            let change = output.value - amount - fee;
+
+            let mut outputs =
+                vec![Output::new(self.wallets[plan.to].address(), amount)];
+            if change > 0 {
+                outputs.push(Output::new(sender.address(), change));
+            }
+            let mut transfer = Transaction::new(
+                vec![Input::new(
+                    out_point.tx_hash.clone(),
+                    out_point.index,
+                    output.value,
+                )],
+                outputs,
+            );
+            sender.sign_transaction(&mut transfer);
+            spent.insert(out_point.clone());
+            Some(transfer)
+        }
+
+        // the mined block on top of the tip, the coinbase claims the whole subsidy and every fee
+        fn next_block(&self, plan: &BlockPlan) -> Block {
+            let (index, timestamp, prev_block_hash) =
+                match self.chain.last_block() {
+                    Some(last) => (
+                        last.index + 1,
+                        last.timestamp + plan.delay,
+                        last.hash.clone(),
+                    ),
+                    None => (0, 1, vec![0; 32]),
+                };
+
+            let mut spent = HashSet::new();
+            let transfers: Vec<Transaction> = plan
+                .payments
+                .iter()
+                .filter_map(|payment| self.payment(payment, &mut spent))
+                .collect();
+            let fees: u64 = transfers
+                .iter()
+                .map(|transfer| transfer.fee().unwrap())
+                .sum();
+
+            let miner = &self.wallets[index as usize % WALLETS];
+            let coinbase = Transaction::coinbase(
+                index,
+                vec![Output::new(
+                    miner.address(),
+                    self.chain.params.block_subsidy(index) + fees,
+                )],
+            );
+            let mut block = Block::new(
+                index,
+                timestamp,
+                prev_block_hash,
+                [vec![coinbase], transfers].concat(),
+                0,
+                self.chain.next_difficulty(),
+            );
+            assert!(block.mine());
+            block
+        }
+    }
+
+    /*
+    Changes to a single field of a valid block on top of the tip.
+    Every one of them breaks a consensus rule, even when the block is mined again afterwards,
+    except Hash, Nonce and Timestamp which only break the stored hash.
+    */
+    #[derive(Debug, Clone)]
+    enum Mutation {
+        Index(u32),
+        // back to the parent's timestamp, or earlier
+        Rewind(u128),
+        PrevBlockHash(Hash),
+        MerkleRoot(Hash),
+        StateRoot(Hash),
+        EasierDifficulty(u128),
+        HashAlgorithm(HashAlgorithm),
+        DropCoinbase,
+        CoinbaseHeight(u32),
+        InflateCoinbase(u64),
+        // an extra coinbase output that makes the sum of the outputs overflow
+        OverflowCoinbase,
+        DuplicateTransaction(Index),
+        // the first transfer pays out more than its input, signed again by the owner
+        InflateTransfer(u64),
+        // the first transfer spends an output its transaction does not have, signed again by the owner
+        SpendUnknownOutput(u32),
+        BreakSignature(Index),
+        Hash(Hash),
+        Nonce(u64),
+        Timestamp(u128),
+    }
+
+    // the mutations that stay invalid after mining the block again
+    fn consensus_mutation() -> impl Strategy<Value = Mutation> {
+        prop_oneof![
+            (1..u32::MAX).prop_map(Mutation::Index),
+            (0..1_000u128).prop_map(Mutation::Rewind),
+            hash().prop_map(Mutation::PrevBlockHash),
+            hash().prop_map(Mutation::MerkleRoot),
+            hash().prop_map(Mutation::StateRoot),
+            ((u128::MAX >> 4) + 1..=u128::MAX)
+                .prop_map(Mutation::EasierDifficulty),
+            prop_oneof![
+                Just(HashAlgorithm::DoubleSha256),
+                Just(HashAlgorithm::Keccak256)
+            ]
+            .prop_map(Mutation::HashAlgorithm),
+            Just(Mutation::DropCoinbase),
+            any::<u32>().prop_map(Mutation::CoinbaseHeight),
+            (1..1_000u64).prop_map(Mutation::InflateCoinbase),
+            Just(Mutation::OverflowCoinbase),
+            any::<Index>().prop_map(Mutation::DuplicateTransaction),
+            (1..1_000u64).prop_map(Mutation::InflateTransfer),
+            // transfers have at most 2 outputs
+            (2..u32::MAX).prop_map(Mutation::SpendUnknownOutput),
+            any::<Index>().prop_map(Mutation::BreakSignature),
+        ]
+    }
+
+    fn mutation() -> impl Strategy<Value = Mutation> {
+        prop_oneof![
+            consensus_mutation(),
+            hash().prop_map(Mutation::Hash),
+            any::<u64>().prop_map(Mutation::Nonce),
+            any::<u128>().prop_map(Mutation::Timestamp),
+        ]
+    }
+
+    impl Mutation {
+        // false when the mutation does not apply to this block, like changing a transfer of a block without any
+        fn apply(&self, block: &mut Block, builder: &ChainBuilder) -> bool {
+            let transactions = &mut block.transactions;
+            match self {
+                Mutation::Index(offset) => {
+                    block.index = block.index.wrapping_add(*offset)
+                }
+                Mutation::Rewind(earlier) => match builder.chain.last_block() {
+                    Some(parent) => {
+                        block.timestamp =
+                            parent.timestamp.saturating_sub(*earlier)
+                    }
+                    None => return false,
+                },
+                // a random hash equal to the old one is as good as no mutation
+                Mutation::PrevBlockHash(hash)
+                    if hash == &block.prev_block_hash =>
+                {
+                    return false
+                }
+                Mutation::PrevBlockHash(hash) => {
+                    block.prev_block_hash = hash.clone()
+                }
+                Mutation::MerkleRoot(hash) if hash == &block.merkle_root => {
+                    return false
+                }
+                Mutation::MerkleRoot(hash) => block.merkle_root = hash.clone(),
+                Mutation::StateRoot(hash) if hash == &block.state_root => {
+                    return false
+                }
+                Mutation::StateRoot(hash) => block.state_root = hash.clone(),
+                Mutation::EasierDifficulty(difficulty) => {
+                    block.difficulty = *difficulty
+                }
+                Mutation::HashAlgorithm(algorithm) => {
+                    block.hash_algorithm = *algorithm
+                }
+                Mutation::DropCoinbase => {
+                    transactions.remove(0);
+                }
+                Mutation::CoinbaseHeight(height) => {
+                    if *height == block.index {
+                        return false;
+                    }
+                    let outputs = transactions[0].outputs.clone();
+                    transactions[0] = Transaction::coinbase(*height, outputs);
+                }
+                Mutation::InflateCoinbase(extra) => {
+                    transactions[0].outputs[0].value += extra
+                }
+                Mutation::OverflowCoinbase => {
+                    let to_addr = transactions[0].outputs[0].to_addr.clone();
+                    transactions[0]
+                        .outputs
+                        .push(Output::new(to_addr, u64::MAX));
+                }
+                Mutation::DuplicateTransaction(index) => {
+                    let transaction =
+                        transactions[index.index(transactions.len())].clone();
+                    transactions.push(transaction);
+                }
+                Mutation::InflateTransfer(extra) => {
+                    let Some(transfer) = transactions.get_mut(1) else {
+                        return false;
+                    };
+                    let owner = builder.owner_of(&transfer.inputs[0].prev_out);
+                    let fee = transfer.fee().unwrap();
+                    transfer.outputs[0].value += fee + extra;
+                    owner.sign_transaction(transfer);
+                }
+                Mutation::SpendUnknownOutput(output_index) => {
+                    let Some(transfer) = transactions.get_mut(1) else {
+                        return false;
+                    };
+                    let owner = builder.owner_of(&transfer.inputs[0].prev_out);
+                    transfer.inputs[0].prev_out.index = *output_index;
+                    owner.sign_transaction(transfer);
+                }
+                Mutation::BreakSignature(index) => {
+                    let Some(transfer) = transactions.get_mut(1) else {
+                        return false;
+                    };
+                    let signature = &mut transfer.inputs[0].signature;
+                    let position = index.index(signature.len());
+                    signature[position] ^= 1;
+                }
+                Mutation::Hash(hash) => block.hash = hash.clone(),
+                Mutation::Nonce(nonce) => block.nonce = *nonce,
+                Mutation::Timestamp(timestamp) => block.timestamp = *timestamp,
+            }
+            true
+        }
+    }
+
+    // the block is refused, and the chain is exactly as it was before
+    fn check_rejected(
+        chain: &mut Blockchain,
+        block: Block,
+    ) -> Result<(), TestCaseError> {
+        let tip = chain.last_block().cloned();
+        let unspent_outputs = chain.unspent_outputs().clone();
+
+        prop_assert!(chain.verify_block(&block).is_err());
+        prop_assert!(chain.update_with_block(block).is_err());
+        prop_assert_eq!(chain.last_block(), tip.as_ref());
+        // Output has no Debug, so the sets are only compared
+        prop_assert!(chain.unspent_outputs() == &unspent_outputs);
+        Ok(())
+    }
+
+    /*
+    Ways to spoil a valid transfer, each one paired with the error the chain must answer.
+    The transfer is signed again after the change where that keeps the signature out of the way.
+    */
+    #[derive(Debug, Clone)]
+    enum Corruption {
+        Overspend(u64),
+        // outputs whose sum does not fit into a u64
+        OverflowOutputs,
+        UnknownOutput(u32),
+        WrongInputValue(u64),
+        // signed by somebody who does not own the spent output
+        StolenOutput,
+        BadSignature(Index),
+        SpendTwice,
+    }
+
+    fn corruption() -> impl Strategy<Value = Corruption> {
+        prop_oneof![
+            (1..1_000u64).prop_map(Corruption::Overspend),
+            Just(Corruption::OverflowOutputs),
+            (2..u32::MAX).prop_map(Corruption::UnknownOutput),
+            (1..1_000u64).prop_map(Corruption::WrongInputValue),
+            Just(Corruption::StolenOutput),
+            any::<Index>().prop_map(Corruption::BadSignature),
+            Just(Corruption::SpendTwice),
+        ]
+    }
+
+    impl Corruption {
+        fn apply(
+            &self,
+            mut transfer: Transaction,
+            builder: &ChainBuilder,
+        ) -> (Transaction, BlockValidationErr) {
+            let owner = builder.owner_of(&transfer.inputs[0].prev_out);
+            let expected = match self {
+                Corruption::Overspend(extra) => {
+                    transfer.outputs[0].value +=
+                        transfer.fee().unwrap() + extra;
+                    BlockValidationErr::InsufficientInputValue
+                }
+                Corruption::OverflowOutputs => {
+                    let to_addr = transfer.outputs[0].to_addr.clone();
+                    transfer.outputs.push(Output::new(to_addr, u64::MAX));
+                    BlockValidationErr::InsufficientInputValue
+                }
+                Corruption::UnknownOutput(index) => {
+                    transfer.inputs[0].prev_out.index = *index;
+                    BlockValidationErr::InvalidInput
+                }
+                Corruption::WrongInputValue(extra) => {
+                    transfer.inputs[0].value += extra;
+                    BlockValidationErr::InvalidInput
+                }
+                Corruption::StolenOutput => {
+                    Wallet::from_secret_bytes(&[0xff; 32])
+                        .sign_transaction(&mut transfer);
+                    return (transfer, BlockValidationErr::MismatchedPublicKey);
+                }
+                Corruption::BadSignature(index) => {
+                    let signature = &mut transfer.inputs[0].signature;
+                    let position = index.index(signature.len());
+                    signature[position] ^= 1;
+                    return (transfer, BlockValidationErr::InvalidSignature);
+                }
+                Corruption::SpendTwice => {
+                    let input = transfer.inputs[0].clone();
+                    transfer.inputs.push(input);
+                    BlockValidationErr::DoubleSpend
+                }
+            };
+            owner.sign_transaction(&mut transfer);
+            (transfer, expected)
+        }
+    }
+
+    // whatever the bytes, decoding does not panic, and a block that decodes encodes back to the same bytes
+    fn check_decoder(bytes: &[u8]) -> Result<Option<Block>, TestCaseError> {
+        let Ok(block) = Block::decode(bytes) else {
+            return Ok(None);
+        };
+        prop_assert_eq!(block.encode(), bytes);
+        Ok(Some(block))
+    }
+
+    proptest! {
+        // every case mines and signs a whole chain, a few dozen of them are enough
+        #![proptest_config(ProptestConfig::with_cases(48))]
+
+        #[test]
+        fn prop_valid_blocks_extend_the_chain(
+            plans in vec(block_plan(), 1..8),
+        ) {
+            let mut builder = ChainBuilder::new();
+            for plan in &plans {
+                let block = builder.next_block(plan);
+                prop_assert_eq!(builder.chain.verify_block(&block), Ok(()));
+                prop_assert_eq!(
+                    builder.chain.update_with_block(block.clone()),
+                    Ok(())
+                );
+                prop_assert_eq!(builder.chain.last_block(), Some(&block));
+            }
+
+            // a node receiving the same blocks ends up with the same ledger
+            let mut replayed = Blockchain::with_params(gen_params());
+            for block in &builder.chain.blocks {
+                prop_assert_eq!(
+                    replayed.update_with_block(block.clone()),
+                    Ok(())
+                );
+            }
+            prop_assert!(
+                replayed.unspent_outputs() == builder.chain.unspent_outputs()
+            );
+        }
+
+        #[test]
+        fn prop_reject_mutated_block(
+            plans in vec(block_plan(), 1..6),
+            mutation in mutation(),
+        ) {
+            let (prefix, last) = plans.split_at(plans.len() - 1);
+            let mut builder = ChainBuilder::with_blocks(prefix);
+            let block = builder.next_block(&last[0]);
+
+            // the stored hash no longer matches the mutated block
+            let mut mutated = block.clone();
+            prop_assume!(mutation.apply(&mut mutated, &builder));
+            prop_assume!(mutated != block);
+            check_rejected(&mut builder.chain, mutated)?;
+
+            // the rejected block left nothing behind that would stop the original block
+            prop_assert_eq!(builder.chain.update_with_block(block), Ok(()));
+        }
+
+        #[test]
+        fn prop_reject_remined_block(
+            plans in vec(block_plan(), 2..6),
+            mutation in consensus_mutation(),
+        ) {
+            let (prefix, last) = plans.split_at(plans.len() - 1);
+            let mut builder = ChainBuilder::with_blocks(prefix);
+            let block = builder.next_block(&last[0]);
+
+            // a miner that does the work again gets past the hash checks, but not past the rules
+            let mut mutated = block.clone();
+            prop_assume!(mutation.apply(&mut mutated, &builder));
+            if !matches!(mutation, Mutation::MerkleRoot(_)) {
+                mutated.merkle_root = mutated.compute_merkle_root();
+            }
+            prop_assert!(mutated.mine());
+            check_rejected(&mut builder.chain, mutated)?;
+        }
+
+        #[test]
+        fn prop_value_is_conserved(plans in vec(block_plan(), 1..8)) {
+            let builder = ChainBuilder::with_blocks(&plans);
+            let chain = &builder.chain;
+
+            let (mut minted, mut fees, mut subsidies) = (0u64, 0u64, 0u64);
+            for block in &chain.blocks {
+                let (coinbase, transfers) =
+                    block.transactions.split_first().unwrap();
+                minted += coinbase.output_value();
+                subsidies += chain.params.block_subsidy(block.index);
+                for transfer in transfers {
+                    // a transfer only moves coins, what it does not pay out goes to the miner
+                    prop_assert!(transfer.output_value() <= transfer.input_value());
+                    fees += transfer.fee().unwrap();
+                }
+            }
+
+            let supply: u64 =
+                chain.unspent_outputs().values().map(|output| output.value).sum();
+            prop_assert_eq!(supply, minted - fees);
+            // fees change hands, only the subsidies add coins
+            prop_assert_eq!(supply, subsidies);
+            let balances: u64 = builder
+                .wallets
+                .iter()
+                .map(|wallet| chain.balance(&wallet.address()))
+                .sum();
+            prop_assert_eq!(balances, supply);
+        }
+
+        #[test]
+        fn prop_reject_invalid_transaction(
+            plans in vec(block_plan(), 1..4),
+            payment in payment_plan(),
+            corruption in corruption(),
+        ) {
+            let builder = ChainBuilder::with_blocks(&plans);
+            let transfer = builder.payment(&payment, &mut HashSet::new());
+            prop_assume!(transfer.is_some());
+            let transfer = transfer.unwrap();
+            prop_assert_eq!(
+                builder.chain.verify_transaction(&transfer),
+                Ok(transfer.fee().unwrap())
+            );
+
+            let (transfer, expected) = corruption.apply(transfer, &builder);
+            prop_assert_eq!(
+                builder.chain.verify_transaction(&transfer),
+                Err(expected)
+            );
+        }
+
+        #[test]
+        fn prop_decode_arbitrary_bytes(bytes in vec(any::<u8>(), 0..512)) {
+            check_decoder(&bytes)?;
+            // most random bytes already fail at the version byte, these get further
+            check_decoder(&[vec![CODEC_VERSION], bytes].concat())?;
+        }
+
+        #[test]
+        fn prop_decode_corrupted_block(
+            plans in vec(block_plan(), 1..4),
+            position in any::<Index>(),
+            byte in any::<u8>(),
+            truncate in any::<bool>(),
+        ) {
+            let (prefix, last) = plans.split_at(plans.len() - 1);
+            let builder = ChainBuilder::with_blocks(prefix);
+            let block = builder.next_block(&last[0]);
+            let mut bytes = block.encode();
+            prop_assert_eq!(check_decoder(&bytes)?, Some(block.clone()));
+
+            let position = position.index(bytes.len());
+            if truncate {
+                bytes.truncate(position);
+            } else {
+                bytes[position] = byte;
+            }
+            // corrupted bytes either do not decode, or decode to a block the chain refuses
+            if let Some(decoded) = check_decoder(&bytes)? {
+                if decoded != block {
+                    prop_assert!(builder.chain.verify_block(&decoded).is_err());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        block::Block,
+        hashtable::{HashAlgorithm, Hashtable},
+        merkle::verify_merkle_proof,
+        transactions::{Output, Transaction},
+    };
+
+    const ALGORITHMS: [HashAlgorithm; 3] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::DoubleSha256,
+        HashAlgorithm::Keccak256,
+    ];
+
+    fn digest_hex(algorithm: HashAlgorithm, bytes: &[u8]) -> String {
+        hex::encode(algorithm.digest(bytes))
+    }
+
+    #[test]
+    fn test_sha256_vectors() {
+        assert_eq!(
+            digest_hex(HashAlgorithm::Sha256, b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            digest_hex(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_double_sha256_vectors() {
+        assert_eq!(
+            digest_hex(HashAlgorithm::DoubleSha256, b""),
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"
+        );
+        assert_eq!(
+            digest_hex(HashAlgorithm::DoubleSha256, b"abc"),
+            "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358"
+        );
+
+        // the 80 bytes header of Bitcoin's genesis block,
+        // Bitcoin shows its hashes with the byte order reversed
+        let header = hex::decode(
+            "01000000000000000000000000000000000000000000000000000000000000000000\
+             00003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a\
+             29ab5f49ffff001d1dac2b7c",
+        )
+        .unwrap();
+        let mut hash = HashAlgorithm::DoubleSha256.digest(&header);
+        hash.reverse();
+        assert_eq!(
+            hex::encode(hash),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+
+    #[test]
+    fn test_keccak256_vectors() {
+        // Ethereum's Keccak-256, not the padded SHA3-256 (which gives a7ffc6f8... for "")
+        assert_eq!(
+            digest_hex(HashAlgorithm::Keccak256, b""),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            digest_hex(HashAlgorithm::Keccak256, b"abc"),
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+        );
+
+        // Ethereum's hash of an empty list of uncles, the RLP encoding of [] is 0xc0
+        assert_eq!(
+            digest_hex(HashAlgorithm::Keccak256, &[0xc0]),
+            "1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347"
+        );
+    }
+
+    #[test]
+    fn test_block_records_hash_algorithm() {
+        let transactions: Vec<Transaction> = (0..3)
+            .map(|i| {
+                Transaction::coinbase(
+                    i,
+                    vec![Output::new("miner_address".to_owned(), 50)],
+                )
+            })
+            .collect();
+        let block = Block::new(0, 1, vec![0; 32], transactions, 0, u128::MAX);
+        assert_eq!(block.hash_algorithm, HashAlgorithm::Sha256);
+
+        let mut hashes = vec![];
+        let mut roots = vec![];
+        for algorithm in ALGORITHMS {
+            let block = block.clone().with_hash_algorithm(algorithm);
+            assert_eq!(block.hash(), algorithm.digest(&block.bytes()));
+            assert_eq!(block.hash(), block.hash_with(algorithm));
+
+            // transaction hashes do not depend on the block, only the tree above them does
+            let proof = block.merkle_proof(2).unwrap();
+            assert_eq!(proof.algorithm, algorithm);
+            assert!(verify_merkle_proof(
+                &block.transactions[2].hash(),
+                &proof,
+                &block.merkle_root
+            ));
+
+            hashes.push(block.hash());
+            roots.push(block.merkle_root);
+        }
+        hashes.dedup();
+        roots.dedup();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(roots.len(), 3);
+
+        // the algorithm is part of the hashed header, so flipping it alone changes the hash
+        let mut flipped = block.clone();
+        flipped.hash_algorithm = HashAlgorithm::Keccak256;
+        assert_ne!(
+            flipped.hash_with(HashAlgorithm::Sha256),
+            block.hash_with(HashAlgorithm::Sha256)
+        );
+    }
+}
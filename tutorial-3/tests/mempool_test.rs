@@ -0,0 +1,303 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        block::Block,
+        blockchain::{BlockValidationErr, Blockchain, ChainParams},
+        codec::Encode,
+        hashtable::Hashtable,
+        mempool::{Mempool, MempoolError},
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    // every hash satisfies the maximum difficulty, so mining finds a nonce right away
+    const EASY_DIFFICULTY: u128 = u128::MAX;
+
+    const MAX_BLOCK_SIZE: usize = 100_000;
+
+    /*
+    Chain with a genesis block paying alice 100 coins in each of `cnt` outputs.
+    Returns the chain, alice's wallet and the out points of her outputs.
+    */
+    fn gen_chain_paying_alice(cnt: u32) -> (Blockchain, Wallet, Vec<OutPoint>) {
+        let alice = Wallet::new();
+        let mut chain = Blockchain::with_params(ChainParams {
+            initial_subsidy: 100 * cnt as u64,
+            initial_difficulty: EASY_DIFFICULTY,
+            ..ChainParams::default()
+        });
+
+        let outputs = (0..cnt)
+            .map(|_| Output::new(alice.address(), 100))
+            .collect();
+        let coinbase = Transaction::coinbase(0, outputs);
+        let out_points = (0..cnt)
+            .map(|index| OutPoint::new(coinbase.hash(), index))
+            .collect();
+
+        let mut genesis =
+            Block::new(0, 1, vec![0; 32], vec![coinbase], 0, EASY_DIFFICULTY);
+        genesis.hash = genesis.hash();
+        chain.update_with_block(genesis).unwrap();
+        (chain, alice, out_points)
+    }
+
+    // alice spends the 100 coins of the out point, paying `fee` and sending the rest to `to`
+    fn gen_transfer(
+        from: &Wallet,
+        out_point: &OutPoint,
+        to: &str,
+        fee: u64,
+    ) -> Transaction {
+        let input = Input::new(out_point.tx_hash.clone(), out_point.index, 100);
+        let mut transaction = Transaction::new(
+            vec![input],
+            vec![Output::new(to.to_owned(), 100 - fee)],
+        );
+        from.sign_transaction(&mut transaction);
+        transaction
+    }
+
+    fn mine_template(chain: &Blockchain, mempool: &Mempool) -> Block {
+        let mut block =
+            mempool.block_template(chain, "miner_address", MAX_BLOCK_SIZE);
+        assert!(block.mine());
+        block
+    }
+
+    #[test]
+    fn test_admit_valid_transactions() {
+        let (chain, alice, out_points) = gen_chain_paying_alice(2);
+        let mut mempool = Mempool::new();
+
+        let transfer = gen_transfer(&alice, &out_points[0], "bob", 10);
+        let tx_hash = mempool.add(&chain, transfer.clone()).unwrap();
+        assert!(mempool.contains(&tx_hash));
+        let entry = mempool.get(&tx_hash).unwrap();
+        assert_eq!(entry.fee, 10);
+        assert_eq!(entry.size, transfer.encode().len());
+
+        assert_eq!(mempool.add(&chain, transfer), Err(MempoolError::Known));
+
+        // a second spend of the same output is refused, even with a higher fee
+        let conflict = gen_transfer(&alice, &out_points[0], "carol", 50);
+        assert_eq!(mempool.add(&chain, conflict), Err(MempoolError::Conflict));
+
+        // and so is anything the chain would refuse
+        let mut forged = gen_transfer(&alice, &out_points[1], "bob", 10);
+        forged.outputs[0].value = 95;
+        assert_eq!(
+            mempool.add(&chain, forged),
+            Err(MempoolError::Invalid(BlockValidationErr::InvalidSignature))
+        );
+        let unknown = OutPoint::new(vec![9; 32], 0);
+        assert_eq!(
+            mempool.add(&chain, gen_transfer(&alice, &unknown, "bob", 10)),
+            Err(MempoolError::Invalid(BlockValidationErr::InvalidInput))
+        );
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_order_by_fee_rate() {
+        let (chain, alice, out_points) = gen_chain_paying_alice(3);
+        let mut mempool = Mempool::new();
+
+        for (out_point, fee) in out_points.iter().zip([5, 20, 10]) {
+            let transfer = gen_transfer(&alice, out_point, "bob", fee);
+            mempool.add(&chain, transfer).unwrap();
+        }
+        let fees: Vec<u64> = mempool
+            .sorted_by_fee_rate()
+            .iter()
+            .map(|entry| entry.fee)
+            .collect();
+        assert_eq!(fees, vec![20, 10, 5]);
+
+        // a larger transaction needs a higher fee to reach the same rate
+        let (chain, alice, out_points) = gen_chain_paying_alice(3);
+        let mut mempool = Mempool::new();
+        let small = gen_transfer(&alice, &out_points[0], "bob", 10);
+        let inputs = out_points[1..]
+            .iter()
+            .map(|out_point| {
+                Input::new(out_point.tx_hash.clone(), out_point.index, 100)
+            })
+            .collect();
+        let mut large =
+            Transaction::new(inputs, vec![Output::new("bob".to_owned(), 188)]);
+        alice.sign_transaction(&mut large);
+        let small_hash = mempool.add(&chain, small).unwrap();
+        mempool.add(&chain, large).unwrap();
+        assert_eq!(
+            mempool.sorted_by_fee_rate()[0].transaction.hash(),
+            small_hash
+        );
+    }
+
+    #[test]
+    fn test_block_template() {
+        let (mut chain, alice, out_points) = gen_chain_paying_alice(3);
+        let mut mempool = Mempool::new();
+        let mut transfers = vec![];
+        for (out_point, fee) in out_points.iter().zip([5, 20, 10]) {
+            let transfer = gen_transfer(&alice, out_point, "bob", fee);
+            mempool.add(&chain, transfer.clone()).unwrap();
+            transfers.push(transfer);
+        }
+
+        let block = mine_template(&chain, &mempool);
+        assert_eq!(block.index, 1);
+        assert_eq!(block.difficulty, chain.next_difficulty());
+        assert_eq!(block.transactions.len(), 4);
+        assert_eq!(block.transactions[1], transfers[1]);
+        assert_eq!(block.transactions[2], transfers[2]);
+        assert_eq!(block.transactions[3], transfers[0]);
+
+        // the coinbase collects the subsidy of block 1 (300) and all the fees
+        let coinbase = &block.transactions[0];
+        assert_eq!(coinbase.coinbase_height(), Some(1));
+        assert_eq!(coinbase.output_value(), 300 + 35);
+
+        chain.update_with_block(block.clone()).unwrap();
+        mempool.remove_block_transactions(&chain, &block);
+        assert!(mempool.is_empty());
+
+        // with a size limit only the best transactions make it into the block
+        let (chain, alice, out_points) = gen_chain_paying_alice(3);
+        let mut mempool = Mempool::new();
+        for (out_point, fee) in out_points.iter().zip([5, 20, 10]) {
+            let transfer = gen_transfer(&alice, out_point, "bob", fee);
+            mempool.add(&chain, transfer).unwrap();
+        }
+        let coinbase_size = block.transactions[0].encode().len();
+        let transfer_size = transfers[0].encode().len();
+        let max_size = coinbase_size + 2 * transfer_size;
+        let block = mempool.block_template(&chain, "miner_address", max_size);
+        let fees: Vec<u64> = block.transactions[1..]
+            .iter()
+            .map(|transaction| transaction.fee().unwrap())
+            .collect();
+        assert_eq!(fees, vec![20, 10]);
+        assert_eq!(block.transactions[0].output_value(), 300 + 30);
+    }
+
+    #[test]
+    fn test_follow_reorgs() {
+        let (mut chain, alice, out_points) = gen_chain_paying_alice(2);
+        let events = chain.subscribe();
+        let mut mempool = Mempool::new();
+        let genesis = chain.last_block().unwrap().clone();
+
+        let mined = gen_transfer(&alice, &out_points[0], "bob", 10);
+        let pending = gen_transfer(&alice, &out_points[1], "bob", 10);
+        let conflict = gen_transfer(&alice, &out_points[1], "carol", 10);
+        mempool.add(&chain, mined.clone()).unwrap();
+
+        // block 1a mines the first transfer and a conflict of the pending one
+        let mut block_1a = Block::new(
+            1,
+            genesis.timestamp + 1,
+            genesis.hash.clone(),
+            vec![
+                Transaction::coinbase(1, vec![]),
+                mined.clone(),
+                conflict.clone(),
+            ],
+            0,
+            EASY_DIFFICULTY,
+        );
+        block_1a.hash = block_1a.hash();
+        mempool.add(&chain, pending.clone()).unwrap();
+        chain.update_with_block(block_1a).unwrap();
+        mempool.update_with_reorg(&chain, &events.try_recv().unwrap());
+        assert!(mempool.is_empty());
+
+        // a longer branch without those transactions takes over,
+        // so they are waiting to be mined again
+        let mut parent = genesis;
+        for index in 1..3 {
+            let mut block = Block::new(
+                index,
+                parent.timestamp + 1,
+                parent.hash.clone(),
+                vec![Transaction::coinbase(index, vec![])],
+                0,
+                EASY_DIFFICULTY,
+            );
+            block.hash = block.hash();
+            chain.update_with_block(block.clone()).unwrap();
+            parent = block;
+        }
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.disconnected.len(), 1);
+        mempool.update_with_reorg(&chain, &event);
+
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool.contains(&mined.hash()));
+        assert!(mempool.contains(&conflict.hash()));
+        assert!(!mempool.contains(&pending.hash()));
+    }
+
+    #[test]
+    fn test_readmit_dependent_transactions() {
+        let (mut chain, alice, out_points) = gen_chain_paying_alice(2);
+        let events = chain.subscribe();
+        let mut mempool = Mempool::new();
+        let genesis = chain.last_block().unwrap().clone();
+
+        // alice pays herself, and spends that output again before it is mined
+        let parent = gen_transfer(&alice, &out_points[0], &alice.address(), 10);
+        let mut child = Transaction::new(
+            vec![Input::new(parent.hash(), 0, 90)],
+            vec![Output::new("bob".to_owned(), 80)],
+        );
+        alice.sign_transaction(&mut child);
+        mempool.add(&chain, parent.clone()).unwrap();
+        mempool.add(&chain, child.clone()).unwrap();
+
+        // a block only spends outputs of the chain, so the child waits for the next one
+        let block_1 = mine_template(&chain, &mempool);
+        assert_eq!(block_1.transactions[1], parent);
+        assert_eq!(block_1.transactions.len(), 2);
+        chain.update_with_block(block_1).unwrap();
+        mempool.update_with_reorg(&chain, &events.try_recv().unwrap());
+        assert_eq!(mempool.len(), 1);
+
+        let block_2 = mine_template(&chain, &mempool);
+        assert_eq!(block_2.transactions[1..], [child.clone()]);
+        chain.update_with_block(block_2).unwrap();
+        mempool.update_with_reorg(&chain, &events.try_recv().unwrap());
+        assert!(mempool.is_empty());
+
+        let pending = gen_transfer(&alice, &out_points[1], "bob", 10);
+        mempool.add(&chain, pending.clone()).unwrap();
+
+        // a heavier branch without the parent and the child takes over
+        let mut parent_block = genesis;
+        for index in 1..4 {
+            let mut block = Block::new(
+                index,
+                parent_block.timestamp + 1,
+                parent_block.hash.clone(),
+                vec![Transaction::coinbase(index, vec![])],
+                0,
+                EASY_DIFFICULTY,
+            );
+            block.hash = block.hash();
+            chain.update_with_block(block.clone()).unwrap();
+            parent_block = block;
+        }
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.disconnected.len(), 2);
+        mempool.update_with_reorg(&chain, &event);
+
+        // both are back, the oldest first, followed by what was waiting in the pool
+        let readmitted: Vec<Transaction> = mempool
+            .sorted_by_arrival()
+            .into_iter()
+            .map(|entry| entry.transaction.clone())
+            .collect();
+        assert_eq!(readmitted, vec![parent, child, pending]);
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use blockchain::{
+        block::Block,
+        hashtable::Hashtable,
+        merkle::{merkle_proof, merkle_root, verify_merkle_proof},
+        now,
+        transactions::{Output, Transaction},
+    };
+
+    fn gen_leaves(cnt: u8) -> Vec<Vec<u8>> {
+        (0..cnt)
+            .map(|i| crypto_hash::digest(crypto_hash::Algorithm::SHA256, &[i]))
+            .collect()
+    }
+
+    fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+        let mut bytes = left.to_vec();
+        bytes.extend(right);
+        crypto_hash::digest(crypto_hash::Algorithm::SHA256, &bytes)
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(merkle_root(&[]), vec![0; 32]);
+
+        // a single leaf is its own root
+        let leaves = gen_leaves(1);
+        assert_eq!(merkle_root(&leaves), leaves[0]);
+
+        // with 3 leaves the last one is paired with itself
+        let leaves = gen_leaves(3);
+        let expected = hash_pair(
+            &hash_pair(&leaves[0], &leaves[1]),
+            &hash_pair(&leaves[2], &leaves[2]),
+        );
+        assert_eq!(merkle_root(&leaves), expected);
+    }
+
+    #[test]
+    fn test_merkle_proof_for_every_leaf() {
+        for cnt in 1..=9 {
+            let leaves = gen_leaves(cnt);
+            let root = merkle_root(&leaves);
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).unwrap();
+                assert!(verify_merkle_proof(leaf, &proof, &root));
+            }
+            assert!(merkle_proof(&leaves, leaves.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_merkle_proof_rejects_tampering() {
+        let leaves = gen_leaves(6);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 4).unwrap();
+
+        // the proof of one leaf does not prove another
+        assert!(!verify_merkle_proof(&leaves[3], &proof, &root));
+
+        // a modified sibling leads to another root
+        let mut tampered = proof.clone();
+        tampered.siblings[1][0] ^= 1;
+        assert!(!verify_merkle_proof(&leaves[4], &tampered, &root));
+
+        // the index decides on which side each sibling sits
+        let mut moved = proof.clone();
+        moved.index = 5;
+        assert!(!verify_merkle_proof(&leaves[4], &moved, &root));
+
+        // an index beyond what the siblings can cover is rejected
+        let mut too_far = proof;
+        too_far.index += 1 << too_far.siblings.len();
+        assert!(!verify_merkle_proof(&leaves[4], &too_far, &root));
+    }
+
+    #[test]
+    fn test_block_merkle_root() {
+        let transactions = (0..5)
+            .map(|i| {
+                Transaction::coinbase(
+                    i,
+                    vec![Output::new("miner_address".to_owned(), 50)],
+                )
+            })
+            .collect::<Vec<Transaction>>();
+        let block = Block::new(0, now(), vec![0; 32], transactions, 0, 1);
+
+        assert_eq!(block.merkle_root, block.compute_merkle_root());
+        assert_eq!(block.merkle_root, merkle_root(&block.transaction_hashes()));
+
+        let proof = block.merkle_proof(3).unwrap();
+        assert!(verify_merkle_proof(
+            &block.transactions[3].hash(),
+            &proof,
+            &block.merkle_root
+        ));
+        assert!(block.merkle_proof(5).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use blockchain::{
+        block::{check_difficulty, Block},
+        hashtable::Hashtable,
+        miner::{nonce_ranges, Miner, MiningStats},
+        transactions::{Output, Transaction},
+    };
+
+    // every hash satisfies the maximum difficulty, so the first nonce of every thread is a solution
+    const EASY_DIFFICULTY: u128 = u128::MAX;
+
+    // only a hash whose difficulty bytes are all zero meets it, which never happens in a test
+    const IMPOSSIBLE_DIFFICULTY: u128 = 1;
+
+    fn gen_block(index: u32, difficulty: u128) -> Block {
+        let coinbase = Transaction::coinbase(
+            index,
+            vec![Output::new("miner_address".to_owned(), 50)],
+        );
+        Block::new(index, 0, vec![0; 32], vec![coinbase], 0, difficulty)
+    }
+
+    #[test]
+    fn test_nonce_ranges() {
+        assert_eq!(nonce_ranges(1), vec![0..u64::MAX]);
+        assert_eq!(nonce_ranges(0), vec![0..u64::MAX]);
+
+        let ranges = nonce_ranges(3);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].start, 0);
+        assert_eq!(ranges[2].end, u64::MAX);
+        // back to back, so no nonce is tried twice and none is skipped
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn test_parallel_mine() {
+        let miner = Miner::new(4);
+        assert_eq!(miner.threads(), 4);
+        assert_eq!(Miner::new(0).threads(), 1);
+
+        let block = miner.mine(gen_block(0, u128::MAX >> 8)).unwrap();
+        assert_eq!(block.hash, block.hash());
+        assert!(check_difficulty(&block.hash, block.difficulty));
+        assert!(miner.stats().hashes >= 1);
+    }
+
+    #[test]
+    fn test_first_solution_cancels_other_threads() {
+        let miner = Miner::new(4);
+        let block = miner.mine(gen_block(0, EASY_DIFFICULTY)).unwrap();
+
+        // the solution is the first nonce of one of the ranges,
+        // and the other threads stopped at their first nonce too
+        let starts: Vec<u64> =
+            nonce_ranges(4).iter().map(|range| range.start).collect();
+        assert!(starts.contains(&block.nonce));
+        assert!(miner.stats().hashes <= 4);
+    }
+
+    #[test]
+    fn test_refresh_template() {
+        let miner = Miner::new(2);
+        let (updates, receiver) = mpsc::channel();
+
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            // only the newest of the queued templates is mined
+            updates.send(gen_block(1, IMPOSSIBLE_DIFFICULTY)).unwrap();
+            updates.send(gen_block(2, EASY_DIFFICULTY)).unwrap();
+            updates
+        });
+
+        let block = miner
+            .mine_with_updates(gen_block(0, IMPOSSIBLE_DIFFICULTY), &receiver)
+            .unwrap();
+        assert_eq!(block.index, 2);
+        assert!(check_difficulty(&block.hash, block.difficulty));
+        drop(sender.join());
+    }
+
+    #[test]
+    fn test_stop_mining() {
+        // closing the updates channel
+        let miner = Miner::new(2);
+        let (updates, receiver) = mpsc::channel::<Block>();
+        drop(updates);
+        assert_eq!(
+            miner.mine_with_updates(
+                gen_block(0, IMPOSSIBLE_DIFFICULTY),
+                &receiver
+            ),
+            None
+        );
+
+        // stopping the miner from another thread
+        let miner = Arc::new(Miner::new(2));
+        let stopper = miner.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stopper.stop();
+        });
+        assert_eq!(miner.mine(gen_block(0, IMPOSSIBLE_DIFFICULTY)), None);
+        assert!(miner.is_stopped());
+        assert!(miner.stats().hashes > 0);
+
+        // a stopped miner stays stopped
+        assert_eq!(miner.mine(gen_block(0, EASY_DIFFICULTY)), None);
+    }
+
+    #[test]
+    fn test_mining_stats() {
+        let stats = MiningStats {
+            hashes: 1_000,
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(stats.hashrate(), 500.0);
+
+        // about 1024 hashes per block at this difficulty, so about 2 seconds at 500 hashes per second
+        let expected = stats.expected_block_time(u128::MAX >> 10).unwrap();
+        assert!((expected.as_secs_f64() - 2.048).abs() < 1e-6);
+
+        let idle = MiningStats {
+            hashes: 0,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(idle.hashrate(), 0.0);
+        assert_eq!(idle.expected_block_time(EASY_DIFFICULTY), None);
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use blockchain::{
+        blockchain::ChainParams,
+        codec::Encode,
+        network::{read_message, write_message, InvItem, Message},
+        transactions::{Input, Output, Transaction},
+    };
+
+    fn gen_messages() -> Vec<Message> {
+        let block = ChainParams {
+            initial_difficulty: u128::MAX >> 4,
+            ..ChainParams::default()
+        }
+        .genesis_block();
+        let transaction = Transaction::new(
+            vec![Input::new(vec![1; 32], 0, 10)],
+            vec![Output::new("bob".to_owned(), 10)],
+        );
+
+        vec![
+            Message::Version {
+                version: 1,
+                block_count: 42,
+            },
+            Message::Verack,
+            Message::Inv(vec![
+                InvItem::Tx(vec![1; 32]),
+                InvItem::Block(vec![2; 32]),
+            ]),
+            Message::GetData(vec![InvItem::Block(vec![3; 32])]),
+            Message::GetBlocks { from_height: 7 },
+            Message::Block(block),
+            Message::Tx(transaction),
+        ]
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let messages = gen_messages();
+        let mut stream = vec![];
+        for message in &messages {
+            write_message(&mut stream, message).unwrap();
+        }
+
+        // the frames come back one by one, in the order they were written
+        let mut reader = Cursor::new(stream);
+        for message in messages {
+            assert_eq!(read_message(&mut reader).unwrap(), message);
+        }
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_reject_malformed_frames() {
+        // a frame claiming more bytes than any message may have
+        let mut reader = Cursor::new(u32::MAX.to_le_bytes().to_vec());
+        assert!(read_message(&mut reader).is_err());
+
+        // a frame cut short
+        let mut frame = vec![];
+        write_message(&mut frame, &Message::GetBlocks { from_height: 1 })
+            .unwrap();
+        frame.pop();
+        assert!(read_message(&mut Cursor::new(frame)).is_err());
+
+        // an unknown message tag
+        let mut payload = Message::Verack.encode();
+        payload[1] = 99;
+        let mut frame = (payload.len() as u32).to_le_bytes().to_vec();
+        frame.extend(payload);
+        assert!(read_message(&mut Cursor::new(frame)).is_err());
+    }
+}
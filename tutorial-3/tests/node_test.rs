@@ -0,0 +1,268 @@
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use blockchain::{
+        block::Block,
+        blockchain::{Blockchain, ChainParams},
+        hashtable::Hashtable,
+        mempool::Mempool,
+        network::{
+            read_message, write_message, InvItem, Message, PROTOCOL_VERSION,
+        },
+        node::{
+            Node, NodeConfig, NodeErrors, NodeHandle, NodeStatus,
+            MAX_BLOCK_SIZE,
+        },
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    // easy enough to mine a block with a handful of hashes
+    fn gen_params() -> ChainParams {
+        ChainParams {
+            initial_difficulty: u128::MAX >> 4,
+            ..ChainParams::default()
+        }
+    }
+
+    fn start_node(
+        peers: Vec<&NodeHandle>,
+        miner: Option<String>,
+    ) -> NodeHandle {
+        let node = Node::bind(NodeConfig {
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+            peers: peers.iter().map(|peer| peer.local_addr()).collect(),
+            params: gen_params(),
+            miner,
+            mining_threads: 2,
+        })
+        .unwrap();
+        let handle = node.handle();
+        thread::spawn(move || node.run());
+        handle
+    }
+
+    // polls the node until its status satisfies the condition, panics after a few seconds
+    fn wait_until(
+        handle: &NodeHandle,
+        condition: impl Fn(&NodeStatus) -> bool,
+    ) -> NodeStatus {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let status = handle.status().unwrap();
+            if condition(&status) {
+                return status;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "timed out, last status {:?}",
+                status
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    // mine the next block on a local copy of the chain, paying the miner
+    fn mine_next_block(chain: &mut Blockchain, miner: &str) -> Block {
+        let mut block =
+            Mempool::new().block_template(chain, miner, MAX_BLOCK_SIZE);
+        assert!(block.mine());
+        chain.update_with_block(block.clone()).unwrap();
+        block
+    }
+
+    #[test]
+    fn test_initial_block_download() {
+        let node_a = start_node(vec![], None);
+        let mut chain = Blockchain::with_params(gen_params());
+        chain
+            .update_with_block(gen_params().genesis_block())
+            .unwrap();
+        for _ in 0..5 {
+            node_a.submit_block(mine_next_block(&mut chain, "miner_address"));
+        }
+        let status_a = wait_until(&node_a, |status| status.block_count == 6);
+
+        // a node joining later downloads the whole chain,
+        // and so does a node that only knows the latecomer
+        let node_b = start_node(vec![&node_a], None);
+        let node_c = start_node(vec![&node_b], None);
+        wait_until(&node_b, |status| status.tip == status_a.tip);
+        wait_until(&node_c, |status| status.tip == status_a.tip);
+
+        for node in [node_a, node_b, node_c] {
+            node.shutdown();
+        }
+    }
+
+    #[test]
+    fn test_gossip_blocks_and_transactions() {
+        let alice = Wallet::new();
+        let node_a = start_node(vec![], None);
+        let node_b = start_node(vec![&node_a], None);
+        let node_c = start_node(vec![&node_b], None);
+        wait_until(&node_b, |status| status.peer_count == 2);
+
+        // a block submitted at one end of the line reaches the other end
+        let mut chain = Blockchain::with_params(gen_params());
+        chain
+            .update_with_block(gen_params().genesis_block())
+            .unwrap();
+        let block = mine_next_block(&mut chain, &alice.address());
+        node_a.submit_block(block.clone());
+        wait_until(&node_c, |status| status.tip == block.hash);
+
+        // and so does a transaction spending alice's reward
+        let coinbase = &block.transactions[0];
+        let out_point = OutPoint::new(coinbase.hash(), 0);
+        let value = coinbase.output_value();
+        let mut transfer = Transaction::new(
+            vec![Input::new(out_point.tx_hash, out_point.index, value)],
+            vec![Output::new("bob".to_owned(), value - 1)],
+        );
+        alice.sign_transaction(&mut transfer);
+        node_c.submit_transaction(transfer);
+        wait_until(&node_a, |status| status.mempool_len == 1);
+
+        for node in [node_a, node_b, node_c] {
+            node.shutdown();
+        }
+    }
+
+    #[test]
+    fn test_follow_mining_node() {
+        let miner = start_node(vec![], Some("miner_address".to_owned()));
+        let follower = start_node(vec![&miner], None);
+
+        let status = wait_until(&follower, |status| status.block_count >= 4);
+        // the follower never goes ahead of the miner
+        let miner_status = miner.status().unwrap();
+        assert!(miner_status.block_count >= status.block_count);
+        assert_eq!(status.mining, None);
+        assert!(miner_status.mining.unwrap().hashes >= 3);
+
+        miner.shutdown();
+        follower.shutdown();
+    }
+
+    #[test]
+    fn test_connect_orphans_after_many_orphans() {
+        let node = start_node(vec![], None);
+
+        // blocks whose parents nobody has fill the node up with orphans
+        for i in 0..100u64 {
+            let mut prev_block_hash = vec![0xee; 32];
+            prev_block_hash[..8].copy_from_slice(&i.to_le_bytes());
+            let mut block =
+                Block::new(1, 1, prev_block_hash, vec![], 0, u128::MAX);
+            block.hash = block.hash();
+            node.submit_block(block);
+        }
+
+        // a newer orphan still gets in, and connects once its parent shows up
+        let mut chain = Blockchain::with_params(gen_params());
+        chain
+            .update_with_block(gen_params().genesis_block())
+            .unwrap();
+        let parent = mine_next_block(&mut chain, "miner_address");
+        let child = mine_next_block(&mut chain, "miner_address");
+        node.submit_block(child.clone());
+        node.submit_block(parent);
+        let status = wait_until(&node, |status| status.block_count == 3);
+        assert_eq!(status.tip, child.hash);
+
+        node.shutdown();
+    }
+
+    #[test]
+    fn test_give_up_on_undelivered_blocks() {
+        let mut chain = Blockchain::with_params(gen_params());
+        chain
+            .update_with_block(gen_params().genesis_block())
+            .unwrap();
+        let blocks: Vec<Block> = (0..2)
+            .map(|_| mine_next_block(&mut chain, "miner_address"))
+            .collect();
+
+        // a peer with a longer chain, played by the test
+        let node = start_node(vec![], None);
+        let mut stream = TcpStream::connect(node.local_addr()).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let version = Message::Version {
+            version: PROTOCOL_VERSION,
+            block_count: 3,
+        };
+        write_message(&mut stream, &version).unwrap();
+        assert!(matches!(
+            read_message(&mut reader).unwrap(),
+            Message::Version { .. }
+        ));
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Verack);
+        let get_blocks = Message::GetBlocks { from_height: 1 };
+        assert_eq!(read_message(&mut reader).unwrap(), get_blocks);
+
+        // the blocks are announced, asked for, and never sent
+        let items: Vec<InvItem> = blocks
+            .iter()
+            .map(|block| InvItem::Block(block.hash.clone()))
+            .collect();
+        write_message(&mut stream, &Message::Inv(items.clone())).unwrap();
+        assert_eq!(read_message(&mut reader).unwrap(), Message::GetData(items));
+
+        // after a while the node asks again instead of waiting forever
+        let asked_at = Instant::now();
+        assert_eq!(read_message(&mut reader).unwrap(), get_blocks);
+        assert!(asked_at.elapsed() >= Duration::from_secs(1));
+
+        // this time the peer delivers
+        for block in &blocks {
+            write_message(&mut stream, &Message::Block(block.clone())).unwrap();
+        }
+        let status = wait_until(&node, |status| status.block_count == 3);
+        assert_eq!(status.tip, blocks[1].hash);
+
+        node.shutdown();
+    }
+
+    #[test]
+    fn test_count_errors() {
+        let node = start_node(vec![], None);
+        assert_eq!(node.status().unwrap().errors, NodeErrors::default());
+
+        // a transfer of coins that do not exist
+        let alice = Wallet::new();
+        let mut transfer = Transaction::new(
+            vec![Input::new(vec![9; 32], 0, 10)],
+            vec![Output::new("bob".to_owned(), 10)],
+        );
+        alice.sign_transaction(&mut transfer);
+        node.submit_transaction(transfer);
+        wait_until(&node, |status| status.errors.rejected_transactions == 1);
+
+        // a block changed after it was mined
+        let mut chain = Blockchain::with_params(gen_params());
+        chain
+            .update_with_block(gen_params().genesis_block())
+            .unwrap();
+        let mut block = mine_next_block(&mut chain, "miner_address");
+        block.timestamp += 1;
+        node.submit_block(block);
+        wait_until(&node, |status| status.errors.rejected_blocks == 1);
+
+        // a peer nobody listens at
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = closed.local_addr().unwrap();
+        drop(closed);
+        node.connect(addr);
+        let status =
+            wait_until(&node, |status| status.errors.failed_connects == 1);
+        assert_eq!(status.block_count, 1);
+        assert_eq!(status.errors.lost_peers, 0);
+
+        node.shutdown();
+    }
+}
@@ -0,0 +1,306 @@
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use blockchain::{
+        block::{Block, BlockHeader},
+        blockchain::{BlockValidationErr, Blockchain, ChainParams},
+        codec::{Decode, Encode},
+        hashtable::{HashAlgorithm, Hashtable},
+        node::{Node, NodeConfig},
+        spv::{HeaderChain, LightClient},
+        transactions::{Input, OutPoint, Output, Transaction},
+        wallet::Wallet,
+    };
+
+    /*
+    Same idea as in blockchain_test: easy to mine, and blocks 50 ms apart against a target of 100 ms
+    make every retarget (each 4 blocks) harder, so the headers cross a few difficulty changes.
+    */
+    fn gen_params() -> ChainParams {
+        ChainParams {
+            initial_subsidy: 300,
+            initial_difficulty: u128::MAX >> 4,
+            retarget_interval: 4,
+            target_block_time: 100,
+            max_retarget_factor: 4,
+            ..ChainParams::default()
+        }
+    }
+
+    fn gen_mined_block(
+        chain: &Blockchain,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let (index, timestamp, prev_block_hash) = match chain.last_block() {
+            Some(last) => {
+                (last.index + 1, last.timestamp + 50, last.hash.clone())
+            }
+            None => (0, 1, vec![0; 32]),
+        };
+        let coinbase = Transaction::coinbase(
+            index,
+            vec![Output::new("miner_address".to_owned(), 0)],
+        );
+        let mut block = Block::new(
+            index,
+            timestamp,
+            prev_block_hash,
+            [vec![coinbase], transactions].concat(),
+            0,
+            chain.next_difficulty(),
+        );
+        assert!(block.mine());
+        block
+    }
+
+    /*
+    Chain of 10 blocks: the genesis block pays alice three outputs of 100 coins,
+    block 1 carries three transfers spending them, the other blocks only have their coinbase.
+    */
+    fn gen_chain() -> Blockchain {
+        let alice = Wallet::new();
+        let mut chain = Blockchain::with_params(gen_params());
+
+        let coinbase = Transaction::coinbase(
+            0,
+            (0..3).map(|_| Output::new(alice.address(), 100)).collect(),
+        );
+        let coinbase_hash = coinbase.hash();
+        let mut genesis = Block::new(
+            0,
+            1,
+            vec![0; 32],
+            vec![coinbase],
+            0,
+            chain.next_difficulty(),
+        );
+        assert!(genesis.mine());
+        chain.update_with_block(genesis).unwrap();
+
+        let transfers = ["bob", "carol", "dave"]
+            .iter()
+            .enumerate()
+            .map(|(index, to)| {
+                let out_point =
+                    OutPoint::new(coinbase_hash.clone(), index as u32);
+                let mut transfer = Transaction::new(
+                    vec![Input::new(out_point.tx_hash, out_point.index, 100)],
+                    vec![Output::new(to.to_string(), 100)],
+                );
+                alice.sign_transaction(&mut transfer);
+                transfer
+            })
+            .collect();
+        let block = gen_mined_block(&chain, transfers);
+        chain.update_with_block(block).unwrap();
+
+        while chain.len() < 10 {
+            let block = gen_mined_block(&chain, vec![]);
+            chain.update_with_block(block).unwrap();
+        }
+        chain
+    }
+
+    fn gen_header_chain(chain: &Blockchain) -> HeaderChain {
+        let mut headers = HeaderChain::new(chain.params.clone());
+        headers
+            .add_headers(chain.blocks.iter().map(Block::header))
+            .unwrap();
+        headers
+    }
+
+    #[test]
+    fn test_header_hashes_like_its_block() {
+        let chain = gen_chain();
+        for block in &chain.blocks {
+            let header = block.header();
+            assert_eq!(header.hash(), block.hash);
+            assert_eq!(BlockHeader::decode(&header.encode()), Ok(header));
+        }
+
+        let block = &chain.blocks[1];
+        for algorithm in [HashAlgorithm::DoubleSha256, HashAlgorithm::Keccak256]
+        {
+            let block = block.clone().with_hash_algorithm(algorithm);
+            assert_eq!(block.header().hash(), block.hash());
+        }
+        let mut header = block.header();
+        header.state_root = vec![1; 32];
+        assert_ne!(header.hash(), block.hash);
+    }
+
+    #[test]
+    fn test_header_chain_follows_difficulty() {
+        let chain = gen_chain();
+        let headers = gen_header_chain(&chain);
+        assert_eq!(headers.len(), 10);
+        assert_eq!(headers.tip(), Some(&chain.last_block().unwrap().header()));
+        assert_eq!(headers.next_difficulty(), chain.next_difficulty());
+
+        // the fast blocks made two retargets harder
+        let difficulties: Vec<u128> = headers
+            .headers()
+            .iter()
+            .map(|header| header.difficulty)
+            .collect();
+        assert_eq!(difficulties[3], u128::MAX >> 4);
+        assert!(difficulties[4] < difficulties[3]);
+        assert!(difficulties[8] < difficulties[4]);
+    }
+
+    #[test]
+    fn test_reject_invalid_headers() {
+        let chain = gen_chain();
+        let mut headers = HeaderChain::new(chain.params.clone());
+        let blocks = &chain.blocks;
+
+        let mut genesis = blocks[0].header();
+        genesis.prev_block_hash = vec![1; 32];
+        assert_eq!(
+            headers.add_header(genesis),
+            Err(BlockValidationErr::InvalidGenesisBlockFormat)
+        );
+        assert_eq!(
+            headers.add_header(blocks[1].header()),
+            Err(BlockValidationErr::MismatchedIndex)
+        );
+        headers.add_header(blocks[0].header()).unwrap();
+
+        // block 2 does not extend the genesis block
+        assert_eq!(
+            headers.add_header(blocks[2].header()),
+            Err(BlockValidationErr::MismatchedPreviousHash)
+        );
+
+        // swapping the transactions of a block changes its merkle root, and with it the hash
+        let mut swapped = blocks[1].header();
+        swapped.merkle_root = blocks[2].merkle_root.clone();
+        assert_eq!(
+            headers.add_header(swapped),
+            Err(BlockValidationErr::InvalidHash)
+        );
+
+        // an easier difficulty, re-mined so that the hash is fine
+        let mut easier = blocks[1].clone();
+        easier.difficulty = u128::MAX;
+        assert!(easier.mine());
+        assert_eq!(
+            headers.add_header(easier.header()),
+            Err(BlockValidationErr::MismatchedDifficulty)
+        );
+
+        let mut earlier = blocks[1].clone();
+        earlier.timestamp = blocks[0].timestamp;
+        assert!(earlier.mine());
+        assert_eq!(
+            headers.add_header(earlier.header()),
+            Err(BlockValidationErr::AchronologicalTimestamp)
+        );
+
+        // a correct hash that does not meet the (impossible) difficulty it claims
+        let mut unmined = blocks[1].header();
+        unmined.difficulty = 1;
+        unmined.hash = unmined.hash();
+        assert_eq!(
+            headers.add_header(unmined),
+            Err(BlockValidationErr::DifficultyNotMet)
+        );
+
+        let mut headers = HeaderChain::new(ChainParams {
+            hash_algorithm: HashAlgorithm::Keccak256,
+            ..chain.params.clone()
+        });
+        assert_eq!(
+            headers.add_header(blocks[0].header()),
+            Err(BlockValidationErr::MismatchedHashAlgorithm)
+        );
+    }
+
+    #[test]
+    fn test_verify_transaction() {
+        let chain = gen_chain();
+        let headers = gen_header_chain(&chain);
+        let block = &chain.blocks[1];
+
+        for transaction in &block.transactions {
+            let tx_hash = transaction.hash();
+            let proof = chain.merkle_proof(&block.hash, &tx_hash).unwrap();
+            // the block and the 8 headers on top of it
+            assert_eq!(
+                headers.verify_transaction(&tx_hash, &block.hash, &proof),
+                Some(9)
+            );
+        }
+
+        let tx_hash = block.transactions[1].hash();
+        let proof = chain.merkle_proof(&block.hash, &tx_hash).unwrap();
+        let other_tx = block.transactions[2].hash();
+        assert_eq!(
+            headers.verify_transaction(&other_tx, &block.hash, &proof),
+            None
+        );
+        let tip = &chain.last_block().unwrap().hash;
+        assert_eq!(headers.verify_transaction(&tx_hash, tip, &proof), None);
+        assert_eq!(
+            headers.verify_transaction(&tx_hash, &vec![0; 32], &proof),
+            None
+        );
+        assert_eq!(headers.confirmations(tip), Some(1));
+
+        // a full node only proves transactions of the block they are in
+        assert_eq!(chain.merkle_proof(tip, &tx_hash), None);
+    }
+
+    #[test]
+    fn test_light_client_syncs_from_node() {
+        let params = gen_params();
+        let node = Node::bind(NodeConfig {
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+            peers: vec![],
+            params: params.clone(),
+            miner: None,
+            mining_threads: 1,
+        })
+        .unwrap();
+        let handle = node.handle();
+        thread::spawn(move || node.run());
+
+        // the node starts from the genesis block of the parameters, so does the local copy
+        let mut chain = Blockchain::with_params(params.clone());
+        chain.update_with_block(params.genesis_block()).unwrap();
+        for _ in 0..5 {
+            let block = gen_mined_block(&chain, vec![]);
+            chain.update_with_block(block.clone()).unwrap();
+            handle.submit_block(block);
+        }
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while handle.status().unwrap().block_count < 6 {
+            assert!(Instant::now() < deadline, "node did not take the blocks");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let mut headers = HeaderChain::new(params);
+        let mut client = LightClient::connect(handle.local_addr()).unwrap();
+        assert_eq!(client.sync(&mut headers).unwrap(), 6);
+        assert_eq!(headers.tip(), Some(&chain.last_block().unwrap().header()));
+        // nothing new the second time
+        assert_eq!(client.sync(&mut headers).unwrap(), 0);
+
+        let block = &chain.blocks[3];
+        let tx_hash = block.transactions[0].hash();
+        let proof =
+            client.merkle_proof(&block.hash, &tx_hash).unwrap().unwrap();
+        assert_eq!(
+            headers.verify_transaction(&tx_hash, &block.hash, &proof),
+            Some(3)
+        );
+        assert_eq!(
+            client.merkle_proof(&block.hash, &vec![0; 32]).unwrap(),
+            None
+        );
+
+        handle.shutdown();
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use blockchain::{
+        block::Block,
+        blockchain::ChainParams,
+        hashtable::Hashtable,
+        storage::{
+            BlockStore, PersistentBlockchain, StorageError, BLOCK_FILE,
+            INDEX_FILE,
+        },
+        transactions::{Output, Transaction},
+    };
+    use tempfile::tempdir;
+
+    const DIFFICULTY: u128 = u128::MAX >> 2;
+
+    fn gen_params() -> ChainParams {
+        ChainParams {
+            initial_difficulty: DIFFICULTY,
+            ..ChainParams::default()
+        }
+    }
+
+    // build the block on top of the given tip, or the genesis block when there is none
+    fn gen_next_block(prev: Option<&Block>) -> Block {
+        let (index, timestamp, prev_block_hash) = match prev {
+            Some(prev) => {
+                (prev.index + 1, prev.timestamp + 1, prev.hash.clone())
+            }
+            None => (0, 1, vec![0; 32]),
+        };
+        let coinbase = Transaction::coinbase(
+            index,
+            vec![Output::new("miner_address".to_owned(), 50)],
+        );
+        let mut block = Block::new(
+            index,
+            timestamp,
+            prev_block_hash,
+            vec![coinbase],
+            0,
+            DIFFICULTY,
+        );
+        block.mine();
+        block
+    }
+
+    fn append_blocks(chain: &mut PersistentBlockchain, cnt: u32) {
+        for _ in 0..cnt {
+            let block = gen_next_block(chain.chain().last_block());
+            chain.update_with_block(block).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reopen_rebuilds_chain() {
+        let dir = tempdir().unwrap();
+        let tip_hash = {
+            let mut chain =
+                PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+            append_blocks(&mut chain, 5);
+            chain.chain().last_block().unwrap().hash.clone()
+        };
+
+        let chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        assert_eq!(chain.chain().len(), 5);
+        assert_eq!(chain.chain().last_block().unwrap().hash, tip_hash);
+        assert_eq!(chain.chain().unspent_outputs().len(), 5);
+        assert_eq!(chain.store().truncated_bytes(), 0);
+
+        // blocks can be looked up by height and by hash
+        let store = chain.store();
+        assert_eq!(store.height_of(&tip_hash), Some(4));
+        let block = store.read_block_by_hash(&tip_hash).unwrap().unwrap();
+        assert_eq!(block.hash(), tip_hash);
+        assert_eq!(store.read_block(2).unwrap().unwrap().index, 2);
+        assert!(store.read_block(5).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_truncate_partially_written_block() {
+        let dir = tempdir().unwrap();
+        let mut chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        append_blocks(&mut chain, 3);
+        drop(chain);
+
+        let block_path = dir.path().join(BLOCK_FILE);
+        let complete_len = fs::metadata(&block_path).unwrap().len();
+
+        // mimic a crash in the middle of appending the 4th block:
+        // the record header made it to disk, but only part of the payload did
+        let mut record = vec![200, 0, 0, 0, 1, 2, 3, 4];
+        record.extend(vec![0xab; 50]);
+        OpenOptions::new()
+            .append(true)
+            .open(&block_path)
+            .unwrap()
+            .write_all(&record)
+            .unwrap();
+
+        let mut chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        assert_eq!(chain.chain().len(), 3);
+        assert_eq!(chain.store().truncated_bytes(), record.len() as u64);
+        assert_eq!(fs::metadata(&block_path).unwrap().len(), complete_len);
+
+        // the chain keeps growing right where the last complete block ended
+        append_blocks(&mut chain, 2);
+        drop(chain);
+        let chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        assert_eq!(chain.chain().len(), 5);
+    }
+
+    #[test]
+    fn test_rebuild_missing_index() {
+        let dir = tempdir().unwrap();
+        let mut chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        append_blocks(&mut chain, 4);
+        drop(chain);
+
+        let index_path = dir.path().join(INDEX_FILE);
+        let index = fs::read(&index_path).unwrap();
+
+        // losing the index, or its last entry, only costs a rebuild
+        fs::remove_file(&index_path).unwrap();
+        let store = BlockStore::open(dir.path()).unwrap();
+        assert_eq!(store.len(), 4);
+        assert_eq!(fs::read(&index_path).unwrap(), index);
+        drop(store);
+
+        fs::write(&index_path, &index[..index.len() - 10]).unwrap();
+        let store = BlockStore::open(dir.path()).unwrap();
+        assert_eq!(store.len(), 4);
+        assert_eq!(fs::read(&index_path).unwrap(), index);
+    }
+
+    #[test]
+    fn test_reject_corrupted_block_file() {
+        let dir = tempdir().unwrap();
+        let mut chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        append_blocks(&mut chain, 3);
+        drop(chain);
+
+        // flip one byte inside the first record, which is followed by more records
+        let block_path = dir.path().join(BLOCK_FILE);
+        let mut data = fs::read(&block_path).unwrap();
+        data[20] ^= 0xff;
+        fs::write(&block_path, &data).unwrap();
+
+        let result = PersistentBlockchain::open(dir.path(), gen_params());
+        assert!(matches!(result, Err(StorageError::Corrupted { offset: 0 })));
+    }
+
+    #[test]
+    fn test_reject_corrupted_record_length() {
+        let dir = tempdir().unwrap();
+        let mut chain =
+            PersistentBlockchain::open(dir.path(), gen_params()).unwrap();
+        append_blocks(&mut chain, 3);
+        drop(chain);
+
+        let block_path = dir.path().join(BLOCK_FILE);
+        let data = fs::read(&block_path).unwrap();
+        let first_len =
+            u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let second = 8 + first_len as usize;
+
+        // the length of the middle record points past the end of the file,
+        // it must not pass for a torn write and cut off the record after it
+        let mut past_end = data.clone();
+        past_end[second + 3] ^= 0xff;
+        fs::write(&block_path, &past_end).unwrap();
+        let result = BlockStore::open(dir.path());
+        assert!(matches!(
+            result,
+            Err(StorageError::Corrupted { offset }) if offset == second as u64
+        ));
+        assert_eq!(fs::metadata(&block_path).unwrap().len(), data.len() as u64);
+
+        // a length still inside the file is caught by the checksum
+        let mut inside = data.clone();
+        inside[second] ^= 0x01;
+        fs::write(&block_path, &inside).unwrap();
+        let result = BlockStore::open(dir.path());
+        assert!(matches!(
+            result,
+            Err(StorageError::Corrupted { offset }) if offset == second as u64
+        ));
+    }
+
+    #[test]
+    fn test_revalidate_blocks_on_open() {
+        let dir = tempdir().unwrap();
+        let mut store = BlockStore::open(dir.path()).unwrap();
+        let genesis = gen_next_block(None);
+        let mut block = gen_next_block(Some(&genesis));
+        store.append(&genesis).unwrap();
+
+        // a well-formed record holding a block that breaks the chain rules
+        block.prev_block_hash = vec![1; 32];
+        store.append(&block).unwrap();
+        drop(store);
+
+        let result = PersistentBlockchain::open(dir.path(), gen_params());
+        assert!(matches!(
+            result,
+            Err(StorageError::InvalidBlock { height: 1, .. })
+        ));
+    }
+}
@@ -2,7 +2,8 @@
 mod tests {
     use blockchain::{
         hashtable::Hashtable,
-        transactions::{Output, Transaction},
+        transactions::{Input, Output, Transaction},
+        wallet::Wallet,
     };
     use rand::Rng;
     use uuid::Uuid;
@@ -16,9 +17,18 @@ mod tests {
         Output::new(random_to_addr.to_owned(), random_value as u64)
     }
 
+    fn generate_random_input() -> Input {
+        let mut rng = rand::thread_rng();
+        let prev_tx_hash: Vec<u8> = (0..32).map(|_| rng.gen()).collect();
+        let prev_output_index: u8 = rng.gen();
+        let random_value: u16 = rng.gen();
+
+        Input::new(prev_tx_hash, prev_output_index as u32, random_value as u64)
+    }
+
     #[test]
     fn test_transaction_create() {
-        let inputs: Vec<Output> = vec![];
+        let inputs: Vec<Input> = vec![];
         let outputs: Vec<Output> = vec![];
         let trans: Transaction = Transaction::new(inputs, outputs);
 
@@ -43,11 +53,11 @@ mod tests {
     #[test]
     fn test_transaction_create_with_inputs() {
         // here let create a vector of input with len = 10
-        let mut inputs: Vec<Output> = vec![];
+        let mut inputs: Vec<Input> = vec![];
         let outputs: Vec<Output> = vec![];
 
         for i in 0..10 {
-            let input = generate_random_output();
+            let input = generate_random_input();
             inputs.push(input);
         }
 
@@ -95,12 +105,12 @@ mod tests {
 
     #[test]
     fn test_transaction_create_with_inputs_outputs() {
-        let mut inputs: Vec<Output> = vec![];
+        let mut inputs: Vec<Input> = vec![];
         let mut outputs: Vec<Output> = vec![];
 
         // generate 10 input & output items and push those to inputs vector and outputs vector
         for i in 0..10 {
-            let input = generate_random_output();
+            let input = generate_random_input();
             let output = generate_random_output();
 
             inputs.push(input);
@@ -163,4 +173,56 @@ mod tests {
         assert!(input_hash_set.len() >= trans.inputs.len());
         assert!(output_hash_set.len() >= trans.outputs.len());
     }
+
+    #[test]
+    fn test_coinbase_transaction() {
+        let coinbase = Transaction::coinbase(
+            7,
+            vec![Output::new("miner_address".to_owned(), 50)],
+        );
+        assert!(coinbase.is_coinbase());
+        assert!(coinbase.is_validate());
+        assert_eq!(coinbase.coinbase_height(), Some(7));
+        assert_eq!(coinbase.fee(), Some(0));
+
+        // the same reward at another height should never share the hash
+        let next = Transaction::coinbase(
+            8,
+            vec![Output::new("miner_address".to_owned(), 50)],
+        );
+        assert_ne!(coinbase.hash(), next.hash());
+
+        // a regular transaction is not a coinbase even with a single input
+        let trans = Transaction::new(vec![generate_random_input()], vec![]);
+        assert!(!trans.is_coinbase());
+        assert_eq!(trans.coinbase_height(), None);
+    }
+
+    #[test]
+    fn test_transaction_signature() {
+        let wallet = Wallet::new();
+        let mut trans = Transaction::new(
+            vec![generate_random_input(), generate_random_input()],
+            vec![Output::new("bob".to_owned(), 1)],
+        );
+
+        // unsigned inputs are not valid
+        assert!(!trans.is_validate());
+
+        wallet.sign_transaction(&mut trans);
+        assert!(trans.is_validate());
+        assert!(trans
+            .inputs
+            .iter()
+            .all(|input| input.public_key == wallet.public_key()));
+
+        // the signing bytes leave signatures out, so signing does not change them
+        let signing_bytes = trans.signing_bytes();
+        wallet.sign_transaction(&mut trans);
+        assert_eq!(signing_bytes, trans.signing_bytes());
+
+        // redirecting the coins breaks every signature
+        trans.outputs[0].to_addr = "mallory".to_owned();
+        assert!(!trans.is_validate());
+    }
 }